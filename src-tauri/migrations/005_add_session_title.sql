@@ -0,0 +1,3 @@
+-- Process-set window title (OSC 0/2) and working directory (OSC 7)
+ALTER TABLE sessions ADD COLUMN title TEXT;
+ALTER TABLE sessions ADD COLUMN cwd TEXT;
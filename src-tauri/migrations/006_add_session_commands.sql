@@ -0,0 +1,18 @@
+-- Commands delimited by shell-integration prompt marks (OSC 133)
+CREATE TABLE IF NOT EXISTS session_commands (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    session_id TEXT NOT NULL,
+    command TEXT NOT NULL DEFAULT '',
+    exit_code INTEGER,
+    output_start INTEGER NOT NULL,
+    output_end INTEGER NOT NULL,
+    started_at TEXT NOT NULL,
+    ended_at TEXT NOT NULL,
+    duration_ms INTEGER NOT NULL DEFAULT 0,
+    FOREIGN KEY (session_id) REFERENCES sessions(id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_session_commands_session_id ON session_commands(session_id);
+
+-- Byte offset of each log chunk within its session's output stream
+ALTER TABLE session_logs ADD COLUMN stream_offset INTEGER;
@@ -0,0 +1,15 @@
+-- User-defined tools, selectable wherever a built-in tool is
+CREATE TABLE IF NOT EXISTS custom_tools (
+    id TEXT PRIMARY KEY,
+    name TEXT NOT NULL,
+    command TEXT NOT NULL,
+    args TEXT NOT NULL DEFAULT '[]',
+    env_vars TEXT NOT NULL DEFAULT '{}',
+    icon_label TEXT NOT NULL DEFAULT '',
+    idle_regex TEXT,
+    usage_regex TEXT,
+    created_at TEXT NOT NULL DEFAULT (datetime('now')),
+    updated_at TEXT NOT NULL DEFAULT (datetime('now'))
+);
+
+CREATE INDEX IF NOT EXISTS idx_custom_tools_name ON custom_tools(name);
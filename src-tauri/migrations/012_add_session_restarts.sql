@@ -0,0 +1,11 @@
+-- Automatic restarts of sessions whose process exited
+CREATE TABLE IF NOT EXISTS session_restarts (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    session_id TEXT NOT NULL,
+    attempt INTEGER NOT NULL,
+    exit_code INTEGER,
+    restarted_at TEXT NOT NULL DEFAULT (datetime('now')),
+    FOREIGN KEY (session_id) REFERENCES sessions(id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_session_restarts_session_id ON session_restarts(session_id);
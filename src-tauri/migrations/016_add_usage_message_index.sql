@@ -0,0 +1,3 @@
+-- Usage imported from tool transcripts is keyed by the message it belongs to, so reading
+-- the same transcript twice does not count it twice
+CREATE UNIQUE INDEX IF NOT EXISTS idx_token_usage_message_id ON token_usage(message_id) WHERE message_id IS NOT NULL;
@@ -0,0 +1,16 @@
+-- Conversations imported from the transcripts tools keep on disk
+CREATE TABLE IF NOT EXISTS transcript_messages (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    session_id TEXT NOT NULL,
+    -- Where the entry came from, so reading a transcript again does not duplicate it
+    entry_id TEXT NOT NULL UNIQUE,
+    role TEXT NOT NULL,
+    kind TEXT NOT NULL,
+    name TEXT,
+    content TEXT NOT NULL DEFAULT '',
+    timestamp TEXT,
+    recorded_at TEXT NOT NULL DEFAULT (datetime('now')),
+    FOREIGN KEY (session_id) REFERENCES sessions(id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_transcript_messages_session_id ON transcript_messages(session_id);
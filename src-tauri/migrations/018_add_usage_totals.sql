@@ -0,0 +1,14 @@
+-- The last running total each session's tool reported per model. Readings of a running
+-- total only add what they grew by to token_usage.
+CREATE TABLE IF NOT EXISTS usage_totals (
+    session_id TEXT NOT NULL,
+    model TEXT NOT NULL,
+    input_tokens INTEGER NOT NULL DEFAULT 0,
+    output_tokens INTEGER NOT NULL DEFAULT 0,
+    cache_read_tokens INTEGER NOT NULL DEFAULT 0,
+    cache_creation_tokens INTEGER NOT NULL DEFAULT 0,
+    cost_usd REAL NOT NULL DEFAULT 0.0,
+    updated_at TEXT NOT NULL DEFAULT (datetime('now')),
+    PRIMARY KEY (session_id, model),
+    FOREIGN KEY (session_id) REFERENCES sessions(id) ON DELETE CASCADE
+);
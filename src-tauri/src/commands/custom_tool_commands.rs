@@ -0,0 +1,32 @@
+use tauri::State;
+
+use crate::db::custom_tool_repo;
+use crate::error::AppResult;
+use crate::models::custom_tool::{CreateCustomTool, CustomTool, UpdateCustomTool};
+use crate::state::AppState;
+
+#[tauri::command]
+pub fn create_custom_tool(state: State<'_, AppState>, input: CreateCustomTool) -> AppResult<CustomTool> {
+    let id = uuid::Uuid::new_v4().to_string();
+    state.db.with_conn(|conn| custom_tool_repo::create_custom_tool(conn, &id, &input))
+}
+
+#[tauri::command]
+pub fn list_custom_tools(state: State<'_, AppState>) -> AppResult<Vec<CustomTool>> {
+    state.db.with_read_conn(custom_tool_repo::list_custom_tools)
+}
+
+#[tauri::command]
+pub fn get_custom_tool(state: State<'_, AppState>, id: String) -> AppResult<CustomTool> {
+    state.db.with_read_conn(|conn| custom_tool_repo::get_custom_tool(conn, &id))
+}
+
+#[tauri::command]
+pub fn update_custom_tool(state: State<'_, AppState>, id: String, input: UpdateCustomTool) -> AppResult<CustomTool> {
+    state.db.with_conn(|conn| custom_tool_repo::update_custom_tool(conn, &id, &input))
+}
+
+#[tauri::command]
+pub fn delete_custom_tool(state: State<'_, AppState>, id: String) -> AppResult<()> {
+    state.db.with_conn(|conn| custom_tool_repo::delete_custom_tool(conn, &id))
+}
@@ -1,20 +1,63 @@
 use tauri::State;
 
 use crate::db::history_repo;
-use crate::models::history::{SearchQuery, SearchResult};
+use crate::error::AppResult;
+use crate::models::history::{SearchQuery, SearchResult, SessionCommand, TranscriptMessage};
 use crate::state::AppState;
 
 #[tauri::command]
-pub fn search_history(state: State<'_, AppState>, query: SearchQuery) -> Result<SearchResult, String> {
-    state.db.with_conn(|conn| history_repo::search_logs(conn, &query))
+pub async fn search_history(state: State<'_, AppState>, query: SearchQuery) -> AppResult<SearchResult> {
+    state
+        .db
+        .clone()
+        .read(move |conn| history_repo::search_logs(conn, &query))
+        .await
 }
 
 #[tauri::command]
-pub fn get_session_log(state: State<'_, AppState>, session_id: String) -> Result<String, String> {
-    state.db.with_conn(|conn| history_repo::get_session_log(conn, &session_id))
+pub async fn get_session_log(state: State<'_, AppState>, session_id: String) -> AppResult<String> {
+    state
+        .db
+        .clone()
+        .read(move |conn| history_repo::get_session_log(conn, &session_id))
+        .await
 }
 
 #[tauri::command]
-pub fn insert_session_log(state: State<'_, AppState>, session_id: String, content: String) -> Result<(), String> {
-    state.db.with_conn(|conn| history_repo::insert_log(conn, &session_id, content.as_bytes()))
+pub fn insert_session_log(state: State<'_, AppState>, session_id: String, content: String) -> AppResult<()> {
+    state.db.with_conn(|conn| history_repo::insert_log(conn, &session_id, content.as_bytes(), None))
+}
+
+#[tauri::command]
+pub async fn list_session_commands(
+    state: State<'_, AppState>,
+    session_id: String,
+) -> AppResult<Vec<SessionCommand>> {
+    state
+        .db
+        .clone()
+        .read(move |conn| history_repo::list_commands(conn, &session_id))
+        .await
+}
+
+#[tauri::command]
+pub async fn get_command_output(state: State<'_, AppState>, command_id: i64) -> AppResult<String> {
+    state
+        .db
+        .clone()
+        .read(move |conn| history_repo::get_command_output(conn, command_id))
+        .await
+}
+
+/// The conversation imported from the session's tool transcripts, oldest first.
+#[tauri::command]
+pub async fn get_session_transcript(
+    state: State<'_, AppState>,
+    session_id: String,
+) -> AppResult<Vec<TranscriptMessage>> {
+    state
+        .db
+        .clone()
+        .read(move |conn| history_repo::list_transcript_messages(conn, &session_id))
+        .await
 }
@@ -1,3 +1,4 @@
+pub mod custom_tool_commands;
 pub mod history_commands;
 pub mod monitoring_commands;
 pub mod platform_commands;
@@ -5,6 +6,7 @@ pub mod pty_commands;
 pub mod session_commands;
 pub mod template_commands;
 
+pub use custom_tool_commands::*;
 pub use history_commands::*;
 pub use monitoring_commands::*;
 pub use platform_commands::*;
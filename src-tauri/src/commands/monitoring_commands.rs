@@ -1,25 +1,62 @@
 use tauri::State;
 
+use crate::db::migrations::MigrationReport;
 use crate::db::monitoring_repo;
-use crate::models::monitoring::{GlobalCostSummary, RecordTokenUsage, SessionCostSummary, TokenUsageRecord};
+use crate::error::AppResult;
+use crate::models::monitoring::{
+    GlobalCostSummary, PersistenceMetrics, RecordTokenUsage, SessionCostSummary, TokenUsageRecord, UsageRulesStatus,
+};
 use crate::state::AppState;
 
 #[tauri::command]
-pub fn record_token_usage(state: State<'_, AppState>, input: RecordTokenUsage) -> Result<(), String> {
+pub fn record_token_usage(state: State<'_, AppState>, input: RecordTokenUsage) -> AppResult<()> {
     state.db.with_conn(|conn| monitoring_repo::record_usage(conn, &input))
 }
 
 #[tauri::command]
-pub fn get_session_usage(state: State<'_, AppState>, session_id: String) -> Result<Vec<TokenUsageRecord>, String> {
-    state.db.with_conn(|conn| monitoring_repo::get_session_usage(conn, &session_id))
+pub async fn get_session_usage(
+    state: State<'_, AppState>,
+    session_id: String,
+) -> AppResult<Vec<TokenUsageRecord>> {
+    state
+        .db
+        .clone()
+        .read(move |conn| monitoring_repo::get_session_usage(conn, &session_id))
+        .await
 }
 
 #[tauri::command]
-pub fn get_session_cost_summary(state: State<'_, AppState>, session_id: String) -> Result<SessionCostSummary, String> {
-    state.db.with_conn(|conn| monitoring_repo::get_session_cost_summary(conn, &session_id))
+pub async fn get_session_cost_summary(
+    state: State<'_, AppState>,
+    session_id: String,
+) -> AppResult<SessionCostSummary> {
+    state
+        .db
+        .clone()
+        .read(move |conn| monitoring_repo::get_session_cost_summary(conn, &session_id))
+        .await
 }
 
 #[tauri::command]
-pub fn get_global_cost_summary(state: State<'_, AppState>) -> Result<GlobalCostSummary, String> {
-    state.db.with_conn(|conn| monitoring_repo::get_global_cost_summary(conn))
+pub async fn get_global_cost_summary(state: State<'_, AppState>) -> AppResult<GlobalCostSummary> {
+    state.db.clone().read(monitoring_repo::get_global_cost_summary).await
+}
+
+/// The usage rules in effect, after picking up any change to the user's rules file.
+#[tauri::command]
+pub fn get_usage_rules(state: State<'_, AppState>) -> UsageRulesStatus {
+    let rules = state.pty_manager.usage_rules();
+    rules.reload(false);
+    rules.status()
+}
+
+#[tauri::command]
+pub fn get_persistence_metrics(state: State<'_, AppState>) -> PersistenceMetrics {
+    state.persistence.metrics()
+}
+
+/// What the migrations run at startup applied, and any schema drift they found and repaired.
+#[tauri::command]
+pub fn get_migration_report(report: State<'_, MigrationReport>) -> MigrationReport {
+    report.inner().clone()
 }
@@ -1,4 +1,10 @@
 use serde::Serialize;
+use tauri::State;
+
+use crate::error::{AppError, AppResult};
+use crate::models::session::{DiscoveredTool, LoginEnvironment};
+use crate::tools::discovery;
+use crate::state::AppState;
 
 #[derive(Serialize)]
 #[serde(rename_all = "camelCase")]
@@ -41,6 +47,35 @@ pub fn get_platform_defaults() -> PlatformDefaults {
     }
 }
 
+/// The captured login environment, waiting for a capture in progress.
+#[tauri::command]
+pub async fn get_login_environment(state: State<'_, AppState>) -> AppResult<LoginEnvironment> {
+    let environment = state.pty_manager.environment().clone();
+    tauri::async_runtime::spawn_blocking(move || environment.info())
+        .await
+        .map_err(|e| AppError::Internal(format!("Task error: {e}")))
+}
+
+/// Re-captures the login-shell environment, e.g. after the user edits their profile.
+/// Applies to sessions started afterwards.
+#[tauri::command]
+pub async fn refresh_login_environment(state: State<'_, AppState>) -> AppResult<LoginEnvironment> {
+    let environment = state.pty_manager.environment().clone();
+    tauri::async_runtime::spawn_blocking(move || environment.refresh())
+        .await
+        .map_err(|e| AppError::Internal(format!("Task error: {e}")))?
+}
+
+/// Finds the known CLI tools on the session PATH and in common install locations.
+#[tauri::command]
+pub async fn discover_tools(state: State<'_, AppState>) -> AppResult<Vec<DiscoveredTool>> {
+    let environment = state.pty_manager.environment().clone();
+    let versions = state.pty_manager.tool_versions().clone();
+    tauri::async_runtime::spawn_blocking(move || discovery::discover_tools(&environment.vars(), &versions))
+        .await
+        .map_err(|e| AppError::Internal(format!("Task error: {e}")))
+}
+
 fn dirs_next() -> Option<String> {
     #[cfg(target_os = "windows")]
     {
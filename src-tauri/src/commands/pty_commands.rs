@@ -1,20 +1,95 @@
+use std::time::Duration;
 use tauri::ipc::Channel;
 use tauri::State;
 
-use crate::models::session::{PtyOutputEvent, SessionConfig};
+use crate::db::{custom_tool_repo, DbPool};
+use crate::error::{AppError, AppResult};
+use crate::models::screen::ScreenSnapshot;
+use crate::models::session::{ConfigValidation, PtyOutputEvent, SessionConfig, TerminalEnvironment};
+use crate::pty::manager::DEFAULT_STOP_GRACE_PERIOD;
+use crate::pty::preflight;
+use crate::pty::subscribers::SubscriberId;
 use crate::state::AppState;
 
+/// Runs pre-flight checks against the login environment, waiting for a capture in progress.
 #[tauri::command]
-pub fn create_session(
+pub async fn validate_session_config(
     state: State<'_, AppState>,
     config: SessionConfig,
+) -> AppResult<ConfigValidation> {
+    let db = state.db.clone();
+    let environment = state.pty_manager.environment().clone();
+    tauri::async_runtime::spawn_blocking(move || {
+        let config = with_custom_tool(&db, config)?;
+        Ok(preflight::validate_config(&config, &environment.vars()))
+    })
+    .await
+    .map_err(|e| AppError::Internal(format!("Task error: {e}")))?
+}
+
+/// Spawns on the blocking thread pool, since the session waits for a login environment
+/// capture in progress.
+#[tauri::command]
+pub async fn create_session(
+    state: State<'_, AppState>,
+    config: SessionConfig,
+    on_event: Channel<PtyOutputEvent>,
+) -> AppResult<String> {
+    let db = state.db.clone();
+    let manager = state.pty_manager.clone();
+    let persistence = state.persistence.clone();
+    tauri::async_runtime::spawn_blocking(move || {
+        let config = with_custom_tool(&db, config)?;
+        manager.create_session(&config, on_event, persistence)
+    })
+    .await
+    .map_err(|e| AppError::Internal(format!("Task error: {e}")))?
+}
+
+/// Loads the definition the config refers to. A missing one is left for pre-flight to report.
+fn with_custom_tool(db: &DbPool, mut config: SessionConfig) -> AppResult<SessionConfig> {
+    if let Some(id) = &config.custom_tool_id {
+        config.custom_tool = match db.with_read_conn(|conn| custom_tool_repo::get_custom_tool(conn, id)) {
+            Ok(tool) => Some(tool),
+            Err(AppError::NotFound { .. }) => None,
+            Err(e) => return Err(e),
+        };
+    }
+    Ok(config)
+}
+
+#[tauri::command]
+pub fn attach_session(
+    state: State<'_, AppState>,
+    session_id: String,
+    on_event: Channel<PtyOutputEvent>,
+) -> AppResult<SubscriberId> {
+    state.pty_manager.attach_session(&session_id, on_event)
+}
+
+#[tauri::command]
+pub fn subscribe_session(
+    state: State<'_, AppState>,
+    session_id: String,
     on_event: Channel<PtyOutputEvent>,
-) -> Result<String, String> {
-    state.pty_manager.create_session(&config, on_event, state.db.clone())
+    replay: Option<bool>,
+) -> AppResult<SubscriberId> {
+    state
+        .pty_manager
+        .subscribe_session(&session_id, on_event, replay.unwrap_or(true))
 }
 
 #[tauri::command]
-pub fn write_to_pty(state: State<'_, AppState>, session_id: String, data: String) -> Result<(), String> {
+pub fn unsubscribe_session(
+    state: State<'_, AppState>,
+    session_id: String,
+    subscriber_id: SubscriberId,
+) -> AppResult<()> {
+    state.pty_manager.unsubscribe_session(&session_id, subscriber_id)
+}
+
+#[tauri::command]
+pub fn write_to_pty(state: State<'_, AppState>, session_id: String, data: String) -> AppResult<()> {
     state.pty_manager.write_to_session(&session_id, data.as_bytes())
 }
 
@@ -24,16 +99,43 @@ pub fn resize_pty(
     session_id: String,
     cols: u16,
     rows: u16,
-) -> Result<(), String> {
+) -> AppResult<()> {
     state.pty_manager.resize_session(&session_id, cols, rows)
 }
 
 #[tauri::command]
-pub fn stop_session(state: State<'_, AppState>, session_id: String) -> Result<(), String> {
-    state.pty_manager.remove_session(&session_id)
+pub fn get_screen_snapshot(
+    state: State<'_, AppState>,
+    session_id: String,
+    include_cells: Option<bool>,
+) -> AppResult<ScreenSnapshot> {
+    state
+        .pty_manager
+        .screen_snapshot(&session_id, include_cells.unwrap_or(false))
+}
+
+/// The TERM, locale and identifying variables the session's PTY advertises.
+#[tauri::command]
+pub fn get_terminal_environment(
+    state: State<'_, AppState>,
+    session_id: String,
+) -> AppResult<TerminalEnvironment> {
+    state.pty_manager.terminal_environment(&session_id)
+}
+
+#[tauri::command]
+pub fn stop_session(
+    state: State<'_, AppState>,
+    session_id: String,
+    grace_period_ms: Option<u64>,
+) -> AppResult<()> {
+    let grace_period = grace_period_ms
+        .map(Duration::from_millis)
+        .unwrap_or(DEFAULT_STOP_GRACE_PERIOD);
+    state.pty_manager.stop_session(&session_id, grace_period)
 }
 
 #[tauri::command]
-pub fn list_sessions(state: State<'_, AppState>) -> Result<Vec<String>, String> {
+pub fn list_sessions(state: State<'_, AppState>) -> AppResult<Vec<String>> {
     state.pty_manager.list_session_ids()
 }
@@ -1,34 +1,40 @@
 use tauri::State;
 
-use crate::db::session_repo::{self, SavedSession};
+use crate::db::session_repo::{self, SavedSession, SessionRestart};
+use crate::error::AppResult;
 use crate::state::AppState;
 
 #[tauri::command]
-pub fn save_session_config(input: SavedSession, state: State<'_, AppState>) -> Result<(), String> {
+pub fn save_session_config(input: SavedSession, state: State<'_, AppState>) -> AppResult<()> {
     state.db.with_conn(|conn| session_repo::save_session(conn, &input))
 }
 
 #[tauri::command]
-pub fn list_saved_sessions(state: State<'_, AppState>) -> Result<Vec<SavedSession>, String> {
-    state.db.with_conn(|conn| session_repo::list_all_sessions(conn))
+pub fn list_saved_sessions(state: State<'_, AppState>) -> AppResult<Vec<SavedSession>> {
+    state.db.with_read_conn(session_repo::list_all_sessions)
 }
 
 #[tauri::command]
-pub fn list_restorable_sessions(state: State<'_, AppState>) -> Result<Vec<SavedSession>, String> {
-    state.db.with_conn(|conn| session_repo::list_restorable_sessions(conn))
+pub fn list_restorable_sessions(state: State<'_, AppState>) -> AppResult<Vec<SavedSession>> {
+    state.db.with_read_conn(session_repo::list_restorable_sessions)
 }
 
 #[tauri::command]
-pub fn update_saved_session_status(id: String, status: String, state: State<'_, AppState>) -> Result<(), String> {
+pub fn update_saved_session_status(id: String, status: String, state: State<'_, AppState>) -> AppResult<()> {
     state.db.with_conn(|conn| session_repo::update_session_status(conn, &id, &status))
 }
 
 #[tauri::command]
-pub fn delete_saved_session(id: String, state: State<'_, AppState>) -> Result<(), String> {
+pub fn delete_saved_session(id: String, state: State<'_, AppState>) -> AppResult<()> {
     state.db.with_conn(|conn| session_repo::delete_session(conn, &id))
 }
 
 #[tauri::command]
-pub fn mark_stale_sessions_stopped(state: State<'_, AppState>) -> Result<(), String> {
-    state.db.with_conn(|conn| session_repo::mark_all_stopped(conn))
+pub fn mark_stale_sessions_stopped(state: State<'_, AppState>) -> AppResult<()> {
+    state.db.with_conn(session_repo::mark_all_stopped)
+}
+
+#[tauri::command]
+pub fn list_session_restarts(session_id: String, state: State<'_, AppState>) -> AppResult<Vec<SessionRestart>> {
+    state.db.with_read_conn(|conn| session_repo::list_restarts(conn, &session_id))
 }
@@ -1,31 +1,32 @@
 use tauri::State;
 
 use crate::db::template_repo;
+use crate::error::AppResult;
 use crate::models::template::{CreateTemplate, PromptTemplate, UpdateTemplate};
 use crate::state::AppState;
 
 #[tauri::command]
-pub fn create_template(state: State<'_, AppState>, input: CreateTemplate) -> Result<PromptTemplate, String> {
+pub fn create_template(state: State<'_, AppState>, input: CreateTemplate) -> AppResult<PromptTemplate> {
     let id = uuid::Uuid::new_v4().to_string();
     state.db.with_conn(|conn| template_repo::create_template(conn, &id, &input))
 }
 
 #[tauri::command]
-pub fn list_templates(state: State<'_, AppState>) -> Result<Vec<PromptTemplate>, String> {
-    state.db.with_conn(|conn| template_repo::list_templates(conn))
+pub fn list_templates(state: State<'_, AppState>) -> AppResult<Vec<PromptTemplate>> {
+    state.db.with_read_conn(template_repo::list_templates)
 }
 
 #[tauri::command]
-pub fn get_template(state: State<'_, AppState>, id: String) -> Result<PromptTemplate, String> {
-    state.db.with_conn(|conn| template_repo::get_template(conn, &id))
+pub fn get_template(state: State<'_, AppState>, id: String) -> AppResult<PromptTemplate> {
+    state.db.with_read_conn(|conn| template_repo::get_template(conn, &id))
 }
 
 #[tauri::command]
-pub fn update_template(state: State<'_, AppState>, id: String, input: UpdateTemplate) -> Result<PromptTemplate, String> {
+pub fn update_template(state: State<'_, AppState>, id: String, input: UpdateTemplate) -> AppResult<PromptTemplate> {
     state.db.with_conn(|conn| template_repo::update_template(conn, &id, &input))
 }
 
 #[tauri::command]
-pub fn delete_template(state: State<'_, AppState>, id: String) -> Result<(), String> {
+pub fn delete_template(state: State<'_, AppState>, id: String) -> AppResult<()> {
     state.db.with_conn(|conn| template_repo::delete_template(conn, &id))
 }
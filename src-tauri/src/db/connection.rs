@@ -1,32 +1,162 @@
 use rusqlite::Connection;
 use std::path::PathBuf;
-use std::sync::Mutex;
+use std::sync::{Arc, Condvar, Mutex};
 
+use crate::error::{AppError, AppResult};
+
+/// Read connections opened alongside the writer.
+pub const DEFAULT_READ_CONNECTIONS: usize = 4;
+
+/// One writer connection plus a small pool of read-only connections. In WAL mode
+/// readers never wait on the writer, so searches and log fetches do not stall
+/// output persistence (and vice versa).
 pub struct DbPool {
-    conn: Mutex<Connection>,
+    writer: Mutex<Connection>,
+    readers: Mutex<Vec<Connection>>,
+    reader_returned: Condvar,
 }
 
 impl DbPool {
-    pub fn new(app_dir: &PathBuf) -> Result<Self, String> {
-        std::fs::create_dir_all(app_dir).map_err(|e| format!("Failed to create app dir: {e}"))?;
+    pub fn new(app_dir: &PathBuf) -> AppResult<Self> {
+        Self::with_read_connections(app_dir, DEFAULT_READ_CONNECTIONS)
+    }
+
+    pub fn with_read_connections(app_dir: &PathBuf, read_connections: usize) -> AppResult<Self> {
+        std::fs::create_dir_all(app_dir)
+            .map_err(|e| AppError::Io(format!("Failed to create app dir: {e}")))?;
         let db_path = app_dir.join("agentdesk.db");
         let conn = Connection::open(&db_path)
-            .map_err(|e| format!("Failed to open database: {e}"))?;
+            .map_err(|e| AppError::Database(format!("Failed to open database: {e}")))?;
 
         // Enable WAL mode for better concurrent performance
-        conn.execute_batch("PRAGMA journal_mode=WAL; PRAGMA foreign_keys=ON;")
-            .map_err(|e| format!("Failed to set PRAGMA: {e}"))?;
+        conn.execute_batch("PRAGMA journal_mode=WAL; PRAGMA foreign_keys=ON;")?;
+
+        let mut readers = Vec::with_capacity(read_connections);
+        for _ in 0..read_connections.max(1) {
+            let reader = Connection::open(&db_path)
+                .map_err(|e| AppError::Database(format!("Failed to open database: {e}")))?;
+            reader.execute_batch("PRAGMA foreign_keys=ON; PRAGMA query_only=ON;")?;
+            readers.push(reader);
+        }
 
         Ok(DbPool {
-            conn: Mutex::new(conn),
+            writer: Mutex::new(conn),
+            readers: Mutex::new(readers),
+            reader_returned: Condvar::new(),
         })
     }
 
-    pub fn with_conn<F, T>(&self, f: F) -> Result<T, String>
+    /// Runs `f` on the writer connection.
+    pub fn with_conn<F, T, E>(&self, f: F) -> AppResult<T>
+    where
+        F: FnOnce(&Connection) -> Result<T, E>,
+        E: Into<AppError>,
+    {
+        let conn = self.writer.lock()?;
+        f(&conn).map_err(Into::into)
+    }
+
+    /// Runs `f` on a read-only connection, waiting for one to free up if all are busy.
+    pub fn with_read_conn<F, T, E>(&self, f: F) -> AppResult<T>
+    where
+        F: FnOnce(&Connection) -> Result<T, E>,
+        E: Into<AppError>,
+    {
+        let conn = {
+            let readers = self.readers.lock()?;
+            let mut readers = self
+                .reader_returned
+                .wait_while(readers, |readers| readers.is_empty())?;
+            readers
+                .pop()
+                .ok_or_else(|| AppError::Internal("No read connection available".to_string()))?
+        };
+        let reader = ReadConnection { pool: self, conn: Some(conn) };
+        f(reader.conn.as_ref().expect("held until drop")).map_err(Into::into)
+    }
+
+    /// Runs `f` on a read connection from the blocking thread pool, so async commands
+    /// never tie up the IPC runtime while a query runs.
+    pub async fn read<F, T, E>(self: Arc<Self>, f: F) -> AppResult<T>
     where
-        F: FnOnce(&Connection) -> Result<T, rusqlite::Error>,
+        F: FnOnce(&Connection) -> Result<T, E> + Send + 'static,
+        T: Send + 'static,
+        E: Into<AppError>,
     {
-        let conn = self.conn.lock().map_err(|e| format!("Lock error: {e}"))?;
-        f(&conn).map_err(|e| format!("Database error: {e}"))
+        tauri::async_runtime::spawn_blocking(move || self.with_read_conn(f))
+            .await
+            .map_err(|e| AppError::Internal(format!("Task error: {e}")))?
+    }
+}
+
+/// A connection borrowed from the read pool, returned when dropped so a panicking
+/// query does not shrink the pool.
+struct ReadConnection<'a> {
+    pool: &'a DbPool,
+    conn: Option<Connection>,
+}
+
+impl Drop for ReadConnection<'_> {
+    fn drop(&mut self) {
+        if let (Some(conn), Ok(mut readers)) = (self.conn.take(), self.pool.readers.lock()) {
+            readers.push(conn);
+        }
+        self.pool.reader_returned.notify_one();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_pool(name: &str) -> DbPool {
+        let dir = std::env::temp_dir().join(format!("agentdesk-pool-{}-{name}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let pool = DbPool::with_read_connections(&dir, 2).unwrap();
+        pool.with_conn(|conn| conn.execute_batch("CREATE TABLE items (value TEXT NOT NULL);"))
+            .unwrap();
+        pool
+    }
+
+    #[test]
+    fn test_reads_do_not_wait_on_the_writer() {
+        let pool = test_pool("concurrent");
+        pool.with_conn(|conn| conn.execute("INSERT INTO items (value) VALUES ('committed')", []))
+            .unwrap();
+
+        // Hold the writer mid-transaction: readers still see the last committed state
+        pool.with_conn(|conn| {
+            conn.execute_batch("BEGIN; INSERT INTO items (value) VALUES ('pending');")?;
+            let count = pool
+                .with_read_conn(|reader| reader.query_row("SELECT COUNT(*) FROM items", [], |row| row.get::<_, i64>(0)))
+                .unwrap();
+            assert_eq!(count, 1);
+            conn.execute_batch("COMMIT;")
+        })
+        .unwrap();
+
+        let count = pool
+            .with_read_conn(|reader| reader.query_row("SELECT COUNT(*) FROM items", [], |row| row.get::<_, i64>(0)))
+            .unwrap();
+        assert_eq!(count, 2);
+    }
+
+    #[test]
+    fn test_panicking_read_returns_its_connection() {
+        let pool = test_pool("panic");
+        for _ in 0..2 {
+            let panicked = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+                pool.with_read_conn(|_| -> AppResult<()> { panic!("query failed") })
+            }));
+            assert!(panicked.is_err());
+        }
+        assert_eq!(pool.readers.lock().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_read_connections_reject_writes() {
+        let pool = test_pool("query-only");
+        let result = pool.with_read_conn(|reader| reader.execute("INSERT INTO items (value) VALUES ('x')", []));
+        assert!(result.is_err());
     }
 }
@@ -0,0 +1,241 @@
+use regex::Regex;
+use rusqlite::{params, Connection, OptionalExtension, Row};
+
+use crate::error::{AppError, AppResult};
+use crate::models::custom_tool::{CreateCustomTool, CustomTool, UpdateCustomTool};
+
+const COLUMNS: &str =
+    "id, name, command, args, env_vars, icon_label, idle_regex, usage_regex, created_at, updated_at";
+
+/// Named groups a usage regex may capture; at least one of the counts or the cost is required.
+const USAGE_GROUPS: [&str; 3] = ["input", "output", "cost"];
+
+pub fn create_custom_tool(conn: &Connection, id: &str, input: &CreateCustomTool) -> AppResult<CustomTool> {
+    validate_name(&input.name)?;
+    validate_command(&input.command)?;
+    let idle_regex = validate_idle_regex(input.idle_regex.as_deref())?;
+    let usage_regex = validate_usage_regex(input.usage_regex.as_deref())?;
+    let args_json = serde_json::to_string(&input.args).unwrap_or_else(|_| "[]".to_string());
+    let env_json = serde_json::to_string(&input.env_vars).unwrap_or_else(|_| "{}".to_string());
+    conn.execute(
+        "INSERT INTO custom_tools (id, name, command, args, env_vars, icon_label, idle_regex, usage_regex)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+        params![
+            id,
+            input.name.trim(),
+            input.command.trim(),
+            args_json,
+            env_json,
+            input.icon_label,
+            idle_regex,
+            usage_regex,
+        ],
+    )?;
+    get_custom_tool(conn, id)
+}
+
+pub fn get_custom_tool(conn: &Connection, id: &str) -> AppResult<CustomTool> {
+    conn.query_row(
+        &format!("SELECT {COLUMNS} FROM custom_tools WHERE id = ?1"),
+        [id],
+        custom_tool_from_row,
+    )
+    .optional()?
+    .ok_or_else(|| AppError::not_found("custom tool", id))
+}
+
+pub fn list_custom_tools(conn: &Connection) -> AppResult<Vec<CustomTool>> {
+    let mut stmt = conn.prepare(&format!("SELECT {COLUMNS} FROM custom_tools ORDER BY name COLLATE NOCASE"))?;
+    let rows = stmt.query_map([], custom_tool_from_row)?;
+    Ok(rows.collect::<Result<_, _>>()?)
+}
+
+pub fn update_custom_tool(conn: &Connection, id: &str, input: &UpdateCustomTool) -> AppResult<CustomTool> {
+    // Validate everything before writing anything, so a bad regex leaves the tool untouched
+    get_custom_tool(conn, id)?;
+    if let Some(name) = &input.name {
+        validate_name(name)?;
+    }
+    if let Some(command) = &input.command {
+        validate_command(command)?;
+    }
+    let idle_regex = input.idle_regex.as_deref().map(|re| validate_idle_regex(Some(re))).transpose()?;
+    let usage_regex = input.usage_regex.as_deref().map(|re| validate_usage_regex(Some(re))).transpose()?;
+
+    // One transaction, so a failed write cannot leave the tool half updated
+    let tx = conn.unchecked_transaction()?;
+    if let Some(name) = &input.name {
+        tx.execute("UPDATE custom_tools SET name = ?1, updated_at = datetime('now') WHERE id = ?2", params![name.trim(), id])?;
+    }
+    if let Some(command) = &input.command {
+        tx.execute("UPDATE custom_tools SET command = ?1, updated_at = datetime('now') WHERE id = ?2", params![command.trim(), id])?;
+    }
+    if let Some(args) = &input.args {
+        let args_json = serde_json::to_string(args).unwrap_or_else(|_| "[]".to_string());
+        tx.execute("UPDATE custom_tools SET args = ?1, updated_at = datetime('now') WHERE id = ?2", params![args_json, id])?;
+    }
+    if let Some(env_vars) = &input.env_vars {
+        let env_json = serde_json::to_string(env_vars).unwrap_or_else(|_| "{}".to_string());
+        tx.execute("UPDATE custom_tools SET env_vars = ?1, updated_at = datetime('now') WHERE id = ?2", params![env_json, id])?;
+    }
+    if let Some(icon_label) = &input.icon_label {
+        tx.execute("UPDATE custom_tools SET icon_label = ?1, updated_at = datetime('now') WHERE id = ?2", params![icon_label, id])?;
+    }
+    if let Some(idle_regex) = idle_regex {
+        tx.execute("UPDATE custom_tools SET idle_regex = ?1, updated_at = datetime('now') WHERE id = ?2", params![idle_regex, id])?;
+    }
+    if let Some(usage_regex) = usage_regex {
+        tx.execute("UPDATE custom_tools SET usage_regex = ?1, updated_at = datetime('now') WHERE id = ?2", params![usage_regex, id])?;
+    }
+    tx.commit()?;
+    get_custom_tool(conn, id)
+}
+
+/// Deletes a tool no template uses. Saved sessions that used it can no longer be restored.
+pub fn delete_custom_tool(conn: &Connection, id: &str) -> AppResult<()> {
+    let templates: i64 = conn.query_row(
+        "SELECT COUNT(*) FROM templates WHERE custom_tool_id = ?1",
+        [id],
+        |row| row.get(0),
+    )?;
+    if templates > 0 {
+        return Err(AppError::Conflict(format!(
+            "Custom tool is used by {templates} template(s)"
+        )));
+    }
+    conn.execute("DELETE FROM custom_tools WHERE id = ?1", [id])?;
+    Ok(())
+}
+
+fn custom_tool_from_row(row: &Row) -> rusqlite::Result<CustomTool> {
+    let args_str: String = row.get(3)?;
+    let env_str: String = row.get(4)?;
+    Ok(CustomTool {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        command: row.get(2)?,
+        args: serde_json::from_str(&args_str).unwrap_or_default(),
+        env_vars: serde_json::from_str(&env_str).unwrap_or_default(),
+        icon_label: row.get(5)?,
+        idle_regex: row.get(6)?,
+        usage_regex: row.get(7)?,
+        created_at: row.get(8)?,
+        updated_at: row.get(9)?,
+    })
+}
+
+fn validate_name(name: &str) -> AppResult<()> {
+    if name.trim().is_empty() {
+        return Err(AppError::Validation("Custom tool name is empty".to_string()));
+    }
+    Ok(())
+}
+
+fn validate_command(command: &str) -> AppResult<()> {
+    if command.trim().is_empty() {
+        return Err(AppError::Validation("Custom tool command is empty".to_string()));
+    }
+    Ok(())
+}
+
+/// The regex to store: `None` for a missing or empty pattern, which clears it.
+fn validate_idle_regex(pattern: Option<&str>) -> AppResult<Option<String>> {
+    let Some(pattern) = pattern.filter(|p| !p.is_empty()) else {
+        return Ok(None);
+    };
+    compile("idle", pattern)?;
+    Ok(Some(pattern.to_string()))
+}
+
+fn validate_usage_regex(pattern: Option<&str>) -> AppResult<Option<String>> {
+    let Some(pattern) = pattern.filter(|p| !p.is_empty()) else {
+        return Ok(None);
+    };
+    let regex = compile("usage", pattern)?;
+    if !regex.capture_names().flatten().any(|name| USAGE_GROUPS.contains(&name)) {
+        return Err(AppError::Validation(
+            "Usage regex must capture at least one of (?P<input>…), (?P<output>…) or (?P<cost>…)".to_string(),
+        ));
+    }
+    Ok(Some(pattern.to_string()))
+}
+
+fn compile(kind: &str, pattern: &str) -> AppResult<Regex> {
+    Regex::new(pattern).map_err(|e| AppError::Validation(format!("Invalid {kind} regex: {e}")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::migrations::run_migrations;
+
+    fn database() -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        run_migrations(&conn).unwrap();
+        conn
+    }
+
+    fn input(name: &str) -> CreateCustomTool {
+        serde_json::from_value(serde_json::json!({
+            "name": name,
+            "command": "gpt-cli",
+            "args": ["--chat"],
+            "envVars": { "GPT_MODE": "tty" },
+            "iconLabel": "GPT",
+            "idleRegex": "^> $",
+            "usageRegex": r"(?P<input>\d+) in / (?P<output>\d+) out",
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_crud() {
+        let conn = database();
+        let created = create_custom_tool(&conn, "t1", &input(" gpt ")).unwrap();
+        assert_eq!(created.name, "gpt");
+        assert_eq!(created.env_vars["GPT_MODE"], "tty");
+        assert_eq!(list_custom_tools(&conn).unwrap().len(), 1);
+
+        let update: UpdateCustomTool =
+            serde_json::from_value(serde_json::json!({ "args": [], "idleRegex": "" })).unwrap();
+        let updated = update_custom_tool(&conn, "t1", &update).unwrap();
+        assert!(updated.args.is_empty());
+        assert_eq!(updated.idle_regex, None);
+        assert_eq!(updated.usage_regex, created.usage_regex);
+
+        delete_custom_tool(&conn, "t1").unwrap();
+        assert_eq!(get_custom_tool(&conn, "t1").unwrap_err().code(), "NOT_FOUND");
+    }
+
+    #[test]
+    fn test_rejects_invalid_definitions() {
+        let conn = database();
+        let mut bad = input("gpt");
+        bad.idle_regex = Some("(unclosed".to_string());
+        assert_eq!(create_custom_tool(&conn, "t1", &bad).unwrap_err().code(), "VALIDATION");
+        let mut bad = input("gpt");
+        bad.usage_regex = Some(r"\d+ tokens".to_string());
+        assert_eq!(create_custom_tool(&conn, "t1", &bad).unwrap_err().code(), "VALIDATION");
+        let mut bad = input("gpt");
+        bad.command = "  ".to_string();
+        assert_eq!(create_custom_tool(&conn, "t1", &bad).unwrap_err().code(), "VALIDATION");
+
+        create_custom_tool(&conn, "t1", &input("gpt")).unwrap();
+        let update: UpdateCustomTool =
+            serde_json::from_value(serde_json::json!({ "name": "renamed", "usageRegex": "[" })).unwrap();
+        assert!(update_custom_tool(&conn, "t1", &update).is_err());
+        assert_eq!(get_custom_tool(&conn, "t1").unwrap().name, "gpt");
+    }
+
+    #[test]
+    fn test_delete_refuses_tools_used_by_templates() {
+        let conn = database();
+        create_custom_tool(&conn, "t1", &input("gpt")).unwrap();
+        conn.execute(
+            "INSERT INTO templates (id, name, tool, prompt, custom_tool_id) VALUES ('p', 'p', 'Custom', '', 't1')",
+            [],
+        )
+        .unwrap();
+        assert_eq!(delete_custom_tool(&conn, "t1").unwrap_err().code(), "CONFLICT");
+    }
+}
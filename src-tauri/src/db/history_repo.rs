@@ -1,17 +1,31 @@
-use rusqlite::{params, Connection};
+use rusqlite::{params, Connection, OptionalExtension};
 
-use crate::models::history::{HistoryEntry, SearchQuery, SearchResult};
+use crate::error::{AppError, AppResult};
+use crate::models::history::{
+    HistoryEntry, NewSessionCommand, NewTranscriptMessage, SearchQuery, SearchResult, SessionCommand, TranscriptMessage,
+};
 
-pub fn insert_log(conn: &Connection, session_id: &str, content: &[u8]) -> Result<(), rusqlite::Error> {
-    let text = String::from_utf8_lossy(content);
+/// `stream_offset` is the position of `content` within the session's output stream, when known.
+pub fn insert_log(conn: &Connection, session_id: &str, content: &[u8], stream_offset: Option<i64>) -> AppResult<()> {
     conn.execute(
-        "INSERT INTO session_logs (session_id, content) VALUES (?1, ?2)",
-        params![session_id, text.as_ref()],
+        "INSERT INTO session_logs (session_id, content, stream_offset) VALUES (?1, ?2, ?3)",
+        params![session_id, decode_keeping_length(content), stream_offset],
     )?;
     Ok(())
 }
 
-pub fn search_logs(conn: &Connection, query: &SearchQuery) -> Result<SearchResult, rusqlite::Error> {
+/// Decodes output without changing its byte length, so stream offsets into the stored text
+/// stay valid: each invalid byte becomes `?` rather than a three-byte U+FFFD.
+fn decode_keeping_length(content: &[u8]) -> String {
+    let mut text = String::with_capacity(content.len());
+    for chunk in content.utf8_chunks() {
+        text.push_str(chunk.valid());
+        text.extend(std::iter::repeat_n('?', chunk.invalid().len()));
+    }
+    text
+}
+
+pub fn search_logs(conn: &Connection, query: &SearchQuery) -> AppResult<SearchResult> {
     let limit = query.limit.unwrap_or(50);
     let offset = query.offset.unwrap_or(0);
 
@@ -100,7 +114,7 @@ pub fn search_logs(conn: &Connection, query: &SearchQuery) -> Result<SearchResul
     }
 }
 
-pub fn get_session_log(conn: &Connection, session_id: &str) -> Result<String, rusqlite::Error> {
+pub fn get_session_log(conn: &Connection, session_id: &str) -> AppResult<String> {
     let mut stmt = conn.prepare(
         "SELECT content FROM session_logs WHERE session_id = ?1 ORDER BY created_at ASC"
     )?;
@@ -109,3 +123,124 @@ pub fn get_session_log(conn: &Connection, session_id: &str) -> Result<String, ru
         .collect::<Result<_, _>>()?;
     Ok(rows.join(""))
 }
+
+pub fn insert_command(conn: &Connection, command: &NewSessionCommand) -> AppResult<()> {
+    conn.execute(
+        "INSERT INTO session_commands (session_id, command, exit_code, output_start, output_end, started_at, ended_at, duration_ms)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+        params![
+            command.session_id,
+            command.command,
+            command.exit_code,
+            command.output_start,
+            command.output_end,
+            command.started_at,
+            command.ended_at,
+            command.duration_ms,
+        ],
+    )?;
+    Ok(())
+}
+
+pub fn list_commands(conn: &Connection, session_id: &str) -> AppResult<Vec<SessionCommand>> {
+    let mut stmt = conn.prepare(
+        "SELECT id, session_id, command, exit_code, output_start, output_end, started_at, ended_at, duration_ms
+         FROM session_commands WHERE session_id = ?1 ORDER BY output_start ASC"
+    )?;
+    let rows = stmt.query_map([session_id], |row| {
+        Ok(SessionCommand {
+            id: row.get(0)?,
+            session_id: row.get(1)?,
+            command: row.get(2)?,
+            exit_code: row.get(3)?,
+            output_start: row.get(4)?,
+            output_end: row.get(5)?,
+            started_at: row.get(6)?,
+            ended_at: row.get(7)?,
+            duration_ms: row.get(8)?,
+        })
+    })?;
+    Ok(rows.collect::<Result<_, _>>()?)
+}
+
+/// Stores a transcript entry unless one with the same `entry_id` is already stored.
+pub fn insert_transcript_message(conn: &Connection, message: &NewTranscriptMessage) -> AppResult<()> {
+    conn.execute(
+        "INSERT OR IGNORE INTO transcript_messages (session_id, entry_id, role, kind, name, content, timestamp)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+        params![
+            message.session_id,
+            message.entry_id,
+            message.role,
+            message.kind,
+            message.name,
+            message.content,
+            message.timestamp,
+        ],
+    )?;
+    Ok(())
+}
+
+pub fn list_transcript_messages(conn: &Connection, session_id: &str) -> AppResult<Vec<TranscriptMessage>> {
+    let mut stmt = conn.prepare(
+        "SELECT id, session_id, role, kind, name, content, timestamp, recorded_at
+         FROM transcript_messages WHERE session_id = ?1 ORDER BY id ASC"
+    )?;
+    let rows = stmt.query_map([session_id], |row| {
+        Ok(TranscriptMessage {
+            id: row.get(0)?,
+            session_id: row.get(1)?,
+            role: row.get(2)?,
+            kind: row.get(3)?,
+            name: row.get(4)?,
+            content: row.get(5)?,
+            timestamp: row.get(6)?,
+            recorded_at: row.get(7)?,
+        })
+    })?;
+    Ok(rows.collect::<Result<_, _>>()?)
+}
+
+/// Where the session's stored output stream ends, so a new process under the same id
+/// continues it rather than reusing offsets of an earlier run.
+pub fn get_log_end(conn: &Connection, session_id: &str) -> AppResult<u64> {
+    let end: i64 = conn.query_row(
+        "SELECT COALESCE(MAX(stream_offset + length(CAST(content AS BLOB))), 0)
+         FROM session_logs WHERE session_id = ?1 AND stream_offset IS NOT NULL",
+        [session_id],
+        |row| row.get(0),
+    )?;
+    Ok(end.max(0) as u64)
+}
+
+/// Reassembles a command's output from the log chunks overlapping its byte range.
+pub fn get_command_output(conn: &Connection, command_id: i64) -> AppResult<String> {
+    let (session_id, start, end): (String, i64, i64) = conn
+        .query_row(
+            "SELECT session_id, output_start, output_end FROM session_commands WHERE id = ?1",
+            [command_id],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+        )
+        .optional()?
+        .ok_or_else(|| AppError::not_found("command", command_id))?;
+
+    let mut stmt = conn.prepare(
+        "SELECT stream_offset, content FROM session_logs
+         WHERE session_id = ?1 AND stream_offset IS NOT NULL
+           AND stream_offset < ?3 AND stream_offset + length(CAST(content AS BLOB)) > ?2
+         ORDER BY stream_offset ASC"
+    )?;
+    let chunks = stmt.query_map(params![session_id, start, end], |row| {
+        Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?))
+    })?;
+
+    let mut output = Vec::new();
+    for chunk in chunks {
+        let (offset, content) = chunk?;
+        let bytes = content.as_bytes();
+        let from = (start - offset).clamp(0, bytes.len() as i64) as usize;
+        let to = (end - offset).clamp(0, bytes.len() as i64) as usize;
+        output.extend_from_slice(&bytes[from..to]);
+    }
+    Ok(String::from_utf8_lossy(&output).into_owned())
+}
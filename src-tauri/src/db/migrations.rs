@@ -1,36 +1,494 @@
-use rusqlite::Connection;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
 
-const MIGRATIONS: &[(&str, &str)] = &[
-    ("001_initial_schema", include_str!("../../migrations/001_initial_schema.sql")),
-    ("002_add_templates", include_str!("../../migrations/002_add_templates.sql")),
-    ("003_add_monitoring", include_str!("../../migrations/003_add_monitoring.sql")),
-    ("004_add_sessions", include_str!("../../migrations/004_add_sessions.sql")),
+use crate::error::{AppError, AppResult};
+
+pub struct Migration {
+    pub name: &'static str,
+    pub kind: MigrationKind,
+}
+
+pub enum MigrationKind {
+    Sql(&'static str),
+    /// Adds a column unless it already exists, so it is safe whatever state the table is in.
+    AddColumn {
+        table: &'static str,
+        column: &'static str,
+        definition: &'static str,
+    },
+}
+
+const MIGRATIONS: &[Migration] = &[
+    Migration { name: "001_initial_schema", kind: MigrationKind::Sql(include_str!("../../migrations/001_initial_schema.sql")) },
+    Migration { name: "002_add_templates", kind: MigrationKind::Sql(include_str!("../../migrations/002_add_templates.sql")) },
+    Migration { name: "003_add_monitoring", kind: MigrationKind::Sql(include_str!("../../migrations/003_add_monitoring.sql")) },
+    Migration { name: "004_add_sessions", kind: MigrationKind::Sql(include_str!("../../migrations/004_add_sessions.sql")) },
+    Migration { name: "005_add_session_title", kind: MigrationKind::Sql(include_str!("../../migrations/005_add_session_title.sql")) },
+    Migration { name: "006_add_session_commands", kind: MigrationKind::Sql(include_str!("../../migrations/006_add_session_commands.sql")) },
+    // 004 was a no-op wherever 001 had already created `sessions`
+    Migration {
+        name: "007_add_session_status",
+        kind: MigrationKind::AddColumn {
+            table: "sessions",
+            column: "status",
+            definition: "TEXT NOT NULL DEFAULT 'stopped'",
+        },
+    },
+    Migration {
+        name: "008_add_session_tool_version",
+        kind: MigrationKind::AddColumn {
+            table: "sessions",
+            column: "tool_version",
+            definition: "TEXT",
+        },
+    },
+    Migration { name: "009_add_custom_tools", kind: MigrationKind::Sql(include_str!("../../migrations/009_add_custom_tools.sql")) },
+    Migration {
+        name: "010_add_template_custom_tool",
+        kind: MigrationKind::AddColumn {
+            table: "templates",
+            column: "custom_tool_id",
+            definition: "TEXT",
+        },
+    },
+    Migration {
+        name: "011_add_session_custom_tool",
+        kind: MigrationKind::AddColumn {
+            table: "sessions",
+            column: "custom_tool_id",
+            definition: "TEXT",
+        },
+    },
+    Migration { name: "012_add_session_restarts", kind: MigrationKind::Sql(include_str!("../../migrations/012_add_session_restarts.sql")) },
+    Migration {
+        name: "013_add_usage_cache_read",
+        kind: MigrationKind::AddColumn {
+            table: "token_usage",
+            column: "cache_read_tokens",
+            definition: "INTEGER NOT NULL DEFAULT 0",
+        },
+    },
+    Migration {
+        name: "014_add_usage_cache_creation",
+        kind: MigrationKind::AddColumn {
+            table: "token_usage",
+            column: "cache_creation_tokens",
+            definition: "INTEGER NOT NULL DEFAULT 0",
+        },
+    },
+    Migration {
+        name: "015_add_usage_message_id",
+        kind: MigrationKind::AddColumn {
+            table: "token_usage",
+            column: "message_id",
+            definition: "TEXT",
+        },
+    },
+    Migration { name: "016_add_usage_message_index", kind: MigrationKind::Sql(include_str!("../../migrations/016_add_usage_message_index.sql")) },
+    Migration { name: "017_add_transcript_messages", kind: MigrationKind::Sql(include_str!("../../migrations/017_add_transcript_messages.sql")) },
+    Migration { name: "018_add_usage_totals", kind: MigrationKind::Sql(include_str!("../../migrations/018_add_usage_totals.sql")) },
 ];
 
-pub fn run_migrations(conn: &Connection) -> Result<(), String> {
+/// What `run_migrations` did beyond applying pending migrations.
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MigrationReport {
+    pub applied: Vec<String>,
+    /// Applied migrations whose source changed since they ran.
+    pub checksum_mismatches: Vec<String>,
+    pub drift: SchemaDrift,
+    /// Schema objects and columns that were recreated to fix drift.
+    pub repaired: Vec<String>,
+}
+
+/// Differences between the schema the migrations produce and the one on disk.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SchemaDrift {
+    /// Tables, indexes and triggers, as `type name`.
+    pub missing_objects: Vec<String>,
+    /// As `table.column`.
+    pub missing_columns: Vec<String>,
+}
+
+impl SchemaDrift {
+    pub fn is_empty(&self) -> bool {
+        self.missing_objects.is_empty() && self.missing_columns.is_empty()
+    }
+}
+
+/// Applies pending migrations (each in its own transaction), then checks the schema
+/// against a freshly migrated one and repairs anything missing.
+pub fn run_migrations(conn: &Connection) -> AppResult<MigrationReport> {
+    let mut report = apply_migrations(conn, MIGRATIONS)?;
+    report.drift = detect_drift(conn)?;
+    if !report.drift.is_empty() {
+        report.repaired = repair_drift(conn)?;
+    }
+    Ok(report)
+}
+
+fn apply_migrations(conn: &Connection, migrations: &[Migration]) -> AppResult<MigrationReport> {
+    ensure_migrations_table(conn)?;
+    let mut report = MigrationReport::default();
+
+    for migration in migrations {
+        let checksum = migration.checksum();
+        let recorded: Option<Option<String>> = conn
+            .query_row(
+                "SELECT checksum FROM _migrations WHERE name = ?1",
+                [migration.name],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(|e| migration_error(migration.name, "check", e))?;
+
+        match recorded {
+            Some(Some(recorded)) => {
+                if recorded != checksum {
+                    report.checksum_mismatches.push(migration.name.to_string());
+                }
+            }
+            // Applied before checksums were recorded
+            Some(None) => {
+                conn.execute(
+                    "UPDATE _migrations SET checksum = ?1 WHERE name = ?2",
+                    params![checksum, migration.name],
+                )
+                .map_err(|e| migration_error(migration.name, "record checksum for", e))?;
+            }
+            None => {
+                let tx = conn
+                    .unchecked_transaction()
+                    .map_err(|e| migration_error(migration.name, "start", e))?;
+                migration
+                    .apply(&tx)
+                    .map_err(|e| migration_error(migration.name, "run", e))?;
+                tx.execute(
+                    "INSERT INTO _migrations (name, checksum) VALUES (?1, ?2)",
+                    params![migration.name, checksum],
+                )
+                .map_err(|e| migration_error(migration.name, "record", e))?;
+                tx.commit()
+                    .map_err(|e| migration_error(migration.name, "commit", e))?;
+                report.applied.push(migration.name.to_string());
+            }
+        }
+    }
+
+    Ok(report)
+}
+
+fn ensure_migrations_table(conn: &Connection) -> AppResult<()> {
     conn.execute_batch(
         "CREATE TABLE IF NOT EXISTS _migrations (
             name TEXT PRIMARY KEY,
+            checksum TEXT,
             applied_at TEXT NOT NULL DEFAULT (datetime('now'))
         );"
-    ).map_err(|e| format!("Failed to create migrations table: {e}"))?;
+    ).map_err(|e| migration_error("_migrations", "create migrations table", e))?;
+    if !column_exists(conn, "_migrations", "checksum")? {
+        conn.execute_batch("ALTER TABLE _migrations ADD COLUMN checksum TEXT;")
+            .map_err(|e| migration_error("_migrations", "add checksum column", e))?;
+    }
+    Ok(())
+}
 
-    for (name, sql) in MIGRATIONS {
-        let already_applied: bool = conn
-            .query_row(
-                "SELECT COUNT(*) > 0 FROM _migrations WHERE name = ?1",
-                [name],
-                |row| row.get(0),
-            )
-            .map_err(|e| format!("Failed to check migration {name}: {e}"))?;
+impl Migration {
+    fn checksum(&self) -> String {
+        let mut hasher = Sha256::new();
+        match &self.kind {
+            MigrationKind::Sql(sql) => hasher.update(sql.as_bytes()),
+            MigrationKind::AddColumn { table, column, definition } => {
+                hasher.update(format!("ADD COLUMN {table}.{column} {definition}").as_bytes())
+            }
+        }
+        hasher
+            .finalize()
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect()
+    }
 
-        if !already_applied {
-            conn.execute_batch(sql)
-                .map_err(|e| format!("Failed to run migration {name}: {e}"))?;
-            conn.execute("INSERT INTO _migrations (name) VALUES (?1)", [name])
-                .map_err(|e| format!("Failed to record migration {name}: {e}"))?;
+    fn apply(&self, conn: &Connection) -> Result<(), rusqlite::Error> {
+        match &self.kind {
+            MigrationKind::Sql(sql) => conn.execute_batch(sql),
+            MigrationKind::AddColumn { table, column, definition } => {
+                if !column_exists(conn, table, column)? {
+                    conn.execute_batch(&format!("ALTER TABLE {table} ADD COLUMN {column} {definition};"))?;
+                }
+                Ok(())
+            }
         }
     }
+}
 
-    Ok(())
+struct SchemaObject {
+    kind: String,
+    name: String,
+    sql: String,
+}
+
+struct Column {
+    name: String,
+    declared_type: String,
+    not_null: bool,
+    default: Option<String>,
+}
+
+/// The schema a database gets from running every migration from scratch.
+fn expected_schema() -> AppResult<Connection> {
+    let conn = Connection::open_in_memory()?;
+    apply_migrations(&conn, MIGRATIONS)?;
+    Ok(conn)
+}
+
+pub fn detect_drift(conn: &Connection) -> AppResult<SchemaDrift> {
+    let expected = expected_schema()?;
+    let mut drift = SchemaDrift::default();
+    for object in schema_objects(&expected)? {
+        if !object_exists(conn, &object.name)? {
+            drift.missing_objects.push(format!("{} {}", object.kind, object.name));
+            continue;
+        }
+        if object.kind != "table" {
+            continue;
+        }
+        for column in table_columns(&expected, &object.name)? {
+            if !column_exists(conn, &object.name, &column.name)? {
+                drift.missing_columns.push(format!("{}.{}", object.name, column.name));
+            }
+        }
+    }
+    Ok(drift)
+}
+
+/// Recreates missing tables, indexes and triggers from the expected schema and adds
+/// missing columns. Returns what was repaired.
+fn repair_drift(conn: &Connection) -> AppResult<Vec<String>> {
+    let expected = expected_schema()?;
+    let tx = conn.unchecked_transaction()?;
+    let mut repaired = Vec::new();
+
+    for object in schema_objects(&expected)? {
+        // Creating an FTS table also creates its shadow tables, so re-check each time
+        if object_exists(&tx, &object.name)? {
+            continue;
+        }
+        tx.execute_batch(&object.sql)
+            .map_err(|e| repair_error(&object.name, e))?;
+        repaired.push(format!("{} {}", object.kind, object.name));
+    }
+
+    for object in schema_objects(&expected)? {
+        if object.kind != "table" {
+            continue;
+        }
+        for column in table_columns(&expected, &object.name)? {
+            if column_exists(&tx, &object.name, &column.name)? {
+                continue;
+            }
+            let mut definition = column.declared_type.clone();
+            if let Some(default) = &column.default {
+                definition.push_str(&format!(" DEFAULT {default}"));
+            }
+            // SQLite only allows adding NOT NULL columns that have a default
+            if column.not_null && column.default.is_some() {
+                definition.push_str(" NOT NULL");
+            }
+            tx.execute_batch(&format!(
+                "ALTER TABLE {} ADD COLUMN {} {definition};",
+                object.name, column.name
+            ))
+            .map_err(|e| repair_error(&format!("{}.{}", object.name, column.name), e))?;
+            repaired.push(format!("{}.{}", object.name, column.name));
+        }
+    }
+
+    tx.commit()?;
+    Ok(repaired)
+}
+
+fn schema_objects(conn: &Connection) -> AppResult<Vec<SchemaObject>> {
+    let mut stmt = conn.prepare(
+        "SELECT type, name, sql FROM sqlite_master
+         WHERE type IN ('table', 'index', 'trigger') AND sql IS NOT NULL
+           AND name NOT LIKE 'sqlite_%' AND name != '_migrations'
+         ORDER BY rowid",
+    )?;
+    let rows = stmt.query_map([], |row| {
+        Ok(SchemaObject {
+            kind: row.get(0)?,
+            name: row.get(1)?,
+            sql: row.get(2)?,
+        })
+    })?;
+    Ok(rows.collect::<Result<_, _>>()?)
+}
+
+fn table_columns(conn: &Connection, table: &str) -> AppResult<Vec<Column>> {
+    let mut stmt = conn.prepare("SELECT name, type, \"notnull\", dflt_value FROM pragma_table_info(?1)")?;
+    let rows = stmt.query_map([table], |row| {
+        Ok(Column {
+            name: row.get(0)?,
+            declared_type: row.get(1)?,
+            not_null: row.get(2)?,
+            default: row.get(3)?,
+        })
+    })?;
+    Ok(rows.collect::<Result<_, _>>()?)
+}
+
+fn object_exists(conn: &Connection, name: &str) -> Result<bool, rusqlite::Error> {
+    conn.query_row(
+        "SELECT COUNT(*) > 0 FROM sqlite_master WHERE name = ?1",
+        [name],
+        |row| row.get(0),
+    )
+}
+
+fn column_exists(conn: &Connection, table: &str, column: &str) -> Result<bool, rusqlite::Error> {
+    conn.query_row(
+        "SELECT COUNT(*) > 0 FROM pragma_table_info(?1) WHERE name = ?2",
+        [table, column],
+        |row| row.get(0),
+    )
+}
+
+fn migration_error(name: &str, step: &str, error: rusqlite::Error) -> AppError {
+    AppError::Migration {
+        name: name.to_string(),
+        message: format!("Failed to {step}: {error}"),
+    }
+}
+
+fn repair_error(object: &str, error: rusqlite::Error) -> AppError {
+    AppError::Migration {
+        name: "schema repair".to_string(),
+        message: format!("Failed to repair {object}: {error}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::session_repo::{self, SavedSession};
+
+    /// A database as the pre-checksum runner left it after the first `version` migrations.
+    fn legacy_database(version: usize) -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "CREATE TABLE _migrations (
+                name TEXT PRIMARY KEY,
+                applied_at TEXT NOT NULL DEFAULT (datetime('now'))
+            );",
+        )
+        .unwrap();
+        for migration in &MIGRATIONS[..version] {
+            migration.apply(&conn).unwrap();
+            conn.execute("INSERT INTO _migrations (name) VALUES (?1)", [migration.name])
+                .unwrap();
+        }
+        conn
+    }
+
+    fn saved_session(id: &str) -> SavedSession {
+        SavedSession {
+            id: id.to_string(),
+            name: "shell".to_string(),
+            tool: "Custom".to_string(),
+            command: "sh".to_string(),
+            args: Vec::new(),
+            working_dir: "/tmp".to_string(),
+            env_vars: Default::default(),
+            cols: 80,
+            rows: 24,
+            status: "running".to_string(),
+            title: None,
+            cwd: None,
+            tool_version: None,
+            custom_tool_id: None,
+            created_at: String::new(),
+            updated_at: String::new(),
+        }
+    }
+
+    #[test]
+    fn test_migrates_from_every_historical_version() {
+        for version in 0..=MIGRATIONS.len() {
+            let conn = legacy_database(version);
+            let report = run_migrations(&conn).unwrap();
+            assert_eq!(report.applied.len(), MIGRATIONS.len() - version, "from version {version}");
+            assert!(report.checksum_mismatches.is_empty());
+            assert!(detect_drift(&conn).unwrap().is_empty(), "from version {version}");
+
+            session_repo::save_session(&conn, &saved_session("s")).unwrap();
+            let restorable = session_repo::list_restorable_sessions(&conn).unwrap();
+            assert_eq!(restorable.len(), 1, "from version {version}");
+
+            let unrecorded: i64 = conn
+                .query_row("SELECT COUNT(*) FROM _migrations WHERE checksum IS NULL", [], |row| row.get(0))
+                .unwrap();
+            assert_eq!(unrecorded, 0);
+        }
+    }
+
+    #[test]
+    fn test_rerun_is_a_no_op() {
+        let conn = Connection::open_in_memory().unwrap();
+        run_migrations(&conn).unwrap();
+        let report = run_migrations(&conn).unwrap();
+        assert!(report.applied.is_empty());
+        assert!(report.drift.is_empty());
+        assert!(report.repaired.is_empty());
+    }
+
+    #[test]
+    fn test_failed_migration_rolls_back() {
+        let conn = Connection::open_in_memory().unwrap();
+        let broken = [Migration {
+            name: "broken",
+            kind: MigrationKind::Sql("CREATE TABLE partial (id INTEGER); INSERT INTO missing VALUES (1);"),
+        }];
+        let error = apply_migrations(&conn, &broken).unwrap_err();
+        assert_eq!(error.code(), "MIGRATION");
+        assert!(!object_exists(&conn, "partial").unwrap());
+        let recorded: i64 = conn
+            .query_row("SELECT COUNT(*) FROM _migrations", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(recorded, 0);
+    }
+
+    #[test]
+    fn test_repairs_drift() {
+        let conn = Connection::open_in_memory().unwrap();
+        run_migrations(&conn).unwrap();
+        conn.execute_batch(
+            "DROP INDEX idx_session_commands_session_id;
+             ALTER TABLE sessions DROP COLUMN title;",
+        )
+        .unwrap();
+
+        let drift = detect_drift(&conn).unwrap();
+        assert_eq!(drift.missing_objects, vec!["index idx_session_commands_session_id"]);
+        assert_eq!(drift.missing_columns, vec!["sessions.title"]);
+
+        let report = run_migrations(&conn).unwrap();
+        assert_eq!(report.repaired.len(), 2);
+        assert!(detect_drift(&conn).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_detects_changed_migration_source() {
+        let conn = Connection::open_in_memory().unwrap();
+        run_migrations(&conn).unwrap();
+        conn.execute(
+            "UPDATE _migrations SET checksum = 'stale' WHERE name = '002_add_templates'",
+            [],
+        )
+        .unwrap();
+        let report = run_migrations(&conn).unwrap();
+        assert_eq!(report.checksum_mismatches, vec!["002_add_templates"]);
+    }
 }
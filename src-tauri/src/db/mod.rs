@@ -1,4 +1,5 @@
 pub mod connection;
+pub mod custom_tool_repo;
 pub mod history_repo;
 pub mod migrations;
 pub mod monitoring_repo;
@@ -1,18 +1,132 @@
-use rusqlite::{params, Connection};
+use rusqlite::{params, Connection, OptionalExtension};
 
+use crate::error::AppResult;
 use crate::models::monitoring::{GlobalCostSummary, RecordTokenUsage, SessionCostSummary, TokenUsageRecord};
 
-pub fn record_usage(conn: &Connection, input: &RecordTokenUsage) -> Result<(), rusqlite::Error> {
+/// Costs closer than this are equal; totals are printed with at most a few decimals.
+const COST_EPSILON: f64 = 1e-9;
+
+/// Records usage. A record with a `message_id` that was already recorded updates that
+/// row instead, since tools rewrite a message's usage as it streams in. A cumulative record
+/// only adds what the session's total for its model grew by since the previous one.
+pub fn record_usage(conn: &Connection, input: &RecordTokenUsage) -> AppResult<()> {
+    if input.cumulative {
+        return record_total(conn, input);
+    }
+    insert_usage(conn, input)
+}
+
+fn record_total(conn: &Connection, total: &RecordTokenUsage) -> AppResult<()> {
+    let previous = conn
+        .query_row(
+            "SELECT input_tokens, output_tokens, cache_read_tokens, cache_creation_tokens, cost_usd
+             FROM usage_totals WHERE session_id = ?1 AND model = ?2",
+            params![total.session_id, total.model],
+            |row| {
+                Ok(RecordTokenUsage {
+                    input_tokens: row.get(0)?,
+                    output_tokens: row.get(1)?,
+                    cache_read_tokens: row.get(2)?,
+                    cache_creation_tokens: row.get(3)?,
+                    cost_usd: row.get(4)?,
+                    ..Default::default()
+                })
+            },
+        )
+        .optional()?;
+    let increase = match previous {
+        Some(previous) if !is_below(total, &previous) => RecordTokenUsage {
+            session_id: total.session_id.clone(),
+            input_tokens: total.input_tokens - previous.input_tokens,
+            output_tokens: total.output_tokens - previous.output_tokens,
+            cache_read_tokens: total.cache_read_tokens - previous.cache_read_tokens,
+            cache_creation_tokens: total.cache_creation_tokens - previous.cache_creation_tokens,
+            model: total.model.clone(),
+            cost_usd: total.cost_usd - previous.cost_usd,
+            message_id: None,
+            cumulative: false,
+        },
+        // A total that went down started over, e.g. in a new process, so all of it is new
+        _ => RecordTokenUsage {
+            message_id: None,
+            cumulative: false,
+            ..total.clone()
+        },
+    };
+
+    conn.execute(
+        "INSERT INTO usage_totals (session_id, model, input_tokens, output_tokens, cache_read_tokens, cache_creation_tokens, cost_usd)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
+         ON CONFLICT(session_id, model) DO UPDATE SET
+            input_tokens = excluded.input_tokens,
+            output_tokens = excluded.output_tokens,
+            cache_read_tokens = excluded.cache_read_tokens,
+            cache_creation_tokens = excluded.cache_creation_tokens,
+            cost_usd = excluded.cost_usd,
+            updated_at = datetime('now')",
+        params![
+            total.session_id,
+            total.model,
+            total.input_tokens,
+            total.output_tokens,
+            total.cache_read_tokens,
+            total.cache_creation_tokens,
+            total.cost_usd,
+        ],
+    )?;
+    // The same total printed again adds nothing
+    if is_empty(&increase) {
+        return Ok(());
+    }
+    insert_usage(conn, &increase)
+}
+
+/// Whether any count or the cost of `usage` is lower than in `other`.
+fn is_below(usage: &RecordTokenUsage, other: &RecordTokenUsage) -> bool {
+    usage.input_tokens < other.input_tokens
+        || usage.output_tokens < other.output_tokens
+        || usage.cache_read_tokens < other.cache_read_tokens
+        || usage.cache_creation_tokens < other.cache_creation_tokens
+        || usage.cost_usd < other.cost_usd - COST_EPSILON
+}
+
+fn is_empty(usage: &RecordTokenUsage) -> bool {
+    usage.input_tokens == 0
+        && usage.output_tokens == 0
+        && usage.cache_read_tokens == 0
+        && usage.cache_creation_tokens == 0
+        && usage.cost_usd.abs() < COST_EPSILON
+}
+
+fn insert_usage(conn: &Connection, input: &RecordTokenUsage) -> AppResult<()> {
     conn.execute(
-        "INSERT INTO token_usage (session_id, input_tokens, output_tokens, model, cost_usd) VALUES (?1, ?2, ?3, ?4, ?5)",
-        params![input.session_id, input.input_tokens, input.output_tokens, input.model, input.cost_usd],
+        "INSERT INTO token_usage (session_id, input_tokens, output_tokens, cache_read_tokens, cache_creation_tokens, model, cost_usd, message_id)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)
+         ON CONFLICT(message_id) WHERE message_id IS NOT NULL DO UPDATE SET
+            input_tokens = excluded.input_tokens,
+            output_tokens = excluded.output_tokens,
+            cache_read_tokens = excluded.cache_read_tokens,
+            cache_creation_tokens = excluded.cache_creation_tokens,
+            model = excluded.model,
+            cost_usd = excluded.cost_usd",
+        params![
+            input.session_id,
+            input.input_tokens,
+            input.output_tokens,
+            input.cache_read_tokens,
+            input.cache_creation_tokens,
+            input.model,
+            input.cost_usd,
+            input.message_id,
+        ],
     )?;
     Ok(())
 }
 
-pub fn get_session_usage(conn: &Connection, session_id: &str) -> Result<Vec<TokenUsageRecord>, rusqlite::Error> {
+pub fn get_session_usage(conn: &Connection, session_id: &str) -> AppResult<Vec<TokenUsageRecord>> {
     let mut stmt = conn.prepare(
-        "SELECT id, session_id, input_tokens, output_tokens, model, cost_usd, recorded_at FROM token_usage WHERE session_id = ?1 ORDER BY recorded_at DESC"
+        "SELECT id, session_id, input_tokens, output_tokens, cache_read_tokens, cache_creation_tokens, model, cost_usd, message_id, recorded_at
+         FROM token_usage WHERE session_id = ?1 ORDER BY recorded_at DESC"
     )?;
     let rows = stmt.query_map([session_id], |row| {
         Ok(TokenUsageRecord {
@@ -20,37 +134,49 @@ pub fn get_session_usage(conn: &Connection, session_id: &str) -> Result<Vec<Toke
             session_id: row.get(1)?,
             input_tokens: row.get(2)?,
             output_tokens: row.get(3)?,
-            model: row.get(4)?,
-            cost_usd: row.get(5)?,
-            recorded_at: row.get(6)?,
+            cache_read_tokens: row.get(4)?,
+            cache_creation_tokens: row.get(5)?,
+            model: row.get(6)?,
+            cost_usd: row.get(7)?,
+            message_id: row.get(8)?,
+            recorded_at: row.get(9)?,
         })
     })?;
-    rows.collect()
+    Ok(rows.collect::<Result<_, _>>()?)
 }
 
-pub fn get_session_cost_summary(conn: &Connection, session_id: &str) -> Result<SessionCostSummary, rusqlite::Error> {
-    conn.query_row(
-        "SELECT COALESCE(SUM(input_tokens), 0), COALESCE(SUM(output_tokens), 0), COALESCE(SUM(cost_usd), 0.0), COUNT(*) FROM token_usage WHERE session_id = ?1",
+pub fn get_session_cost_summary(conn: &Connection, session_id: &str) -> AppResult<SessionCostSummary> {
+    Ok(conn.query_row(
+        "SELECT COALESCE(SUM(input_tokens), 0), COALESCE(SUM(output_tokens), 0), COALESCE(SUM(cache_read_tokens), 0),
+                COALESCE(SUM(cache_creation_tokens), 0), COALESCE(SUM(cost_usd), 0.0), COUNT(*)
+         FROM token_usage WHERE session_id = ?1",
         [session_id],
         |row| {
             Ok(SessionCostSummary {
                 session_id: session_id.to_string(),
                 total_input_tokens: row.get(0)?,
                 total_output_tokens: row.get(1)?,
-                total_cost_usd: row.get(2)?,
-                record_count: row.get(3)?,
+                total_cache_read_tokens: row.get(2)?,
+                total_cache_creation_tokens: row.get(3)?,
+                total_cost_usd: row.get(4)?,
+                record_count: row.get(5)?,
             })
         },
-    )
+    )?)
 }
 
-pub fn get_global_cost_summary(conn: &Connection) -> Result<GlobalCostSummary, rusqlite::Error> {
-    let total_input: i64 = conn.query_row("SELECT COALESCE(SUM(input_tokens), 0) FROM token_usage", [], |r| r.get(0))?;
-    let total_output: i64 = conn.query_row("SELECT COALESCE(SUM(output_tokens), 0) FROM token_usage", [], |r| r.get(0))?;
-    let total_cost: f64 = conn.query_row("SELECT COALESCE(SUM(cost_usd), 0.0) FROM token_usage", [], |r| r.get(0))?;
+pub fn get_global_cost_summary(conn: &Connection) -> AppResult<GlobalCostSummary> {
+    let (total_input, total_output, total_cache_read, total_cache_creation, total_cost) = conn.query_row(
+        "SELECT COALESCE(SUM(input_tokens), 0), COALESCE(SUM(output_tokens), 0), COALESCE(SUM(cache_read_tokens), 0),
+                COALESCE(SUM(cache_creation_tokens), 0), COALESCE(SUM(cost_usd), 0.0)
+         FROM token_usage",
+        [],
+        |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?, r.get(3)?, r.get(4)?)),
+    )?;
 
     let mut stmt = conn.prepare(
-        "SELECT session_id, SUM(input_tokens), SUM(output_tokens), SUM(cost_usd), COUNT(*) FROM token_usage GROUP BY session_id ORDER BY SUM(cost_usd) DESC"
+        "SELECT session_id, SUM(input_tokens), SUM(output_tokens), SUM(cache_read_tokens), SUM(cache_creation_tokens), SUM(cost_usd), COUNT(*)
+         FROM token_usage GROUP BY session_id ORDER BY SUM(cost_usd) DESC"
     )?;
     let per_session: Vec<SessionCostSummary> = stmt
         .query_map([], |row| {
@@ -58,8 +184,10 @@ pub fn get_global_cost_summary(conn: &Connection) -> Result<GlobalCostSummary, r
                 session_id: row.get(0)?,
                 total_input_tokens: row.get(1)?,
                 total_output_tokens: row.get(2)?,
-                total_cost_usd: row.get(3)?,
-                record_count: row.get(4)?,
+                total_cache_read_tokens: row.get(3)?,
+                total_cache_creation_tokens: row.get(4)?,
+                total_cost_usd: row.get(5)?,
+                record_count: row.get(6)?,
             })
         })?
         .collect::<Result<_, _>>()?;
@@ -67,8 +195,115 @@ pub fn get_global_cost_summary(conn: &Connection) -> Result<GlobalCostSummary, r
     Ok(GlobalCostSummary {
         total_input_tokens: total_input,
         total_output_tokens: total_output,
+        total_cache_read_tokens: total_cache_read,
+        total_cache_creation_tokens: total_cache_creation,
         total_cost_usd: total_cost,
         session_count: per_session.len() as i64,
         per_session,
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::migrations::run_migrations;
+    use crate::pty::usage_rules::UsageRules;
+    use crate::tools::{AiderAdapter, CustomAdapter};
+
+    const CLAUDE_COST: &str = include_str!("../../tests/fixtures/terminal/claude_cost.txt");
+    const AIDER_SESSION: &str = include_str!("../../tests/fixtures/terminal/aider_session.txt");
+
+    fn database() -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        run_migrations(&conn).unwrap();
+        conn.execute(
+            "INSERT INTO sessions (id, name, tool, command, working_dir) VALUES ('s', 's', 'Custom', 'sh', '')",
+            [],
+        )
+        .unwrap();
+        conn
+    }
+
+    fn total(input_tokens: i64, output_tokens: i64, cost_usd: f64) -> RecordTokenUsage {
+        RecordTokenUsage {
+            session_id: "s".to_string(),
+            input_tokens,
+            output_tokens,
+            model: "claude".to_string(),
+            cost_usd,
+            cumulative: true,
+            ..Default::default()
+        }
+    }
+
+    /// Feeds `transcript` to `parse` in flushes of `lines` lines each, as the output
+    /// logger does, along with where each flush starts, and records every reading.
+    fn replay(
+        conn: &Connection,
+        transcript: &str,
+        lines: usize,
+        parse: impl Fn(&str, u64) -> Vec<RecordTokenUsage>,
+    ) -> SessionCostSummary {
+        let all: Vec<&str> = transcript.split_inclusive('\n').collect();
+        let mut position = 0;
+        for flush in all.chunks(lines) {
+            let text = flush.concat();
+            for usage in parse(&text, position) {
+                record_usage(conn, &usage).unwrap();
+            }
+            position += text.len() as u64;
+        }
+        get_session_cost_summary(conn, "s").unwrap()
+    }
+
+    #[test]
+    fn test_cumulative_readings_store_increases() {
+        let conn = database();
+        record_usage(&conn, &total(100, 10, 0.5)).unwrap();
+        // The same total again adds nothing
+        record_usage(&conn, &total(100, 10, 0.5)).unwrap();
+        record_usage(&conn, &total(150, 30, 0.75)).unwrap();
+        let usage = get_session_usage(&conn, "s").unwrap();
+        assert_eq!(usage.len(), 2);
+        let summary = get_session_cost_summary(&conn, "s").unwrap();
+        assert_eq!((summary.total_input_tokens, summary.total_output_tokens), (150, 30));
+        assert!((summary.total_cost_usd - 0.75).abs() < COST_EPSILON);
+
+        // A lower total is a new run of the tool, all of which is new
+        record_usage(&conn, &total(20, 5, 0.1)).unwrap();
+        let summary = get_session_cost_summary(&conn, "s").unwrap();
+        assert_eq!((summary.total_input_tokens, summary.record_count), (170, 3));
+        assert!((summary.total_cost_usd - 0.85).abs() < COST_EPSILON);
+
+        // Totals are kept per model
+        record_usage(&conn, &RecordTokenUsage { model: "codex".to_string(), ..total(100, 10, 0.0) }).unwrap();
+        assert_eq!(get_session_cost_summary(&conn, "s").unwrap().total_input_tokens, 270);
+    }
+
+    #[test]
+    fn test_replayed_claude_cost_summaries_add_up_to_the_last_total() {
+        let rules = UsageRules::default();
+        for lines in [1, 3, 7, 16, usize::MAX] {
+            let conn = database();
+            let summary = replay(&conn, CLAUDE_COST, lines, |text, position| {
+                rules.parse(&CustomAdapter::default(), text, "s", position)
+            });
+            assert!((summary.total_cost_usd - 0.0734).abs() < COST_EPSILON, "{lines} lines per flush");
+        }
+    }
+
+    #[test]
+    fn test_replayed_aider_session_adds_up_to_the_session_cost() {
+        let rules = UsageRules::default();
+        for lines in [1, 4, 9, usize::MAX] {
+            let conn = database();
+            let parse = |text: &str, position| rules.parse(&AiderAdapter, text, "s", position);
+            replay(&conn, AIDER_SESSION, lines, parse);
+            // Parsing the same output again records nothing new
+            let summary = replay(&conn, AIDER_SESSION, lines, parse);
+            assert!((summary.total_cost_usd - 0.05).abs() < COST_EPSILON, "{lines} lines per flush");
+            assert_eq!(summary.record_count, 3);
+            assert_eq!(summary.total_input_tokens, 4200 + 5600 + 5900);
+        }
+    }
+}
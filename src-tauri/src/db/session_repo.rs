@@ -1,6 +1,8 @@
 use rusqlite::{params, Connection};
 use serde::{Deserialize, Serialize};
 
+use crate::error::{AppError, AppResult};
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct SavedSession {
@@ -14,17 +16,39 @@ pub struct SavedSession {
     pub cols: u16,
     pub rows: u16,
     pub status: String,
+    #[serde(default)]
+    pub title: Option<String>,
+    #[serde(default)]
+    pub cwd: Option<String>,
+    /// Version of the tool the session last ran, recorded by the backend after launch.
+    #[serde(default)]
+    pub tool_version: Option<String>,
+    /// The saved tool a `Custom` session launched.
+    #[serde(default)]
+    pub custom_tool_id: Option<String>,
     pub created_at: String,
     pub updated_at: String,
 }
 
-pub fn save_session(conn: &Connection, session: &SavedSession) -> Result<(), rusqlite::Error> {
+/// An automatic restart, recorded when the exited process is scheduled to start again.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionRestart {
+    pub id: i64,
+    pub session_id: String,
+    pub attempt: u32,
+    pub exit_code: Option<i32>,
+    pub restarted_at: String,
+}
+
+pub fn save_session(conn: &Connection, session: &SavedSession) -> AppResult<()> {
     let args_json = serde_json::to_string(&session.args).unwrap_or_else(|_| "[]".to_string());
     let env_json = serde_json::to_string(&session.env_vars).unwrap_or_else(|_| "{}".to_string());
 
     conn.execute(
-        "INSERT OR REPLACE INTO sessions (id, name, tool, command, args, working_dir, env_vars, cols, rows, status, updated_at)
-         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, datetime('now'))",
+        "INSERT OR REPLACE INTO sessions (id, name, tool, command, args, working_dir, env_vars, cols, rows, status, title, cwd, tool_version, custom_tool_id, updated_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12,
+                 COALESCE(?13, (SELECT tool_version FROM sessions WHERE id = ?1)), ?14, datetime('now'))",
         params![
             session.id,
             session.name,
@@ -36,22 +60,47 @@ pub fn save_session(conn: &Connection, session: &SavedSession) -> Result<(), rus
             session.cols,
             session.rows,
             session.status,
+            session.title,
+            session.cwd,
+            session.tool_version,
+            session.custom_tool_id,
         ],
     )?;
     Ok(())
 }
 
-pub fn update_session_status(conn: &Connection, id: &str, status: &str) -> Result<(), rusqlite::Error> {
-    conn.execute(
+pub fn update_session_status(conn: &Connection, id: &str, status: &str) -> AppResult<()> {
+    let updated = conn.execute(
         "UPDATE sessions SET status = ?1, updated_at = datetime('now') WHERE id = ?2",
         params![status, id],
     )?;
+    if updated == 0 {
+        return Err(AppError::not_found("session", id));
+    }
+    Ok(())
+}
+
+pub fn update_session_metadata(conn: &Connection, id: &str, title: Option<&str>, cwd: Option<&str>) -> AppResult<()> {
+    conn.execute(
+        "UPDATE sessions SET title = ?1, cwd = ?2, updated_at = datetime('now') WHERE id = ?3",
+        params![title, cwd, id],
+    )?;
+    Ok(())
+}
+
+/// Records the tool version once the backend has probed it. The row may not be saved yet,
+/// in which case there is nothing to update.
+pub fn update_tool_version(conn: &Connection, id: &str, version: &str) -> AppResult<()> {
+    conn.execute(
+        "UPDATE sessions SET tool_version = ?1 WHERE id = ?2",
+        params![version, id],
+    )?;
     Ok(())
 }
 
-pub fn list_all_sessions(conn: &Connection) -> Result<Vec<SavedSession>, rusqlite::Error> {
+pub fn list_all_sessions(conn: &Connection) -> AppResult<Vec<SavedSession>> {
     let mut stmt = conn.prepare(
-        "SELECT id, name, tool, command, args, working_dir, env_vars, cols, rows, status, created_at, updated_at
+        "SELECT id, name, tool, command, args, working_dir, env_vars, cols, rows, status, title, cwd, tool_version, custom_tool_id, created_at, updated_at
          FROM sessions ORDER BY created_at DESC"
     )?;
     let rows = stmt.query_map([], |row| {
@@ -68,16 +117,20 @@ pub fn list_all_sessions(conn: &Connection) -> Result<Vec<SavedSession>, rusqlit
             cols: row.get(7)?,
             rows: row.get(8)?,
             status: row.get(9)?,
-            created_at: row.get(10)?,
-            updated_at: row.get(11)?,
+            title: row.get(10)?,
+            cwd: row.get(11)?,
+            tool_version: row.get(12)?,
+            custom_tool_id: row.get(13)?,
+            created_at: row.get(14)?,
+            updated_at: row.get(15)?,
         })
     })?;
-    rows.collect()
+    Ok(rows.collect::<Result<_, _>>()?)
 }
 
-pub fn list_restorable_sessions(conn: &Connection) -> Result<Vec<SavedSession>, rusqlite::Error> {
+pub fn list_restorable_sessions(conn: &Connection) -> AppResult<Vec<SavedSession>> {
     let mut stmt = conn.prepare(
-        "SELECT id, name, tool, command, args, working_dir, env_vars, cols, rows, status, created_at, updated_at
+        "SELECT id, name, tool, command, args, working_dir, env_vars, cols, rows, status, title, cwd, tool_version, custom_tool_id, created_at, updated_at
          FROM sessions WHERE status = 'running' ORDER BY created_at DESC"
     )?;
     let rows = stmt.query_map([], |row| {
@@ -94,19 +147,54 @@ pub fn list_restorable_sessions(conn: &Connection) -> Result<Vec<SavedSession>,
             cols: row.get(7)?,
             rows: row.get(8)?,
             status: row.get(9)?,
-            created_at: row.get(10)?,
-            updated_at: row.get(11)?,
+            title: row.get(10)?,
+            cwd: row.get(11)?,
+            tool_version: row.get(12)?,
+            custom_tool_id: row.get(13)?,
+            created_at: row.get(14)?,
+            updated_at: row.get(15)?,
+        })
+    })?;
+    Ok(rows.collect::<Result<_, _>>()?)
+}
+
+pub fn session_exists(conn: &Connection, id: &str) -> AppResult<bool> {
+    Ok(conn.query_row("SELECT EXISTS(SELECT 1 FROM sessions WHERE id = ?1)", [id], |row| row.get(0))?)
+}
+
+/// Records a restart. Sessions that are not saved keep no restart history.
+pub fn record_restart(conn: &Connection, session_id: &str, attempt: u32, exit_code: Option<i32>) -> AppResult<()> {
+    conn.execute(
+        "INSERT INTO session_restarts (session_id, attempt, exit_code)
+         SELECT ?1, ?2, ?3 WHERE EXISTS (SELECT 1 FROM sessions WHERE id = ?1)",
+        params![session_id, attempt, exit_code],
+    )?;
+    Ok(())
+}
+
+pub fn list_restarts(conn: &Connection, session_id: &str) -> AppResult<Vec<SessionRestart>> {
+    let mut stmt = conn.prepare(
+        "SELECT id, session_id, attempt, exit_code, restarted_at
+         FROM session_restarts WHERE session_id = ?1 ORDER BY id",
+    )?;
+    let rows = stmt.query_map([session_id], |row| {
+        Ok(SessionRestart {
+            id: row.get(0)?,
+            session_id: row.get(1)?,
+            attempt: row.get(2)?,
+            exit_code: row.get(3)?,
+            restarted_at: row.get(4)?,
         })
     })?;
-    rows.collect()
+    Ok(rows.collect::<Result<_, _>>()?)
 }
 
-pub fn delete_session(conn: &Connection, id: &str) -> Result<(), rusqlite::Error> {
+pub fn delete_session(conn: &Connection, id: &str) -> AppResult<()> {
     conn.execute("DELETE FROM sessions WHERE id = ?1", [id])?;
     Ok(())
 }
 
-pub fn mark_all_stopped(conn: &Connection) -> Result<(), rusqlite::Error> {
+pub fn mark_all_stopped(conn: &Connection) -> AppResult<()> {
     conn.execute(
         "UPDATE sessions SET status = 'stopped', updated_at = datetime('now') WHERE status = 'running'",
         [],
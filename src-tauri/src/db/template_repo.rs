@@ -1,19 +1,24 @@
-use rusqlite::{params, Connection};
+use rusqlite::{params, Connection, OptionalExtension};
 
+use crate::db::custom_tool_repo;
+use crate::error::{AppError, AppResult};
 use crate::models::template::{CreateTemplate, PromptTemplate, UpdateTemplate};
 
-pub fn create_template(conn: &Connection, id: &str, input: &CreateTemplate) -> Result<PromptTemplate, rusqlite::Error> {
+pub fn create_template(conn: &Connection, id: &str, input: &CreateTemplate) -> AppResult<PromptTemplate> {
+    if let Some(tool_id) = &input.custom_tool_id {
+        custom_tool_repo::get_custom_tool(conn, tool_id)?;
+    }
     let tags_json = serde_json::to_string(&input.tags).unwrap_or_else(|_| "[]".to_string());
     conn.execute(
-        "INSERT INTO templates (id, name, tool, prompt, description, tags) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
-        params![id, input.name, input.tool, input.prompt, input.description, tags_json],
+        "INSERT INTO templates (id, name, tool, prompt, description, tags, custom_tool_id) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+        params![id, input.name, input.tool, input.prompt, input.description, tags_json, input.custom_tool_id],
     )?;
     get_template(conn, id)
 }
 
-pub fn get_template(conn: &Connection, id: &str) -> Result<PromptTemplate, rusqlite::Error> {
+pub fn get_template(conn: &Connection, id: &str) -> AppResult<PromptTemplate> {
     conn.query_row(
-        "SELECT id, name, tool, prompt, description, tags, created_at, updated_at FROM templates WHERE id = ?1",
+        "SELECT id, name, tool, prompt, description, tags, custom_tool_id, created_at, updated_at FROM templates WHERE id = ?1",
         [id],
         |row| {
             let tags_str: String = row.get(5)?;
@@ -25,16 +30,19 @@ pub fn get_template(conn: &Connection, id: &str) -> Result<PromptTemplate, rusql
                 prompt: row.get(3)?,
                 description: row.get(4)?,
                 tags,
-                created_at: row.get(6)?,
-                updated_at: row.get(7)?,
+                custom_tool_id: row.get(6)?,
+                created_at: row.get(7)?,
+                updated_at: row.get(8)?,
             })
         },
     )
+    .optional()?
+    .ok_or_else(|| AppError::not_found("template", id))
 }
 
-pub fn list_templates(conn: &Connection) -> Result<Vec<PromptTemplate>, rusqlite::Error> {
+pub fn list_templates(conn: &Connection) -> AppResult<Vec<PromptTemplate>> {
     let mut stmt = conn.prepare(
-        "SELECT id, name, tool, prompt, description, tags, created_at, updated_at FROM templates ORDER BY updated_at DESC",
+        "SELECT id, name, tool, prompt, description, tags, custom_tool_id, created_at, updated_at FROM templates ORDER BY updated_at DESC",
     )?;
     let rows = stmt.query_map([], |row| {
         let tags_str: String = row.get(5)?;
@@ -46,14 +54,15 @@ pub fn list_templates(conn: &Connection) -> Result<Vec<PromptTemplate>, rusqlite
             prompt: row.get(3)?,
             description: row.get(4)?,
             tags,
-            created_at: row.get(6)?,
-            updated_at: row.get(7)?,
+            custom_tool_id: row.get(6)?,
+            created_at: row.get(7)?,
+            updated_at: row.get(8)?,
         })
     })?;
-    rows.collect()
+    Ok(rows.collect::<Result<_, _>>()?)
 }
 
-pub fn update_template(conn: &Connection, id: &str, input: &UpdateTemplate) -> Result<PromptTemplate, rusqlite::Error> {
+pub fn update_template(conn: &Connection, id: &str, input: &UpdateTemplate) -> AppResult<PromptTemplate> {
     if let Some(name) = &input.name {
         conn.execute("UPDATE templates SET name = ?1, updated_at = datetime('now') WHERE id = ?2", params![name, id])?;
     }
@@ -70,10 +79,17 @@ pub fn update_template(conn: &Connection, id: &str, input: &UpdateTemplate) -> R
         let tags_json = serde_json::to_string(tags).unwrap_or_else(|_| "[]".to_string());
         conn.execute("UPDATE templates SET tags = ?1, updated_at = datetime('now') WHERE id = ?2", params![tags_json, id])?;
     }
+    if let Some(tool_id) = &input.custom_tool_id {
+        let tool_id = (!tool_id.is_empty()).then_some(tool_id);
+        if let Some(tool_id) = tool_id {
+            custom_tool_repo::get_custom_tool(conn, tool_id)?;
+        }
+        conn.execute("UPDATE templates SET custom_tool_id = ?1, updated_at = datetime('now') WHERE id = ?2", params![tool_id, id])?;
+    }
     get_template(conn, id)
 }
 
-pub fn delete_template(conn: &Connection, id: &str) -> Result<(), rusqlite::Error> {
+pub fn delete_template(conn: &Connection, id: &str) -> AppResult<()> {
     conn.execute("DELETE FROM templates WHERE id = ?1", [id])?;
     Ok(())
 }
@@ -0,0 +1,138 @@
+use serde::ser::SerializeStruct;
+use serde::{Serialize, Serializer};
+use std::fmt;
+
+pub type AppResult<T> = Result<T, AppError>;
+
+/// Error returned by every command. Serialises as `{ code, message, context }`, where
+/// `code` is stable so the frontend can branch on it and `message` is for display.
+#[derive(Debug, Clone, PartialEq)]
+pub enum AppError {
+    NotFound { resource: &'static str, id: String },
+    Database(String),
+    Migration { name: String, message: String },
+    Pty(String),
+    /// The session's process could not be started.
+    Spawn { command: String, message: String },
+    Io(String),
+    Validation(String),
+    Conflict(String),
+    Internal(String),
+}
+
+impl AppError {
+    pub fn not_found(resource: &'static str, id: impl ToString) -> Self {
+        AppError::NotFound {
+            resource,
+            id: id.to_string(),
+        }
+    }
+
+    pub fn code(&self) -> &'static str {
+        match self {
+            AppError::NotFound { .. } => "NOT_FOUND",
+            AppError::Database(_) => "DATABASE",
+            AppError::Migration { .. } => "MIGRATION",
+            AppError::Pty(_) => "PTY",
+            AppError::Spawn { .. } => "SPAWN_FAILED",
+            AppError::Io(_) => "IO",
+            AppError::Validation(_) => "VALIDATION",
+            AppError::Conflict(_) => "CONFLICT",
+            AppError::Internal(_) => "INTERNAL",
+        }
+    }
+
+    fn context(&self) -> Option<serde_json::Value> {
+        match self {
+            AppError::NotFound { resource, id } => Some(serde_json::json!({ "resource": resource, "id": id })),
+            AppError::Migration { name, .. } => Some(serde_json::json!({ "migration": name })),
+            AppError::Spawn { command, .. } => Some(serde_json::json!({ "command": command })),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for AppError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AppError::NotFound { resource, id } => {
+                let mut resource = resource.chars();
+                let first = resource.next().map(|c| c.to_uppercase().to_string()).unwrap_or_default();
+                write!(f, "{first}{} not found: {id}", resource.as_str())
+            }
+            AppError::Database(message) => write!(f, "Database error: {message}"),
+            AppError::Migration { name, message } => write!(f, "Migration {name} failed: {message}"),
+            AppError::Pty(message) => write!(f, "PTY error: {message}"),
+            AppError::Spawn { command, message } => write!(f, "Failed to spawn {command}: {message}"),
+            AppError::Io(message) => write!(f, "IO error: {message}"),
+            AppError::Validation(message) => write!(f, "Invalid input: {message}"),
+            AppError::Conflict(message) => write!(f, "Conflict: {message}"),
+            AppError::Internal(message) => write!(f, "Internal error: {message}"),
+        }
+    }
+}
+
+impl std::error::Error for AppError {}
+
+impl Serialize for AppError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let context = self.context();
+        let mut state = serializer.serialize_struct("AppError", 3)?;
+        state.serialize_field("code", self.code())?;
+        state.serialize_field("message", &self.to_string())?;
+        state.serialize_field("context", &context)?;
+        state.end()
+    }
+}
+
+impl From<rusqlite::Error> for AppError {
+    fn from(error: rusqlite::Error) -> Self {
+        match error {
+            rusqlite::Error::SqliteFailure(failure, _)
+                if failure.code == rusqlite::ErrorCode::ConstraintViolation =>
+            {
+                AppError::Conflict(error.to_string())
+            }
+            _ => AppError::Database(error.to_string()),
+        }
+    }
+}
+
+impl From<std::io::Error> for AppError {
+    fn from(error: std::io::Error) -> Self {
+        AppError::Io(error.to_string())
+    }
+}
+
+impl<T> From<std::sync::PoisonError<T>> for AppError {
+    fn from(error: std::sync::PoisonError<T>) -> Self {
+        AppError::Internal(format!("Lock error: {error}"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_serialises_code_message_and_context() {
+        let value = serde_json::to_value(AppError::not_found("session", "abc")).unwrap();
+        assert_eq!(
+            value,
+            serde_json::json!({
+                "code": "NOT_FOUND",
+                "message": "Session not found: abc",
+                "context": { "resource": "session", "id": "abc" },
+            })
+        );
+    }
+
+    #[test]
+    fn test_constraint_violation_is_a_conflict() {
+        let conn = rusqlite::Connection::open_in_memory().unwrap();
+        conn.execute_batch("CREATE TABLE t (id TEXT PRIMARY KEY); INSERT INTO t VALUES ('a');")
+            .unwrap();
+        let error: AppError = conn.execute("INSERT INTO t VALUES ('a')", []).unwrap_err().into();
+        assert_eq!(error.code(), "CONFLICT");
+    }
+}
@@ -1,8 +1,11 @@
 mod commands;
-mod db;
-mod models;
-mod pty;
+pub mod db;
+pub mod error;
+pub mod models;
+mod process;
+pub mod pty;
 mod state;
+pub mod tools;
 
 use tauri::Manager;
 
@@ -19,22 +22,34 @@ pub fn run() {
                 .app_data_dir()
                 .expect("Failed to get app data dir");
 
-            let state = AppState::new(app_dir).expect("Failed to initialize app state");
+            let state = AppState::new(app_dir.clone(), app.handle().clone()).expect("Failed to initialize app state");
 
             // Run migrations
-            state.db.with_conn(|conn| {
-                db::migrations::run_migrations(conn)
-                    .map_err(|_| rusqlite::Error::InvalidQuery)?;
-                Ok(())
-            }).expect("Failed to run migrations");
+            let report = state
+                .db
+                .with_conn(db::migrations::run_migrations)
+                .expect("Failed to run migrations");
+
+            state.pty_manager.start_activity_monitor();
+            state.pty_manager.start_usage_import();
+            let rule_files = pty::usage_rules::USER_RULES_FILES.iter().map(|name| app_dir.join(name)).collect();
+            state.pty_manager.start_usage_rules(rule_files);
+            state.pty_manager.environment().refresh_in_background();
 
             app.manage(state);
+            app.manage(report);
             Ok(())
         })
         .invoke_handler(tauri::generate_handler![
+            commands::validate_session_config,
             commands::create_session,
+            commands::attach_session,
+            commands::subscribe_session,
+            commands::unsubscribe_session,
             commands::write_to_pty,
             commands::resize_pty,
+            commands::get_screen_snapshot,
+            commands::get_terminal_environment,
             commands::stop_session,
             commands::list_sessions,
             commands::create_template,
@@ -42,20 +57,35 @@ pub fn run() {
             commands::get_template,
             commands::update_template,
             commands::delete_template,
+            commands::create_custom_tool,
+            commands::list_custom_tools,
+            commands::get_custom_tool,
+            commands::update_custom_tool,
+            commands::delete_custom_tool,
             commands::search_history,
             commands::get_session_log,
             commands::insert_session_log,
+            commands::list_session_commands,
+            commands::get_command_output,
+            commands::get_session_transcript,
             commands::record_token_usage,
             commands::get_session_usage,
             commands::get_session_cost_summary,
             commands::get_global_cost_summary,
+            commands::get_usage_rules,
+            commands::get_persistence_metrics,
+            commands::get_migration_report,
             commands::get_platform_defaults,
+            commands::get_login_environment,
+            commands::refresh_login_environment,
+            commands::discover_tools,
             commands::save_session_config,
             commands::list_saved_sessions,
             commands::list_restorable_sessions,
             commands::update_saved_session_status,
             commands::delete_saved_session,
             commands::mark_stale_sessions_stopped,
+            commands::list_session_restarts,
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
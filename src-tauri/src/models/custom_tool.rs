@@ -0,0 +1,52 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// A user-defined tool. Sessions and templates refer to it by id with `CliTool::Custom`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CustomTool {
+    pub id: String,
+    pub name: String,
+    pub command: String,
+    pub args: Vec<String>,
+    pub env_vars: HashMap<String, String>,
+    /// Short text shown in place of a tool icon, e.g. `GPT`.
+    pub icon_label: String,
+    /// Matched against the last screen line to tell the tool is waiting for input.
+    pub idle_regex: Option<String>,
+    /// Matched against output to record usage, using the named groups `input`, `output`,
+    /// `cost` and `model`.
+    pub usage_regex: Option<String>,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CreateCustomTool {
+    pub name: String,
+    pub command: String,
+    #[serde(default)]
+    pub args: Vec<String>,
+    #[serde(default)]
+    pub env_vars: HashMap<String, String>,
+    #[serde(default)]
+    pub icon_label: String,
+    #[serde(default)]
+    pub idle_regex: Option<String>,
+    #[serde(default)]
+    pub usage_regex: Option<String>,
+}
+
+/// Fields left `None` are unchanged; an empty regex clears it.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UpdateCustomTool {
+    pub name: Option<String>,
+    pub command: Option<String>,
+    pub args: Option<Vec<String>>,
+    pub env_vars: Option<HashMap<String, String>>,
+    pub icon_label: Option<String>,
+    pub idle_regex: Option<String>,
+    pub usage_regex: Option<String>,
+}
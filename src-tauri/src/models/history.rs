@@ -24,3 +24,59 @@ pub struct SearchResult {
     pub entries: Vec<HistoryEntry>,
     pub total: i64,
 }
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionCommand {
+    pub id: i64,
+    pub session_id: String,
+    pub command: String,
+    pub exit_code: Option<i32>,
+    /// Byte range of the command's output within the session's log stream.
+    pub output_start: i64,
+    pub output_end: i64,
+    pub started_at: String,
+    pub ended_at: String,
+    pub duration_ms: i64,
+}
+
+#[derive(Debug, Clone)]
+pub struct NewSessionCommand {
+    pub session_id: String,
+    pub command: String,
+    pub exit_code: Option<i32>,
+    pub output_start: i64,
+    pub output_end: i64,
+    pub started_at: String,
+    pub ended_at: String,
+    pub duration_ms: i64,
+}
+
+/// One entry of a conversation imported from a tool's transcript.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TranscriptMessage {
+    pub id: i64,
+    pub session_id: String,
+    /// `user`, `assistant` or `tool`.
+    pub role: String,
+    /// `message`, `reasoning`, `tool_call` or `tool_output`.
+    pub kind: String,
+    /// The tool called, for tool calls.
+    pub name: Option<String>,
+    pub content: String,
+    /// When the tool wrote the entry, if its transcript says.
+    pub timestamp: Option<String>,
+    pub recorded_at: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct NewTranscriptMessage {
+    pub session_id: String,
+    pub entry_id: String,
+    pub role: String,
+    pub kind: String,
+    pub name: Option<String>,
+    pub content: String,
+    pub timestamp: Option<String>,
+}
@@ -1,4 +1,6 @@
+pub mod custom_tool;
 pub mod history;
 pub mod monitoring;
+pub mod screen;
 pub mod session;
 pub mod template;
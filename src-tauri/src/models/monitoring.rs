@@ -7,19 +7,36 @@ pub struct TokenUsageRecord {
     pub session_id: String,
     pub input_tokens: i64,
     pub output_tokens: i64,
+    pub cache_read_tokens: i64,
+    pub cache_creation_tokens: i64,
     pub model: String,
     pub cost_usd: f64,
+    /// The message the usage belongs to, for usage imported from a tool's transcript.
+    pub message_id: Option<String>,
     pub recorded_at: String,
 }
 
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct RecordTokenUsage {
     pub session_id: String,
+    /// Input tokens that were neither read from nor written to the prompt cache.
     pub input_tokens: i64,
     pub output_tokens: i64,
+    #[serde(default)]
+    pub cache_read_tokens: i64,
+    #[serde(default)]
+    pub cache_creation_tokens: i64,
     pub model: String,
     pub cost_usd: f64,
+    /// Identifies the message the usage belongs to. Recording the same message again
+    /// replaces its counts instead of adding to them.
+    #[serde(default)]
+    pub message_id: Option<String>,
+    /// Whether the counts are the running totals of the session (per model) rather than
+    /// the usage of one message. Only the increase since the previous total is stored.
+    #[serde(default)]
+    pub cumulative: bool,
 }
 
 #[derive(Debug, Clone, Serialize)]
@@ -28,6 +45,8 @@ pub struct SessionCostSummary {
     pub session_id: String,
     pub total_input_tokens: i64,
     pub total_output_tokens: i64,
+    pub total_cache_read_tokens: i64,
+    pub total_cache_creation_tokens: i64,
     pub total_cost_usd: f64,
     pub record_count: i64,
 }
@@ -37,7 +56,49 @@ pub struct SessionCostSummary {
 pub struct GlobalCostSummary {
     pub total_input_tokens: i64,
     pub total_output_tokens: i64,
+    pub total_cache_read_tokens: i64,
+    pub total_cache_creation_tokens: i64,
     pub total_cost_usd: f64,
     pub session_count: i64,
     pub per_session: Vec<SessionCostSummary>,
 }
+
+/// A usage rule in effect; see `pty::usage_rules`.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UsageRuleSummary {
+    pub name: String,
+    pub tools: Vec<String>,
+    pub cumulative: bool,
+    /// Whether it comes from the user's rules file rather than the bundled rules.
+    pub user: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UsageRulesStatus {
+    /// Where the user's rules are read from; it need not exist.
+    pub user_file: Option<String>,
+    pub rules: Vec<UsageRuleSummary>,
+    /// Problems with the user's rules file. Invalid rules are left out.
+    pub errors: Vec<String>,
+}
+
+/// Counters from the background worker that persists session output.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PersistenceMetrics {
+    pub queue_capacity: u64,
+    pub queue_depth: u64,
+    pub enqueued_jobs: u64,
+    pub written_jobs: u64,
+    pub batches: u64,
+    /// Jobs skipped because their writes failed; the rest of the batch still commits.
+    pub failed_jobs: u64,
+    pub failed_batches: u64,
+    /// Times a session found the queue full and kept its output buffered.
+    pub queue_full_count: u64,
+    /// Times a session had to wait for room in the queue.
+    pub blocked_sends: u64,
+    pub last_batch_ms: u64,
+}
@@ -0,0 +1,42 @@
+use serde::Serialize;
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScreenSnapshot {
+    pub session_id: String,
+    pub rows: u16,
+    pub cols: u16,
+    /// Visible rows as plain text, trailing blanks trimmed.
+    pub lines: Vec<String>,
+    pub cursor_row: u16,
+    pub cursor_col: u16,
+    pub cursor_visible: bool,
+    pub alternate_screen: bool,
+    pub title: Option<String>,
+    pub cwd: Option<String>,
+    /// Per-cell styling, only filled when requested.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cells: Option<Vec<Vec<ScreenCell>>>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScreenCell {
+    pub text: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fg: Option<CellColor>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bg: Option<CellColor>,
+    pub bold: bool,
+    pub italic: bool,
+    pub underline: bool,
+    pub inverse: bool,
+    pub wide: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum CellColor {
+    Indexed { index: u8 },
+    Rgb { r: u8, g: u8, b: u8 },
+}
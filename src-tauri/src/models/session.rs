@@ -1,8 +1,10 @@
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::path::PathBuf;
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+use crate::models::custom_tool::CustomTool;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum CliTool {
     ClaudeCode,
     Codex,
@@ -12,7 +14,6 @@ pub enum CliTool {
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
-#[allow(dead_code)]
 pub enum SessionStatus {
     Starting,
     Running,
@@ -34,6 +35,97 @@ pub struct SessionConfig {
     pub env_vars: HashMap<String, String>,
     pub cols: u16,
     pub rows: u16,
+    /// How long the session must be silent before it is reported as idle.
+    #[serde(default)]
+    pub idle_threshold_ms: Option<u64>,
+    /// Size of the in-memory scrollback replayed when a view reattaches.
+    #[serde(default)]
+    pub scrollback_bytes: Option<usize>,
+    /// How long output is batched before being sent to the UI; 0 sends every read immediately.
+    #[serde(default)]
+    pub output_coalesce_ms: Option<u64>,
+    /// Batch size at which output is sent regardless of the coalescing interval.
+    #[serde(default)]
+    pub output_coalesce_bytes: Option<usize>,
+    /// Overrides for the terminal variables advertised to the process.
+    #[serde(default)]
+    pub terminal: Option<TerminalProfile>,
+    /// Continue an earlier conversation using the tool's resume arguments.
+    #[serde(default)]
+    pub resume: Option<ResumeTarget>,
+    /// With `CliTool::Custom`, the id of a saved tool definition to launch.
+    #[serde(default)]
+    pub custom_tool_id: Option<String>,
+    /// The definition `custom_tool_id` refers to, loaded by the backend before launch.
+    #[serde(skip)]
+    pub custom_tool: Option<CustomTool>,
+    /// Whether the process is started again when it exits on its own.
+    #[serde(default)]
+    pub restart: Option<RestartPolicy>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RestartMode {
+    #[default]
+    Never,
+    /// Restart after a non-zero exit or a signal.
+    OnFailure,
+    /// Restart after any exit that was not requested with `stop_session`.
+    Always,
+}
+
+/// Unset fields use the defaults from `pty::restart`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RestartPolicy {
+    #[serde(default)]
+    pub mode: RestartMode,
+    /// Restarts allowed before the session is left exited. A run that stays up long
+    /// enough resets the count.
+    #[serde(default)]
+    pub max_retries: Option<u32>,
+    /// Delay before the first restart; each further attempt doubles it.
+    #[serde(default)]
+    pub initial_backoff_ms: Option<u64>,
+    #[serde(default)]
+    pub max_backoff_ms: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "type", content = "id", rename_all = "camelCase")]
+pub enum ResumeTarget {
+    /// The most recent conversation in the working directory.
+    Latest,
+    Conversation(String),
+}
+
+/// What the PTY advertises to the process. Unset fields use the defaults from
+/// `pty::terminal_profile`; `env_vars` still win over anything set here.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TerminalProfile {
+    /// `TERM`, e.g. `xterm-256color`.
+    #[serde(default)]
+    pub term: Option<String>,
+    /// Whether to set `COLORTERM=truecolor`.
+    #[serde(default)]
+    pub truecolor: Option<bool>,
+    /// Locale for `LANG` (and `LC_ALL` when set explicitly), e.g. `en_US.UTF-8`.
+    #[serde(default)]
+    pub locale: Option<String>,
+    /// Further identifying variables set alongside `TERM_PROGRAM`.
+    #[serde(default)]
+    pub extra_env: HashMap<String, String>,
+}
+
+/// The terminal variables a session was started with, after defaults and overrides.
+#[derive(Debug, Clone, Default, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct TerminalEnvironment {
+    pub vars: BTreeMap<String, String>,
+    /// Variables removed from the inherited environment.
+    pub unset: Vec<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -43,6 +135,8 @@ pub enum PtyOutputEvent {
     Data {
         #[serde(rename = "sessionId")]
         session_id: String,
+        /// Sent as base64, a JSON number array is several times the payload size.
+        #[serde(with = "base64_bytes")]
         data: Vec<u8>,
     },
     #[serde(rename = "exited")]
@@ -59,3 +153,101 @@ pub enum PtyOutputEvent {
         message: String,
     },
 }
+
+mod base64_bytes {
+    use base64::engine::general_purpose::STANDARD;
+    use base64::Engine;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&STANDARD.encode(bytes))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        let encoded = String::deserialize(deserializer)?;
+        STANDARD.decode(encoded).map_err(serde::de::Error::custom)
+    }
+}
+
+/// Payload of the `session-activity-change` event.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionActivityEvent {
+    pub session_id: String,
+    pub state: SessionStatus,
+    /// Epoch milliseconds at which the session entered `state`.
+    pub since: i64,
+}
+
+/// Payload of the `session-title-changed` event.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionTitleEvent {
+    pub session_id: String,
+    pub title: Option<String>,
+    pub cwd: Option<String>,
+}
+
+/// Payload of the `session-restarting` event, sent when an exited process is about to be
+/// started again under the same session id.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionRestartEvent {
+    pub session_id: String,
+    /// 1 for the first restart since the session last ran stably.
+    pub attempt: u32,
+    pub exit_code: Option<i32>,
+    pub delay_ms: u64,
+}
+
+/// A problem found by `validate_session_config`. `field` names the `SessionConfig` field
+/// (or `envVars.NAME`) so the dialog can highlight it.
+#[derive(Debug, Clone, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ConfigIssue {
+    pub field: String,
+    pub message: String,
+}
+
+/// Result of pre-flight checks on a `SessionConfig`. Errors block the launch; warnings do not.
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ConfigValidation {
+    pub errors: Vec<ConfigIssue>,
+    pub warnings: Vec<ConfigIssue>,
+    /// Where the command was found, if it was.
+    pub resolved_command: Option<PathBuf>,
+}
+
+impl ConfigValidation {
+    pub fn is_valid(&self) -> bool {
+        self.errors.is_empty()
+    }
+}
+
+/// A known tool found by `discover_tools`.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DiscoveredTool {
+    pub tool: CliTool,
+    /// The command name that was searched for.
+    pub command: String,
+    pub path: Option<PathBuf>,
+    /// Whether `path` is on the session PATH, i.e. the bare command name will resolve.
+    pub on_path: bool,
+    pub version: Option<String>,
+    /// Found, and its version command ran successfully.
+    pub usable: bool,
+    pub error: Option<String>,
+}
+
+/// The login-shell environment merged under every session's `env_vars`.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LoginEnvironment {
+    pub shell: Option<String>,
+    pub vars: HashMap<String, String>,
+    pub captured_at: Option<String>,
+    /// Why the last refresh failed; the previous environment stays in use.
+    pub error: Option<String>,
+}
@@ -9,6 +9,8 @@ pub struct PromptTemplate {
     pub prompt: String,
     pub description: String,
     pub tags: Vec<String>,
+    /// The saved tool a `Custom` template launches.
+    pub custom_tool_id: Option<String>,
     pub created_at: String,
     pub updated_at: String,
 }
@@ -21,6 +23,8 @@ pub struct CreateTemplate {
     pub prompt: String,
     pub description: String,
     pub tags: Vec<String>,
+    #[serde(default)]
+    pub custom_tool_id: Option<String>,
 }
 
 #[derive(Debug, Clone, Deserialize)]
@@ -31,4 +35,6 @@ pub struct UpdateTemplate {
     pub prompt: Option<String>,
     pub description: Option<String>,
     pub tags: Option<Vec<String>>,
+    /// An empty id clears it.
+    pub custom_tool_id: Option<String>,
 }
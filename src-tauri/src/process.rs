@@ -0,0 +1,136 @@
+use std::io::{ErrorKind, Read};
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::sync::{mpsc, Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::error::{AppError, AppResult};
+
+/// How long to keep reading after the process exits. Whatever it wrote is already in the
+/// pipe by then; a pipe still open past this is held by a child it left running (e.g. an
+/// ssh-agent started from a login script).
+const EXIT_DRAIN_TIMEOUT: Duration = Duration::from_millis(200);
+
+pub struct ProcessOutput {
+    pub status: ExitStatus,
+    pub stdout: Vec<u8>,
+    pub stderr: Vec<u8>,
+}
+
+/// Runs `command` to completion with no stdin, killing it and anything it started if it
+/// takes longer than `timeout`.
+pub fn output_with_timeout(command: &mut Command, timeout: Duration) -> AppResult<ProcessOutput> {
+    let program = command.get_program().to_string_lossy().into_owned();
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::CommandExt;
+        command.process_group(0);
+    }
+    let mut child = command
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| AppError::Spawn {
+            command: program.clone(),
+            message: e.to_string(),
+        })?;
+
+    // Read on separate threads so a full pipe cannot stall the child past the timeout
+    let (closed_tx, closed) = mpsc::channel();
+    let stdout = drain(child.stdout.take(), closed_tx.clone());
+    let stderr = drain(child.stderr.take(), closed_tx);
+
+    let deadline = Instant::now() + timeout;
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        if Instant::now() >= deadline {
+            kill_process_group(&mut child);
+            let _ = child.wait();
+            return Err(AppError::Internal(format!(
+                "{program} did not finish within {}s",
+                timeout.as_secs_f32()
+            )));
+        }
+        std::thread::sleep(Duration::from_millis(20));
+    };
+
+    let drain_deadline = deadline.min(Instant::now() + EXIT_DRAIN_TIMEOUT);
+    for _ in 0..2 {
+        if closed.recv_timeout(drain_deadline.saturating_duration_since(Instant::now())).is_err() {
+            break;
+        }
+    }
+
+    let stdout = std::mem::take(&mut *stdout.lock()?);
+    let stderr = std::mem::take(&mut *stderr.lock()?);
+    Ok(ProcessOutput { status, stdout, stderr })
+}
+
+/// Reads `pipe` into the returned buffer on its own thread, signalling `closed` at EOF.
+fn drain(pipe: Option<impl Read + Send + 'static>, closed: mpsc::Sender<()>) -> Arc<Mutex<Vec<u8>>> {
+    let output = Arc::new(Mutex::new(Vec::new()));
+    let buffer = output.clone();
+    std::thread::spawn(move || {
+        if let Some(mut pipe) = pipe {
+            let mut chunk = [0u8; 8192];
+            loop {
+                match pipe.read(&mut chunk) {
+                    Ok(0) => break,
+                    Ok(n) => match buffer.lock() {
+                        Ok(mut buffer) => buffer.extend_from_slice(&chunk[..n]),
+                        Err(_) => break,
+                    },
+                    Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                    Err(_) => break,
+                }
+            }
+        }
+        let _ = closed.send(());
+    });
+    output
+}
+
+/// Kills the child and, on unix, every process still in the group it leads.
+fn kill_process_group(child: &mut Child) {
+    #[cfg(unix)]
+    unsafe {
+        libc::killpg(child.id() as libc::pid_t, libc::SIGKILL);
+    }
+    let _ = child.kill();
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_captures_output_and_enforces_timeout() {
+        let output = output_with_timeout(
+            Command::new("/bin/sh").args(["-c", "echo out; echo err >&2"]),
+            Duration::from_secs(5),
+        )
+        .unwrap();
+        assert!(output.status.success());
+        assert_eq!(output.stdout, b"out\n");
+        assert_eq!(output.stderr, b"err\n");
+
+        let started = Instant::now();
+        let result = output_with_timeout(Command::new("/bin/sh").args(["-c", "sleep 5"]), Duration::from_millis(200));
+        assert!(result.is_err());
+        assert!(started.elapsed() < Duration::from_secs(2));
+    }
+
+    #[test]
+    fn test_does_not_wait_for_children_holding_the_pipe() {
+        let started = Instant::now();
+        let output = output_with_timeout(
+            Command::new("/bin/sh").args(["-c", "sleep 5 & echo out"]),
+            Duration::from_secs(3),
+        )
+        .unwrap();
+        assert_eq!(output.stdout, b"out\n");
+        assert!(started.elapsed() < Duration::from_secs(1));
+    }
+}
@@ -0,0 +1,117 @@
+use std::time::{Duration, Instant};
+
+use crate::models::session::SessionStatus;
+
+pub const DEFAULT_IDLE_THRESHOLD: Duration = Duration::from_secs(2);
+/// How often the manager's monitor polls each session.
+pub const ACTIVITY_POLL_INTERVAL: Duration = Duration::from_secs(1);
+/// Shortest idle threshold a session may use: a shorter one would still only be noticed
+/// at the next poll.
+pub const MIN_IDLE_THRESHOLD: Duration = ACTIVITY_POLL_INTERVAL;
+/// Silence after which a tool showing its input prompt counts as idle, ahead of the threshold.
+pub const PROMPT_SETTLE_TIME: Duration = Duration::from_millis(300);
+
+/// Tracks when a session last produced output and whether it is currently
+/// working or idle. The reader thread records output; the manager's monitor polls.
+pub struct ActivityTracker {
+    last_output_at: Instant,
+    idle: bool,
+    since: i64,
+    idle_threshold: Duration,
+}
+
+impl ActivityTracker {
+    pub fn new(idle_threshold: Duration) -> Self {
+        ActivityTracker {
+            last_output_at: Instant::now(),
+            idle: false,
+            since: chrono::Utc::now().timestamp_millis(),
+            idle_threshold,
+        }
+    }
+
+    pub fn record_output(&mut self) {
+        self.last_output_at = Instant::now();
+    }
+
+    /// Returns the new state and the time it started (epoch millis) if the state flipped since the last poll.
+    /// `awaiting_input` is only consulted once output has settled and the threshold has not yet passed.
+    /// The start is when the flip happened, not when the poll noticed it: the last output for
+    /// Working, and the end of the silence that made the session idle for Idle.
+    pub fn poll(&mut self, now: Instant, awaiting_input: impl FnOnce() -> bool) -> Option<(SessionStatus, i64)> {
+        let silence = now.saturating_duration_since(self.last_output_at);
+        let idle = silence >= self.idle_threshold
+            || (silence >= PROMPT_SETTLE_TIME && (self.idle || awaiting_input()));
+        if idle == self.idle {
+            return None;
+        }
+        self.idle = idle;
+        let flipped_after = match (idle, silence >= self.idle_threshold) {
+            (false, _) => Duration::ZERO,
+            (true, true) => self.idle_threshold,
+            (true, false) => PROMPT_SETTLE_TIME,
+        };
+        let ago = silence.saturating_sub(flipped_after);
+        self.since = chrono::Utc::now().timestamp_millis() - ago.as_millis() as i64;
+        Some((self.state(), self.since))
+    }
+
+    fn state(&self) -> SessionStatus {
+        if self.idle {
+            SessionStatus::Idle
+        } else {
+            SessionStatus::Working
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_flips_to_idle_after_threshold() {
+        let mut tracker = ActivityTracker::new(Duration::from_millis(100));
+        let start = tracker.last_output_at;
+        assert!(tracker.poll(start + Duration::from_millis(50), || false).is_none());
+        let (state, _) = tracker.poll(start + Duration::from_millis(150), || false).unwrap();
+        assert!(matches!(state, SessionStatus::Idle));
+        assert!(tracker.poll(start + Duration::from_millis(200), || false).is_none());
+    }
+
+    #[test]
+    fn test_reports_when_the_flip_happened() {
+        let mut tracker = ActivityTracker::new(Duration::from_secs(2));
+        let start = tracker.last_output_at;
+        // Noticed 3s after the threshold passed
+        let (_, since) = tracker.poll(start + Duration::from_secs(5), || false).unwrap();
+        let lag = chrono::Utc::now().timestamp_millis() - since;
+        assert!((3000..4000).contains(&lag), "{lag}");
+
+        tracker.record_output();
+        let output_at = tracker.last_output_at;
+        let (_, since) = tracker.poll(output_at + Duration::from_millis(200), || false).unwrap();
+        let lag = chrono::Utc::now().timestamp_millis() - since;
+        assert!((200..1200).contains(&lag), "{lag}");
+    }
+
+    #[test]
+    fn test_input_prompt_flips_to_idle_early() {
+        let mut tracker = ActivityTracker::new(Duration::from_secs(10));
+        let start = tracker.last_output_at;
+        assert!(tracker.poll(start + Duration::from_millis(100), || true).is_none());
+        let (state, _) = tracker.poll(start + PROMPT_SETTLE_TIME, || true).unwrap();
+        assert!(matches!(state, SessionStatus::Idle));
+        assert!(tracker.poll(start + Duration::from_secs(1), || false).is_none());
+    }
+
+    #[test]
+    fn test_output_flips_back_to_working() {
+        let mut tracker = ActivityTracker::new(Duration::from_millis(100));
+        let start = tracker.last_output_at;
+        tracker.poll(start + Duration::from_millis(150), || false);
+        tracker.record_output();
+        let (state, _) = tracker.poll(Instant::now(), || false).unwrap();
+        assert!(matches!(state, SessionStatus::Working));
+    }
+}
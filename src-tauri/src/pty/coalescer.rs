@@ -0,0 +1,128 @@
+use std::time::{Duration, Instant};
+
+pub const DEFAULT_COALESCE_INTERVAL: Duration = Duration::from_millis(8);
+pub const DEFAULT_COALESCE_BYTES: usize = 64 * 1024; // 64KB
+
+/// Batches PTY reads so the UI receives one event per interval (or per
+/// `max_bytes`) instead of one per 4KB read. A zero interval disables batching.
+pub struct OutputCoalescer {
+    pending: Vec<u8>,
+    pending_since: Option<Instant>,
+    interval: Duration,
+    max_bytes: usize,
+}
+
+impl OutputCoalescer {
+    pub fn new(interval: Duration, max_bytes: usize) -> Self {
+        OutputCoalescer {
+            pending: Vec::new(),
+            pending_since: None,
+            interval,
+            max_bytes: max_bytes.max(1),
+        }
+    }
+
+    /// Buffers `bytes` and returns a batch if one is due.
+    pub fn push(&mut self, bytes: &[u8], now: Instant) -> Option<Vec<u8>> {
+        if self.pending.is_empty() {
+            self.pending_since = Some(now);
+        }
+        self.pending.extend_from_slice(bytes);
+        let due = self
+            .deadline()
+            .is_some_and(|deadline| now >= deadline);
+        if due || self.pending.len() >= self.max_bytes {
+            self.take()
+        } else {
+            None
+        }
+    }
+
+    /// When the pending batch must be flushed, if anything is pending.
+    pub fn deadline(&self) -> Option<Instant> {
+        self.pending_since.map(|since| since + self.interval)
+    }
+
+    pub fn take(&mut self) -> Option<Vec<u8>> {
+        self.pending_since = None;
+        if self.pending.is_empty() {
+            return None;
+        }
+        Some(std::mem::take(&mut self.pending))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::session::PtyOutputEvent;
+
+    #[test]
+    fn test_flushes_on_size_and_deadline() {
+        let start = Instant::now();
+        let mut coalescer = OutputCoalescer::new(Duration::from_millis(10), 8);
+        assert!(coalescer.push(b"abc", start).is_none());
+        assert_eq!(coalescer.deadline(), Some(start + Duration::from_millis(10)));
+        assert_eq!(coalescer.push(b"defgh", start).unwrap(), b"abcdefgh");
+        assert!(coalescer.deadline().is_none());
+
+        assert!(coalescer.push(b"x", start).is_none());
+        let later = start + Duration::from_millis(10);
+        assert_eq!(coalescer.push(b"y", later).unwrap(), b"xy");
+    }
+
+    #[test]
+    fn test_zero_interval_passes_reads_through() {
+        let mut coalescer = OutputCoalescer::new(Duration::ZERO, DEFAULT_COALESCE_BYTES);
+        assert_eq!(coalescer.push(b"abc", Instant::now()).unwrap(), b"abc");
+    }
+
+    /// Simulates `cat` of a large file arriving as 4KB reads, comparing one JSON
+    /// number-array event per read with coalesced base64 events. Timing-sensitive, so
+    /// run it explicitly with `cargo test --release -- --ignored`.
+    #[test]
+    #[ignore]
+    fn test_benchmark_cat_large_file() {
+        let line = b"2024-01-01T00:00:00Z INFO agent: processed request id=42 status=ok elapsed=13ms\r\n";
+        let file: Vec<u8> = line.iter().copied().cycle().take(4 * 1024 * 1024).collect();
+        let reads: Vec<&[u8]> = file.chunks(4096).collect();
+
+        let started = Instant::now();
+        let mut legacy_bytes = 0;
+        for read in &reads {
+            let numbers: Vec<u64> = read.iter().map(|&b| b as u64).collect();
+            let event = serde_json::json!({ "type": "data", "sessionId": "bench", "data": numbers });
+            legacy_bytes += serde_json::to_vec(&event).unwrap().len();
+        }
+        let legacy_elapsed = started.elapsed();
+
+        // Reads land faster than the interval, so only the size cap triggers flushes
+        let started = Instant::now();
+        let now = Instant::now();
+        let mut coalescer = OutputCoalescer::new(DEFAULT_COALESCE_INTERVAL, DEFAULT_COALESCE_BYTES);
+        let mut events = 0;
+        let mut compact_bytes = 0;
+        let mut batches: Vec<Vec<u8>> = reads.iter().filter_map(|read| coalescer.push(read, now)).collect();
+        batches.extend(coalescer.take());
+        for data in batches {
+            let event = PtyOutputEvent::Data {
+                session_id: "bench".to_string(),
+                data,
+            };
+            compact_bytes += serde_json::to_vec(&event).unwrap().len();
+            events += 1;
+        }
+        let compact_elapsed = started.elapsed();
+
+        assert_eq!(events, file.len().div_ceil(DEFAULT_COALESCE_BYTES));
+        assert!(compact_bytes < file.len() * 4 / 3 + events * 64);
+        assert!(compact_bytes * 2 < legacy_bytes);
+        let mb = file.len() as f64 / (1024.0 * 1024.0);
+        assert!(
+            compact_elapsed < legacy_elapsed,
+            "coalesced base64 at {:.1} MB/s is slower than legacy at {:.1} MB/s",
+            mb / compact_elapsed.as_secs_f64(),
+            mb / legacy_elapsed.as_secs_f64(),
+        );
+    }
+}
@@ -0,0 +1,243 @@
+use crate::models::screen::{CellColor, ScreenCell, ScreenSnapshot};
+
+const MAX_TITLE_LEN: usize = 256;
+
+/// Headless VT100/xterm state machine fed with the same bytes the UI receives,
+/// so the backend can see what an agent is actually showing.
+pub struct TerminalEmulator {
+    parser: vt100::Parser<MetadataCallbacks>,
+}
+
+/// Process-set window title (OSC 0/2) and working directory (OSC 7).
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TerminalMetadata {
+    pub title: Option<String>,
+    pub cwd: Option<String>,
+}
+
+#[derive(Default)]
+struct MetadataCallbacks {
+    metadata: TerminalMetadata,
+    changed: bool,
+}
+
+impl MetadataCallbacks {
+    fn set_title(&mut self, raw: &[u8]) {
+        let title = sanitize(&String::from_utf8_lossy(raw));
+        let title = (!title.is_empty()).then_some(title);
+        if self.metadata.title != title {
+            self.metadata.title = title;
+            self.changed = true;
+        }
+    }
+
+    fn set_cwd(&mut self, raw: &[u8]) {
+        let Some(cwd) = parse_osc7_path(&String::from_utf8_lossy(raw)) else {
+            return;
+        };
+        if self.metadata.cwd.as_deref() != Some(cwd.as_str()) {
+            self.metadata.cwd = Some(cwd);
+            self.changed = true;
+        }
+    }
+}
+
+impl vt100::Callbacks for MetadataCallbacks {
+    fn set_window_title(&mut self, _: &mut vt100::Screen, title: &[u8]) {
+        self.set_title(title);
+    }
+
+    fn unhandled_osc(&mut self, _: &mut vt100::Screen, params: &[&[u8]]) {
+        // vt100 splits OSC payloads on ';', so titles and paths containing one land here
+        match params {
+            [b"0" | b"2", rest @ ..] => self.set_title(&rest.join(&b';')),
+            [b"7", rest @ ..] => self.set_cwd(&rest.join(&b';')),
+            _ => {}
+        }
+    }
+}
+
+impl TerminalEmulator {
+    pub fn new(rows: u16, cols: u16) -> Self {
+        TerminalEmulator {
+            parser: vt100::Parser::new_with_callbacks(rows, cols, 0, MetadataCallbacks::default()),
+        }
+    }
+
+    pub fn metadata(&self) -> &TerminalMetadata {
+        &self.parser.callbacks().metadata
+    }
+
+    /// Returns the current metadata if the title or cwd changed since the last call.
+    pub fn take_metadata_change(&mut self) -> Option<TerminalMetadata> {
+        let callbacks = self.parser.callbacks_mut();
+        if !callbacks.changed {
+            return None;
+        }
+        callbacks.changed = false;
+        Some(callbacks.metadata.clone())
+    }
+
+    pub fn process(&mut self, bytes: &[u8]) {
+        self.parser.process(bytes);
+    }
+
+    /// Returns the cursor as (row, col).
+    pub fn cursor_position(&self) -> (u16, u16) {
+        self.parser.screen().cursor_position()
+    }
+
+    /// Visible text between two (row, col) positions.
+    pub fn text_between(&self, start: (u16, u16), end: (u16, u16)) -> String {
+        self.parser
+            .screen()
+            .contents_between(start.0, start.1, end.0, end.1)
+    }
+
+    /// All visible text, one line per row.
+    pub fn screen_text(&self) -> String {
+        self.parser.screen().contents()
+    }
+
+    /// `(rows, cols)`.
+    pub fn size(&self) -> (u16, u16) {
+        self.parser.screen().size()
+    }
+
+    pub fn resize(&mut self, rows: u16, cols: u16) {
+        self.parser.screen_mut().set_size(rows, cols);
+    }
+
+    pub fn snapshot(&self, session_id: &str, include_cells: bool) -> ScreenSnapshot {
+        let screen = self.parser.screen();
+        let (rows, cols) = screen.size();
+        let (cursor_row, cursor_col) = screen.cursor_position();
+        let metadata = self.metadata();
+        let lines = screen
+            .rows(0, cols)
+            .map(|line| line.trim_end().to_string())
+            .collect();
+        let cells = include_cells.then(|| {
+            (0..rows)
+                .map(|row| {
+                    (0..cols)
+                        .filter_map(|col| screen.cell(row, col))
+                        .filter(|cell| !cell.is_wide_continuation())
+                        .map(|cell| ScreenCell {
+                            text: cell.contents().to_string(),
+                            fg: to_cell_color(cell.fgcolor()),
+                            bg: to_cell_color(cell.bgcolor()),
+                            bold: cell.bold(),
+                            italic: cell.italic(),
+                            underline: cell.underline(),
+                            inverse: cell.inverse(),
+                            wide: cell.is_wide(),
+                        })
+                        .collect()
+                })
+                .collect()
+        });
+
+        ScreenSnapshot {
+            session_id: session_id.to_string(),
+            rows,
+            cols,
+            lines,
+            cursor_row,
+            cursor_col,
+            cursor_visible: !screen.hide_cursor(),
+            alternate_screen: screen.alternate_screen(),
+            title: metadata.title.clone(),
+            cwd: metadata.cwd.clone(),
+            cells,
+        }
+    }
+}
+
+fn to_cell_color(color: vt100::Color) -> Option<CellColor> {
+    match color {
+        vt100::Color::Default => None,
+        vt100::Color::Idx(index) => Some(CellColor::Indexed { index }),
+        vt100::Color::Rgb(r, g, b) => Some(CellColor::Rgb { r, g, b }),
+    }
+}
+
+/// Drops control characters so a hostile title cannot smuggle escape sequences into the UI.
+fn sanitize(text: &str) -> String {
+    text.chars()
+        .filter(|c| !c.is_control())
+        .take(MAX_TITLE_LEN)
+        .collect::<String>()
+        .trim()
+        .to_string()
+}
+
+/// Parses an OSC 7 payload like `file://host/home/me/my%20dir` into a local path.
+fn parse_osc7_path(uri: &str) -> Option<String> {
+    let rest = uri.strip_prefix("file://")?;
+    let path = &rest[rest.find('/')?..];
+    let path = sanitize(&percent_decode(path));
+    (!path.is_empty()).then_some(path)
+}
+
+fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' && i + 2 < bytes.len() {
+            let hex = std::str::from_utf8(&bytes[i + 1..i + 3]).ok();
+            if let Some(byte) = hex.and_then(|hex| u8::from_str_radix(hex, 16).ok()) {
+                decoded.push(byte);
+                i += 3;
+                continue;
+            }
+        }
+        decoded.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_snapshot_tracks_text_and_cursor() {
+        let mut emulator = TerminalEmulator::new(4, 20);
+        emulator.process(b"hello\r\n\x1b[1;31mworld\x1b[0m");
+        let snapshot = emulator.snapshot("s", true);
+        assert_eq!(snapshot.lines[0], "hello");
+        assert_eq!(snapshot.lines[1], "world");
+        assert_eq!((snapshot.cursor_row, snapshot.cursor_col), (1, 5));
+        let cell = &snapshot.cells.unwrap()[1][0];
+        assert!(cell.bold);
+        assert!(matches!(cell.fg, Some(CellColor::Indexed { index: 1 })));
+    }
+
+    #[test]
+    fn test_osc_title_and_cwd() {
+        let mut emulator = TerminalEmulator::new(4, 20);
+        emulator.process(b"\x1b]0;build; deploy\x07\x1b]7;file://host/home/me/my%20dir\x1b\\");
+        let metadata = emulator.take_metadata_change().unwrap();
+        assert_eq!(metadata.title.as_deref(), Some("build; deploy"));
+        assert_eq!(metadata.cwd.as_deref(), Some("/home/me/my dir"));
+        assert!(emulator.take_metadata_change().is_none());
+
+        emulator.process(b"\x1b]2;evil\x1b[31m\x07");
+        let metadata = emulator.take_metadata_change().unwrap();
+        assert_eq!(metadata.title.as_deref(), Some("evil"));
+    }
+
+    #[test]
+    fn test_alternate_screen_and_resize() {
+        let mut emulator = TerminalEmulator::new(4, 20);
+        emulator.process(b"\x1b[?1049h");
+        emulator.resize(10, 40);
+        let snapshot = emulator.snapshot("s", false);
+        assert!(snapshot.alternate_screen);
+        assert_eq!((snapshot.rows, snapshot.cols), (10, 40));
+        assert!(snapshot.cells.is_none());
+    }
+}
@@ -1,72 +1,319 @@
 use std::collections::HashMap;
-use std::sync::{Arc, Mutex};
-use tauri::ipc::Channel;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+use std::time::Duration;
 
-use crate::db::DbPool;
-use crate::models::session::{PtyOutputEvent, SessionConfig};
-use crate::pty::session::PtySession;
+use crate::error::{AppError, AppResult};
+use crate::models::screen::ScreenSnapshot;
+use crate::models::session::{CliTool, SessionConfig, TerminalEnvironment};
+use crate::pty::activity::ACTIVITY_POLL_INTERVAL;
+use crate::pty::persistence::{PersistJob, PersistenceWorker};
+use crate::pty::preflight;
+use crate::pty::session::{ExitHook, PtySession};
+use crate::pty::shell_env::ShellEnvironment;
+use crate::pty::sink::{EventSink, OutputSink, SessionEvent};
+use crate::pty::subscribers::SubscriberId;
+use crate::pty::usage_import::{UsageImporter, IMPORT_POLL_INTERVAL};
+use crate::pty::usage_rules::{UsageRules, RULES_POLL_INTERVAL};
+use crate::tools::{self, discovery::{self, ToolVersions}};
+
+/// Grace period between SIGTERM and SIGKILL when the caller does not specify one.
+pub const DEFAULT_STOP_GRACE_PERIOD: Duration = Duration::from_secs(3);
+
+type Sessions = Mutex<HashMap<String, PtySession>>;
 
 pub struct PtyManager {
-    sessions: Mutex<HashMap<String, PtySession>>,
+    sessions: Arc<Sessions>,
+    next_subscriber_id: AtomicU64,
+    events: Arc<dyn EventSink>,
+    environment: Arc<ShellEnvironment>,
+    tool_versions: Arc<ToolVersions>,
+    usage: Arc<UsageImporter>,
+    usage_rules: Arc<UsageRules>,
 }
 
 impl PtyManager {
-    pub fn new() -> Self {
+    pub fn new(events: Arc<dyn EventSink>) -> Self {
         PtyManager {
-            sessions: Mutex::new(HashMap::new()),
+            sessions: Arc::new(Mutex::new(HashMap::new())),
+            next_subscriber_id: AtomicU64::new(1),
+            events,
+            environment: Arc::default(),
+            tool_versions: Arc::default(),
+            usage: Arc::default(),
+            usage_rules: Arc::default(),
         }
     }
 
+    /// The login-shell environment merged under each session's own variables.
+    pub fn environment(&self) -> &Arc<ShellEnvironment> {
+        &self.environment
+    }
+
+    pub fn tool_versions(&self) -> &Arc<ToolVersions> {
+        &self.tool_versions
+    }
+
+    /// The rules that read usage from session output.
+    pub fn usage_rules(&self) -> &Arc<UsageRules> {
+        &self.usage_rules
+    }
+
+    fn allocate_subscriber_id(&self) -> SubscriberId {
+        self.next_subscriber_id.fetch_add(1, Ordering::Relaxed)
+    }
+
+    /// Starts the background monitor that emits `session-activity-change`
+    /// whenever a session flips between Working and Idle.
+    pub fn start_activity_monitor(&self) {
+        let sessions = Arc::downgrade(&self.sessions);
+        let events = self.events.clone();
+        std::thread::spawn(move || loop {
+            std::thread::sleep(ACTIVITY_POLL_INTERVAL);
+            let Some(sessions) = sessions.upgrade() else {
+                break;
+            };
+            let changes: Vec<_> = match sessions.lock() {
+                Ok(sessions) => sessions.values().filter_map(PtySession::poll_activity).collect(),
+                Err(_) => continue,
+            };
+            for change in changes {
+                events.emit(SessionEvent::ActivityChanged(change));
+            }
+        });
+    }
+
+    /// Starts importing usage from the transcripts of the tools that keep them.
+    pub fn start_usage_import(&self) {
+        self.usage.start(IMPORT_POLL_INTERVAL);
+    }
+
+    /// Loads the user's usage rules from `path` and reloads them whenever the file changes.
+    pub fn start_usage_rules(&self, paths: Vec<PathBuf>) {
+        self.usage_rules.set_user_files(paths);
+        self.usage_rules.start(RULES_POLL_INTERVAL);
+    }
+
     pub fn create_session(
         &self,
         config: &SessionConfig,
-        channel: Channel<PtyOutputEvent>,
-        db: Arc<DbPool>,
-    ) -> Result<String, String> {
-        let session = PtySession::spawn(config, channel, db)?;
+        sink: impl OutputSink,
+        persistence: Arc<PersistenceWorker>,
+    ) -> AppResult<String> {
+        let base_env = self.environment.vars();
+        let validation = preflight::validate_config(config, &base_env);
+        if !validation.is_valid() {
+            let messages: Vec<_> = validation.errors.into_iter().map(|issue| issue.message).collect();
+            return Err(AppError::Validation(messages.join("; ")));
+        }
+        let restarter = Arc::new(Restarter {
+            sessions: Arc::downgrade(&self.sessions),
+            usage: self.usage.clone(),
+            events: self.events.clone(),
+            environment: self.environment.clone(),
+            persistence: persistence.clone(),
+            usage_rules: self.usage_rules.clone(),
+        });
+        let adapter = tools::adapter_for(config)?;
+        let log_offset = persistence.log_end(&config.id)?;
+        // Held from spawn to insert so the exit hook of a process that exits at once finds the session
+        let mut sessions = self.sessions.lock()?;
+        // Replacing a live session would leave its process running with nothing to stop it
+        if sessions.get(&config.id).is_some_and(|session| !session.has_exited()) {
+            return Err(AppError::Conflict(format!("Session {} is already running", config.id)));
+        }
+        let session = PtySession::spawn(
+            config,
+            &base_env,
+            log_offset,
+            persistence.clone(),
+            self.usage_rules.clone(),
+            self.events.clone(),
+            restarter.hook(),
+        )?;
+        session.subscribe(self.allocate_subscriber_id(), sink, true)?;
         let id = session.session_id().to_string();
-        self.sessions
-            .lock()
-            .map_err(|e| format!("Lock error: {e}"))?
-            .insert(id.clone(), session);
+        sessions.insert(id.clone(), session);
+        drop(sessions);
+
+        let mut env = (*base_env).clone();
+        env.extend(adapter.default_env());
+        env.extend(config.env_vars.clone());
+        self.usage.watch(&id, adapter.clone(), &config.working_dir, env, persistence.clone());
+
+        // Custom commands are not probed: running an arbitrary script with --version is not safe
+        if let (Some(path), false) = (validation.resolved_command, matches!(config.tool, CliTool::Custom)) {
+            let session_id = id.clone();
+            discovery::probe_in_background(
+                self.tool_versions.clone(),
+                adapter,
+                path,
+                base_env,
+                move |version| persistence.submit(PersistJob::ToolVersion { session_id, version }),
+            );
+        }
         Ok(id)
     }
 
-    pub fn write_to_session(&self, session_id: &str, data: &[u8]) -> Result<(), String> {
-        let sessions = self
-            .sessions
-            .lock()
-            .map_err(|e| format!("Lock error: {e}"))?;
+    /// Makes `sink` the only subscriber of the session, replaying its scrollback.
+    pub fn attach_session(
+        &self,
+        session_id: &str,
+        sink: impl OutputSink,
+    ) -> AppResult<SubscriberId> {
+        let sessions = self.sessions.lock()?;
         let session = sessions
             .get(session_id)
-            .ok_or_else(|| format!("Session not found: {session_id}"))?;
+            .ok_or_else(|| AppError::not_found("session", session_id))?;
+        let subscriber_id = self.allocate_subscriber_id();
+        session.attach(subscriber_id, sink)?;
+        Ok(subscriber_id)
+    }
+
+    /// Adds `sink` alongside the session's existing subscribers.
+    pub fn subscribe_session(
+        &self,
+        session_id: &str,
+        sink: impl OutputSink,
+        replay: bool,
+    ) -> AppResult<SubscriberId> {
+        let sessions = self.sessions.lock()?;
+        let session = sessions
+            .get(session_id)
+            .ok_or_else(|| AppError::not_found("session", session_id))?;
+        let subscriber_id = self.allocate_subscriber_id();
+        session.subscribe(subscriber_id, sink, replay)?;
+        Ok(subscriber_id)
+    }
+
+    pub fn unsubscribe_session(&self, session_id: &str, subscriber_id: SubscriberId) -> AppResult<()> {
+        let sessions = self.sessions.lock()?;
+        let session = sessions
+            .get(session_id)
+            .ok_or_else(|| AppError::not_found("session", session_id))?;
+        if !session.unsubscribe(subscriber_id)? {
+            return Err(AppError::not_found("subscriber", subscriber_id));
+        }
+        Ok(())
+    }
+
+    pub fn write_to_session(&self, session_id: &str, data: &[u8]) -> AppResult<()> {
+        let sessions = self.sessions.lock()?;
+        let session = sessions
+            .get(session_id)
+            .ok_or_else(|| AppError::not_found("session", session_id))?;
         session.write(data)
     }
 
-    pub fn resize_session(&self, session_id: &str, cols: u16, rows: u16) -> Result<(), String> {
-        let sessions = self
-            .sessions
-            .lock()
-            .map_err(|e| format!("Lock error: {e}"))?;
+    pub fn resize_session(&self, session_id: &str, cols: u16, rows: u16) -> AppResult<()> {
+        let sessions = self.sessions.lock()?;
         let session = sessions
             .get(session_id)
-            .ok_or_else(|| format!("Session not found: {session_id}"))?;
+            .ok_or_else(|| AppError::not_found("session", session_id))?;
         session.resize(cols, rows)
     }
 
-    pub fn remove_session(&self, session_id: &str) -> Result<(), String> {
-        self.sessions
-            .lock()
-            .map_err(|e| format!("Lock error: {e}"))?
+    pub fn screen_snapshot(&self, session_id: &str, include_cells: bool) -> AppResult<ScreenSnapshot> {
+        let sessions = self.sessions.lock()?;
+        let session = sessions
+            .get(session_id)
+            .ok_or_else(|| AppError::not_found("session", session_id))?;
+        session.screen_snapshot(include_cells)
+    }
+
+    pub fn terminal_environment(&self, session_id: &str) -> AppResult<TerminalEnvironment> {
+        let sessions = self.sessions.lock()?;
+        let session = sessions
+            .get(session_id)
+            .ok_or_else(|| AppError::not_found("session", session_id))?;
+        Ok(session.terminal_environment().clone())
+    }
+
+    /// Removes the session and stops its process in the background, cancelling any pending
+    /// restart. Sessions whose process exited stay attachable, with their scrollback and exit
+    /// code, until they are stopped. The exit status is reported to the session's subscribers once the process is gone.
+    pub fn stop_session(&self, session_id: &str, grace_period: Duration) -> AppResult<()> {
+        let session = self
+            .sessions
+            .lock()?
             .remove(session_id);
+        if let Some(session) = session {
+            let usage = self.usage.clone();
+            let session_id = session_id.to_string();
+            std::thread::spawn(move || {
+                let _ = session.stop(grace_period);
+                usage.unwatch(&session_id);
+            });
+        }
         Ok(())
     }
 
-    pub fn list_session_ids(&self) -> Result<Vec<String>, String> {
-        let sessions = self
-            .sessions
-            .lock()
-            .map_err(|e| format!("Lock error: {e}"))?;
+    pub fn list_session_ids(&self) -> AppResult<Vec<String>> {
+        let sessions = self.sessions.lock()?;
         Ok(sessions.keys().cloned().collect())
     }
 }
+
+/// Starts sessions again after their process exited, as their restart policy asks, and stops
+/// importing usage for sessions that exited for good.
+struct Restarter {
+    sessions: Weak<Sessions>,
+    usage: Arc<UsageImporter>,
+    events: Arc<dyn EventSink>,
+    environment: Arc<ShellEnvironment>,
+    persistence: Arc<PersistenceWorker>,
+    usage_rules: Arc<UsageRules>,
+}
+
+impl Restarter {
+    fn hook(self: &Arc<Self>) -> ExitHook {
+        let restarter = self.clone();
+        Arc::new(move |session_id: &str, delay: Option<Duration>| {
+            // Its transcripts are free for the next session in the same directory to claim
+            let Some(delay) = delay else {
+                restarter.usage.unwatch(session_id);
+                return;
+            };
+            let restarter = restarter.clone();
+            let session_id = session_id.to_string();
+            std::thread::spawn(move || {
+                std::thread::sleep(delay);
+                restarter.restart(&session_id);
+            });
+        })
+    }
+
+    /// Replaces the session with a new process unless it was stopped during the backoff.
+    fn restart(self: &Arc<Self>, session_id: &str) {
+        let Some(sessions) = self.sessions.upgrade() else {
+            return;
+        };
+        let base_env = self.environment.vars();
+        // Held while spawning so `stop_session` either cancels the restart or stops the new process
+        let Ok(mut sessions) = sessions.lock() else {
+            return;
+        };
+        let Some(previous) = sessions.get(session_id) else {
+            return;
+        };
+        if !previous.restart_pending() {
+            return;
+        }
+        match previous.respawn(
+            &base_env,
+            self.persistence.clone(),
+            self.usage_rules.clone(),
+            self.events.clone(),
+            self.hook(),
+        ) {
+            Ok(session) => {
+                sessions.insert(session_id.to_string(), session);
+            }
+            Err(e) => {
+                let _ = previous.finish_pending_restart(Some(format!("Restart failed: {e}")));
+                self.usage.unwatch(session_id);
+            }
+        }
+    }
+}
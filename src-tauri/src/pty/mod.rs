@@ -1,5 +1,19 @@
+pub mod activity;
+pub mod coalescer;
+pub mod emulator;
 pub mod manager;
+pub mod persistence;
+pub mod preflight;
+pub mod restart;
+pub mod scrollback;
 pub mod session;
+pub mod shell_env;
+pub mod shell_integration;
+pub mod sink;
+pub mod subscribers;
+pub mod terminal_profile;
 pub mod token_parser;
+pub mod usage_import;
+pub mod usage_rules;
 
 pub use manager::PtyManager;
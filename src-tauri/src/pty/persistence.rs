@@ -0,0 +1,429 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, SyncSender, TrySendError};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use rusqlite::Connection;
+
+use crate::db::{history_repo, monitoring_repo, session_repo, DbPool};
+use crate::error::{AppError, AppResult};
+use crate::models::history::{NewSessionCommand, NewTranscriptMessage};
+use crate::models::monitoring::{PersistenceMetrics, RecordTokenUsage};
+
+pub const DEFAULT_QUEUE_CAPACITY: usize = 1024;
+/// How long looking up where a session's log ends waits for queued output to be written.
+const LOG_END_SYNC_TIMEOUT: Duration = Duration::from_secs(2);
+/// Upper bound on jobs written in a single transaction.
+const MAX_BATCH_JOBS: usize = 256;
+
+/// Database work produced by PTY sessions.
+pub enum PersistJob {
+    /// A chunk of session output plus the commands that finished within it.
+    Output {
+        session_id: String,
+        content: Vec<u8>,
+        /// Usage the session's tool reported within `content`.
+        usage: Vec<RecordTokenUsage>,
+        stream_offset: u64,
+        commands: Vec<NewSessionCommand>,
+    },
+    /// Usage and conversation entries imported from a tool's transcript files.
+    Transcript {
+        usage: Vec<RecordTokenUsage>,
+        messages: Vec<NewTranscriptMessage>,
+    },
+    Metadata {
+        session_id: String,
+        title: Option<String>,
+        cwd: Option<String>,
+    },
+    Status {
+        session_id: String,
+        status: String,
+    },
+    ToolVersion {
+        session_id: String,
+        version: String,
+    },
+    Restart {
+        session_id: String,
+        attempt: u32,
+        exit_code: Option<i32>,
+    },
+    /// Acknowledged once every job queued before it has been written.
+    Barrier(mpsc::Sender<()>),
+}
+
+#[derive(Default)]
+struct Counters {
+    enqueued: AtomicU64,
+    written: AtomicU64,
+    batches: AtomicU64,
+    failed_jobs: AtomicU64,
+    failed_batches: AtomicU64,
+    queue_full: AtomicU64,
+    blocked_sends: AtomicU64,
+    last_batch_ms: AtomicU64,
+}
+
+/// Single writer thread that persists output from every session. Sessions submit over a
+/// bounded queue; the worker drains whatever is waiting and writes it in one transaction.
+///
+/// When the queue is full, [`try_submit`](Self::try_submit) hands the job back so the
+/// session can keep buffering, and [`submit`](Self::submit) blocks until there is room.
+/// Both cases are counted in the metrics.
+pub struct PersistenceWorker {
+    sender: SyncSender<PersistJob>,
+    db: Arc<DbPool>,
+    counters: Arc<Counters>,
+    capacity: usize,
+}
+
+impl PersistenceWorker {
+    pub fn start(db: Arc<DbPool>, capacity: usize) -> Self {
+        let capacity = capacity.max(1);
+        let (sender, receiver) = mpsc::sync_channel(capacity);
+        let counters = Arc::new(Counters::default());
+        let worker_counters = counters.clone();
+        let worker_db = db.clone();
+        std::thread::spawn(move || run(receiver, &worker_db, &worker_counters));
+        PersistenceWorker {
+            sender,
+            db,
+            counters,
+            capacity,
+        }
+    }
+
+    /// Queues `job` without blocking. Returns it back if the queue is full.
+    pub fn try_submit(&self, job: PersistJob) -> Result<(), PersistJob> {
+        match self.sender.try_send(job) {
+            Ok(()) => {
+                self.counters.enqueued.fetch_add(1, Ordering::Relaxed);
+                Ok(())
+            }
+            Err(TrySendError::Full(job)) => {
+                self.counters.queue_full.fetch_add(1, Ordering::Relaxed);
+                Err(job)
+            }
+            // The worker only stops when the process is shutting down
+            Err(TrySendError::Disconnected(_)) => Ok(()),
+        }
+    }
+
+    /// Queues `job`, waiting for room if the queue is full.
+    pub fn submit(&self, job: PersistJob) {
+        let job = match self.sender.try_send(job) {
+            Ok(()) => {
+                self.counters.enqueued.fetch_add(1, Ordering::Relaxed);
+                return;
+            }
+            Err(TrySendError::Full(job)) => job,
+            Err(TrySendError::Disconnected(_)) => return,
+        };
+        self.counters.blocked_sends.fetch_add(1, Ordering::Relaxed);
+        if self.sender.send(job).is_ok() {
+            self.counters.enqueued.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Waits until everything queued so far has been written. Returns false on timeout.
+    pub fn sync(&self, timeout: Duration) -> bool {
+        let (ack_tx, ack_rx) = mpsc::channel();
+        self.submit(PersistJob::Barrier(ack_tx));
+        ack_rx.recv_timeout(timeout).is_ok()
+    }
+
+    /// Where the session's stored output stream ends, once output queued by an earlier
+    /// process under the same id has been written.
+    pub fn log_end(&self, session_id: &str) -> AppResult<u64> {
+        self.sync(LOG_END_SYNC_TIMEOUT);
+        self.db.with_read_conn(|conn| history_repo::get_log_end(conn, session_id))
+    }
+
+    pub fn metrics(&self) -> PersistenceMetrics {
+        let enqueued = self.counters.enqueued.load(Ordering::Relaxed);
+        let written = self.counters.written.load(Ordering::Relaxed);
+        PersistenceMetrics {
+            queue_capacity: self.capacity as u64,
+            queue_depth: enqueued.saturating_sub(written),
+            enqueued_jobs: enqueued,
+            written_jobs: written,
+            batches: self.counters.batches.load(Ordering::Relaxed),
+            failed_jobs: self.counters.failed_jobs.load(Ordering::Relaxed),
+            failed_batches: self.counters.failed_batches.load(Ordering::Relaxed),
+            queue_full_count: self.counters.queue_full.load(Ordering::Relaxed),
+            blocked_sends: self.counters.blocked_sends.load(Ordering::Relaxed),
+            last_batch_ms: self.counters.last_batch_ms.load(Ordering::Relaxed),
+        }
+    }
+}
+
+fn run(receiver: Receiver<PersistJob>, db: &DbPool, counters: &Counters) {
+    while let Ok(first) = receiver.recv() {
+        let mut batch = vec![first];
+        while batch.len() < MAX_BATCH_JOBS {
+            match receiver.try_recv() {
+                Ok(job) => batch.push(job),
+                Err(_) => break,
+            }
+        }
+
+        let started = Instant::now();
+        let (jobs, barriers): (Vec<_>, Vec<_>) = batch
+            .into_iter()
+            .partition(|job| !matches!(job, PersistJob::Barrier(_)));
+        if !jobs.is_empty() {
+            let result = db.with_conn(|conn| {
+                let mut tx = conn.unchecked_transaction()?;
+                for job in &jobs {
+                    // A failing job (e.g. a session row that was deleted) only rolls back itself
+                    let savepoint = tx.savepoint()?;
+                    match write_job(&savepoint, job) {
+                        Ok(()) => savepoint.commit()?,
+                        Err(_) => {
+                            counters.failed_jobs.fetch_add(1, Ordering::Relaxed);
+                        }
+                    }
+                }
+                tx.commit()
+            });
+            if result.is_err() {
+                counters.failed_batches.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        counters.batches.fetch_add(1, Ordering::Relaxed);
+        counters
+            .last_batch_ms
+            .store(started.elapsed().as_millis() as u64, Ordering::Relaxed);
+        counters
+            .written
+            .fetch_add((jobs.len() + barriers.len()) as u64, Ordering::Relaxed);
+
+        for barrier in barriers {
+            if let PersistJob::Barrier(ack) = barrier {
+                let _ = ack.send(());
+            }
+        }
+    }
+}
+
+fn write_job(conn: &Connection, job: &PersistJob) -> AppResult<()> {
+    match job {
+        PersistJob::Output {
+            session_id,
+            content,
+            usage,
+            stream_offset,
+            commands,
+        } => {
+            // Output can arrive before the frontend saves the session row; without the row
+            // there is nothing for it to belong to
+            if !session_repo::session_exists(conn, session_id)? {
+                return Ok(());
+            }
+            for record in usage {
+                monitoring_repo::record_usage(conn, record)?;
+            }
+            if !content.is_empty() {
+                history_repo::insert_log(conn, session_id, content, Some(*stream_offset as i64))?;
+            }
+            for command in commands {
+                history_repo::insert_command(conn, command)?;
+            }
+            Ok(())
+        }
+        PersistJob::Transcript { usage, messages } => {
+            // Transcripts can name sessions that were never saved; their entries are skipped
+            let mut saved = HashMap::new();
+            let mut is_saved = |session_id: &str| -> AppResult<bool> {
+                if let Some(&exists) = saved.get(session_id) {
+                    return Ok(exists);
+                }
+                let exists = session_repo::session_exists(conn, session_id)?;
+                saved.insert(session_id.to_string(), exists);
+                Ok(exists)
+            };
+            for record in usage {
+                if is_saved(&record.session_id)? {
+                    monitoring_repo::record_usage(conn, record)?;
+                }
+            }
+            for message in messages {
+                if is_saved(&message.session_id)? {
+                    history_repo::insert_transcript_message(conn, message)?;
+                }
+            }
+            Ok(())
+        }
+        PersistJob::Metadata {
+            session_id,
+            title,
+            cwd,
+        } => session_repo::update_session_metadata(conn, session_id, title.as_deref(), cwd.as_deref()),
+        // Sessions that are not saved have no row to update
+        PersistJob::Status { session_id, status } => {
+            match session_repo::update_session_status(conn, session_id, status) {
+                Err(AppError::NotFound { .. }) => Ok(()),
+                result => result,
+            }
+        }
+        PersistJob::ToolVersion { session_id, version } => {
+            session_repo::update_tool_version(conn, session_id, version)
+        }
+        PersistJob::Restart {
+            session_id,
+            attempt,
+            exit_code,
+        } => session_repo::record_restart(conn, session_id, *attempt, *exit_code),
+        PersistJob::Barrier(_) => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::migrations;
+
+    fn test_db(name: &str) -> Arc<DbPool> {
+        let dir = std::env::temp_dir().join(format!("agentdesk-persistence-{}-{name}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let db = Arc::new(DbPool::new(&dir).unwrap());
+        db.with_conn(migrations::run_migrations).unwrap();
+        db.with_conn(|conn| {
+            conn.execute(
+                "INSERT INTO sessions (id, name, tool, command, working_dir) VALUES ('s', 's', 'Custom', 'sh', '')",
+                [],
+            )
+        })
+        .unwrap();
+        db
+    }
+
+    fn output(content: &str, stream_offset: u64) -> PersistJob {
+        PersistJob::Output {
+            session_id: "s".to_string(),
+            content: content.as_bytes().to_vec(),
+            usage: Vec::new(),
+            stream_offset,
+            commands: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_writes_queued_output() {
+        let db = test_db("writes");
+        let worker = PersistenceWorker::start(db.clone(), 16);
+        worker.submit(output("hello ", 0));
+        worker.submit(PersistJob::Output {
+            session_id: "s".to_string(),
+            content: b"Total cost: $0.50\n".to_vec(),
+            usage: vec![RecordTokenUsage {
+                session_id: "s".to_string(),
+                input_tokens: 0,
+                output_tokens: 0,
+                model: "claude".to_string(),
+                cost_usd: 0.5,
+                ..Default::default()
+            }],
+            stream_offset: 6,
+            commands: Vec::new(),
+        });
+        assert!(worker.sync(Duration::from_secs(5)));
+
+        let log = db.with_conn(|conn| history_repo::get_session_log(conn, "s")).unwrap();
+        assert!(log.contains("hello") && log.contains("Total cost"));
+        let usage = db.with_conn(|conn| monitoring_repo::get_session_usage(conn, "s")).unwrap();
+        assert_eq!(usage.len(), 1);
+
+        let metrics = worker.metrics();
+        assert_eq!(metrics.written_jobs, 3);
+        assert_eq!(metrics.queue_depth, 0);
+    }
+
+    #[test]
+    fn test_skips_jobs_for_unsaved_sessions() {
+        let db = test_db("unsaved");
+        let worker = PersistenceWorker::start(db.clone(), 16);
+        worker.submit(PersistJob::Status {
+            session_id: "unsaved".to_string(),
+            status: "running".to_string(),
+        });
+        worker.submit(PersistJob::Restart {
+            session_id: "unsaved".to_string(),
+            attempt: 1,
+            exit_code: Some(1),
+        });
+        let usage = |session_id: &str| RecordTokenUsage {
+            session_id: session_id.to_string(),
+            input_tokens: 10,
+            model: "gpt".to_string(),
+            cumulative: true,
+            ..Default::default()
+        };
+        let message = |session_id: &str| NewTranscriptMessage {
+            session_id: session_id.to_string(),
+            entry_id: format!("{session_id}:1"),
+            role: "user".to_string(),
+            kind: "message".to_string(),
+            name: None,
+            content: "hi".to_string(),
+            timestamp: None,
+        };
+        worker.submit(PersistJob::Transcript {
+            usage: vec![usage("unsaved"), usage("s")],
+            messages: vec![message("unsaved"), message("s")],
+        });
+        worker.submit(PersistJob::Output {
+            session_id: "unsaved".to_string(),
+            content: b"early output".to_vec(),
+            usage: vec![RecordTokenUsage { cumulative: false, ..usage("unsaved") }],
+            stream_offset: 0,
+            commands: vec![NewSessionCommand {
+                session_id: "unsaved".to_string(),
+                command: "ls".to_string(),
+                exit_code: Some(0),
+                output_start: 0,
+                output_end: 5,
+                started_at: String::new(),
+                ended_at: String::new(),
+                duration_ms: 0,
+            }],
+        });
+        assert!(worker.sync(Duration::from_secs(5)));
+
+        assert_eq!(worker.metrics().failed_jobs, 0);
+        let usage = db.with_conn(|conn| monitoring_repo::get_session_usage(conn, "s")).unwrap();
+        assert_eq!(usage.len(), 1);
+        let messages = db.with_conn(|conn| history_repo::list_transcript_messages(conn, "s")).unwrap();
+        assert_eq!(messages.len(), 1);
+    }
+
+    #[test]
+    fn test_full_queue_hands_job_back() {
+        let db = test_db("full");
+        let worker = PersistenceWorker::start(db.clone(), 1);
+
+        // Hold the connection so the worker stalls on its first batch
+        let rejected = db
+            .with_conn(|_| -> AppResult<_> {
+                let deadline = Instant::now() + Duration::from_secs(5);
+                let mut offset = 0;
+                while Instant::now() < deadline {
+                    if let Err(job) = worker.try_submit(output("x", offset)) {
+                        return Ok(Some(job));
+                    }
+                    offset += 1;
+                }
+                Ok(None)
+            })
+            .unwrap();
+        assert!(matches!(rejected, Some(PersistJob::Output { .. })));
+
+        assert!(worker.sync(Duration::from_secs(5)));
+        let metrics = worker.metrics();
+        assert_eq!(metrics.queue_full_count, 1);
+        assert_eq!(metrics.failed_batches, 0);
+    }
+}
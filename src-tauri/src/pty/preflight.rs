@@ -0,0 +1,364 @@
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+
+use crate::models::session::{CliTool, ConfigIssue, ConfigValidation, SessionConfig};
+use crate::pty::activity::MIN_IDLE_THRESHOLD;
+use crate::tools;
+
+/// Largest terminal dimension accepted; anything bigger is a unit mix-up (pixels, not cells).
+const MAX_DIMENSION: u16 = 1000;
+const MIN_COLS: u16 = 20;
+const MIN_ROWS: u16 = 5;
+
+/// Checks a session config before launch: the command resolves, the working directory is
+/// usable, env var names are valid and the terminal size and idle threshold are sane.
+/// `base_env` is the environment the session's own variables are merged over.
+pub fn validate_config(config: &SessionConfig, base_env: &HashMap<String, String>) -> ConfigValidation {
+    let mut validation = ConfigValidation::default();
+    check_working_dir(config, &mut validation);
+    check_command(config, base_env, &mut validation);
+    check_env_vars(config, &mut validation);
+    check_size(config, &mut validation);
+    check_idle_threshold(config, &mut validation);
+    validation
+}
+
+fn error(validation: &mut ConfigValidation, field: &str, message: String) {
+    validation.errors.push(ConfigIssue {
+        field: field.to_string(),
+        message,
+    });
+}
+
+fn warning(validation: &mut ConfigValidation, field: &str, message: String) {
+    validation.warnings.push(ConfigIssue {
+        field: field.to_string(),
+        message,
+    });
+}
+
+fn check_working_dir(config: &SessionConfig, validation: &mut ConfigValidation) {
+    let dir = &config.working_dir;
+    if dir.as_os_str().is_empty() {
+        warning(
+            validation,
+            "workingDir",
+            "No working directory set; the session starts in the app's directory".to_string(),
+        );
+        return;
+    }
+    match std::fs::metadata(dir) {
+        Err(_) => error(
+            validation,
+            "workingDir",
+            format!("Working directory does not exist: {}", dir.display()),
+        ),
+        Ok(metadata) if !metadata.is_dir() => error(
+            validation,
+            "workingDir",
+            format!("Working directory is not a directory: {}", dir.display()),
+        ),
+        Ok(metadata) => {
+            if !is_writable(dir, &metadata) {
+                warning(
+                    validation,
+                    "workingDir",
+                    format!("Working directory is not writable: {}", dir.display()),
+                );
+            }
+        }
+    }
+}
+
+fn check_command(config: &SessionConfig, base_env: &HashMap<String, String>, validation: &mut ConfigValidation) {
+    if let Some(id) = &config.custom_tool_id {
+        if config.tool != CliTool::Custom {
+            error(validation, "customToolId", format!("{:?} sessions cannot use a custom tool", config.tool));
+            return;
+        }
+        if config.custom_tool.is_none() {
+            error(validation, "customToolId", format!("Custom tool `{id}` does not exist"));
+            return;
+        }
+    }
+    let adapter = match tools::adapter_for(config) {
+        Ok(adapter) => adapter,
+        Err(e) => {
+            error(validation, "customToolId", e.to_string());
+            return;
+        }
+    };
+    let launch = match tools::launch_command(adapter.as_ref(), config) {
+        Ok(launch) => launch,
+        Err(e) => {
+            error(validation, "resume", e.to_string());
+            return;
+        }
+    };
+    let command = launch.command.trim();
+    if command.is_empty() {
+        error(validation, "command", "Command is empty".to_string());
+        return;
+    }
+    let tool_env = adapter.default_env();
+    let path = config
+        .env_vars
+        .get("PATH")
+        .or_else(|| tool_env.get("PATH"))
+        .or_else(|| base_env.get("PATH"))
+        .map(OsString::from)
+        .or_else(|| std::env::var_os("PATH"));
+    match resolve_command(command, &config.working_dir, path.as_deref()) {
+        Some(resolved) => validation.resolved_command = Some(resolved),
+        None if has_separator(command) => error(
+            validation,
+            "command",
+            format!("`{command}` does not exist or is not executable"),
+        ),
+        None => error(
+            validation,
+            "command",
+            format!("`{command}` was not found on the session's PATH"),
+        ),
+    }
+}
+
+fn check_env_vars(config: &SessionConfig, validation: &mut ConfigValidation) {
+    let mut names: Vec<_> = config.env_vars.keys().collect();
+    names.sort();
+    for name in names {
+        let field = format!("envVars.{name}");
+        if name.is_empty() {
+            error(validation, "envVars", "Environment variable name is empty".to_string());
+        } else if name.contains('=') || name.contains('\0') {
+            error(
+                validation,
+                &field,
+                format!("Environment variable name `{name}` may not contain `=` or NUL"),
+            );
+        } else if !is_portable_name(name) {
+            warning(
+                validation,
+                &field,
+                format!("`{name}` is not a portable environment variable name; some shells will ignore it"),
+            );
+        }
+        if config.env_vars[name].contains('\0') {
+            error(
+                validation,
+                &field,
+                format!("Value of `{name}` may not contain NUL"),
+            );
+        }
+    }
+}
+
+fn check_size(config: &SessionConfig, validation: &mut ConfigValidation) {
+    for (field, value, min) in [("cols", config.cols, MIN_COLS), ("rows", config.rows, MIN_ROWS)] {
+        if value == 0 || value > MAX_DIMENSION {
+            error(
+                validation,
+                field,
+                format!("{field} must be between 1 and {MAX_DIMENSION}, got {value}"),
+            );
+        } else if value < min {
+            warning(
+                validation,
+                field,
+                format!("{field} is {value}; most terminal apps need at least {min}"),
+            );
+        }
+    }
+}
+
+fn check_idle_threshold(config: &SessionConfig, validation: &mut ConfigValidation) {
+    let min = MIN_IDLE_THRESHOLD.as_millis() as u64;
+    if let Some(value) = config.idle_threshold_ms.filter(|&ms| ms < min) {
+        error(
+            validation,
+            "idleThresholdMs",
+            format!("idleThresholdMs must be at least {min} (activity is polled every {min} ms), got {value}"),
+        );
+    }
+}
+
+/// Finds the executable `command` would run. Commands containing a path separator are
+/// resolved against `working_dir`; bare names are looked up on `path`.
+pub fn resolve_command(command: &str, working_dir: &Path, path: Option<&std::ffi::OsStr>) -> Option<PathBuf> {
+    if has_separator(command) {
+        let candidate = working_dir.join(command);
+        return executable_candidates(&candidate).into_iter().find(|c| is_executable(c));
+    }
+    std::env::split_paths(path?)
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .flat_map(|dir| executable_candidates(&dir.join(command)))
+        .find(|candidate| is_executable(candidate))
+}
+
+fn has_separator(command: &str) -> bool {
+    command.contains('/') || (cfg!(windows) && command.contains('\\'))
+}
+
+fn is_portable_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+#[cfg(windows)]
+fn executable_candidates(path: &Path) -> Vec<PathBuf> {
+    if path.extension().is_some() {
+        return vec![path.to_path_buf()];
+    }
+    let extensions = std::env::var("PATHEXT").unwrap_or_else(|_| ".COM;.EXE;.BAT;.CMD".to_string());
+    extensions
+        .split(';')
+        .filter(|ext| !ext.is_empty())
+        .map(|ext| {
+            let mut candidate = path.as_os_str().to_owned();
+            candidate.push(ext);
+            PathBuf::from(candidate)
+        })
+        .collect()
+}
+
+#[cfg(not(windows))]
+fn executable_candidates(path: &Path) -> Vec<PathBuf> {
+    vec![path.to_path_buf()]
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    std::fs::metadata(path).is_ok_and(|m| m.is_file() && m.permissions().mode() & 0o111 != 0)
+}
+
+#[cfg(not(unix))]
+fn is_executable(path: &Path) -> bool {
+    path.is_file()
+}
+
+#[cfg(unix)]
+fn is_writable(dir: &Path, _metadata: &std::fs::Metadata) -> bool {
+    use std::os::unix::ffi::OsStrExt;
+    let Ok(path) = std::ffi::CString::new(dir.as_os_str().as_bytes()) else {
+        return false;
+    };
+    // SAFETY: `path` is a valid NUL-terminated string for the duration of the call
+    unsafe { libc::access(path.as_ptr(), libc::W_OK) == 0 }
+}
+
+#[cfg(not(unix))]
+fn is_writable(_dir: &Path, metadata: &std::fs::Metadata) -> bool {
+    !metadata.permissions().readonly()
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    fn config(command: &str, working_dir: &str) -> SessionConfig {
+        serde_json::from_value(serde_json::json!({
+            "id": "s",
+            "name": "s",
+            "tool": "Custom",
+            "command": command,
+            "args": [],
+            "workingDir": working_dir,
+            "envVars": {},
+            "cols": 80,
+            "rows": 24,
+        }))
+        .unwrap()
+    }
+
+    fn validate(config: &SessionConfig) -> ConfigValidation {
+        validate_config(config, &HashMap::new())
+    }
+
+    fn fields(issues: &[ConfigIssue]) -> Vec<&str> {
+        issues.iter().map(|issue| issue.field.as_str()).collect()
+    }
+
+    #[test]
+    fn test_valid_config() {
+        let validation = validate(&config("sh", "/tmp"));
+        assert!(validation.is_valid(), "{:?}", validation.errors);
+        assert!(validation.warnings.is_empty());
+        assert!(validation.resolved_command.is_some());
+    }
+
+    #[test]
+    fn test_reports_missing_command_and_directory() {
+        let validation = validate(&config("agentdesk-no-such-tool", "/no/such/dir"));
+        assert_eq!(fields(&validation.errors), vec!["workingDir", "command"]);
+
+        let validation = validate(&config("./missing.sh", "/tmp"));
+        assert!(validation.errors[0].message.contains("does not exist"));
+    }
+
+    #[test]
+    fn test_uses_the_session_path() {
+        let mut config = config("sh", "/tmp");
+        config.env_vars.insert("PATH".to_string(), "/no/such/dir".to_string());
+        let validation = validate(&config);
+        assert_eq!(fields(&validation.errors), vec!["command"]);
+
+        // The session's own PATH wins over the login environment's
+        let base_env = HashMap::from([("PATH".to_string(), "/bin:/usr/bin".to_string())]);
+        assert_eq!(fields(&validate_config(&config, &base_env).errors), vec!["command"]);
+        config.env_vars.remove("PATH");
+        assert!(validate_config(&config, &base_env).is_valid());
+    }
+
+    #[test]
+    fn test_checks_env_names_and_size() {
+        let mut config = config("sh", "/tmp");
+        config.env_vars.insert("A=B".to_string(), "x".to_string());
+        config.env_vars.insert("my-var".to_string(), "x".to_string());
+        config.cols = 0;
+        config.rows = 3;
+        let validation = validate(&config);
+        assert_eq!(fields(&validation.errors), vec!["envVars.A=B", "cols"]);
+        assert_eq!(fields(&validation.warnings), vec!["envVars.my-var", "rows"]);
+    }
+
+    #[test]
+    fn test_rejects_idle_threshold_below_poll_interval() {
+        let mut config = config("sh", "/tmp");
+        config.idle_threshold_ms = Some(500);
+        assert_eq!(fields(&validate(&config).errors), vec!["idleThresholdMs"]);
+        config.idle_threshold_ms = Some(1000);
+        assert!(validate(&config).errors.is_empty());
+    }
+
+    #[test]
+    fn test_resolves_custom_tool_definitions() {
+        let mut config = config("", "/tmp");
+        config.custom_tool_id = Some("gone".to_string());
+        assert_eq!(fields(&validate(&config).errors), vec!["customToolId"]);
+
+        config.custom_tool = Some(
+            serde_json::from_value(serde_json::json!({
+                "id": "gone",
+                "name": "shell",
+                "command": "sh",
+                "args": [],
+                "envVars": { "PATH": "/no/such/dir" },
+                "iconLabel": "",
+                "idleRegex": null,
+                "usageRegex": null,
+                "createdAt": "",
+                "updatedAt": "",
+            }))
+            .unwrap(),
+        );
+        // The tool's own PATH is searched, and the session's wins over it
+        assert_eq!(fields(&validate(&config).errors), vec!["command"]);
+        config.env_vars.insert("PATH".to_string(), "/bin:/usr/bin".to_string());
+        assert!(validate(&config).is_valid());
+    }
+}
@@ -0,0 +1,106 @@
+use std::time::Duration;
+
+use crate::models::session::{RestartMode, RestartPolicy};
+
+pub const DEFAULT_MAX_RETRIES: u32 = 5;
+pub const DEFAULT_INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+pub const DEFAULT_MAX_BACKOFF: Duration = Duration::from_secs(60);
+/// A process that ran at least this long is considered healthy again, so its crash starts
+/// a fresh series of retries instead of using up the old one.
+pub const STABLE_RUN: Duration = Duration::from_secs(300);
+
+/// A restart the policy allows.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PlannedRestart {
+    pub attempt: u32,
+    pub delay: Duration,
+}
+
+/// Counts the restarts of one session across the processes it goes through.
+#[derive(Debug, Default)]
+pub struct RestartTracker {
+    policy: RestartPolicy,
+    attempts: u32,
+}
+
+impl RestartTracker {
+    pub fn new(policy: RestartPolicy) -> Self {
+        RestartTracker { policy, attempts: 0 }
+    }
+
+    /// Decides what happens after the process exited on its own with `exit_code` (`None` if
+    /// it could not be waited on) having run for `ran_for`. Returns the restart to make, or
+    /// `None` if the session stays exited.
+    pub fn next(&mut self, exit_code: Option<i32>, ran_for: Duration) -> Option<PlannedRestart> {
+        let restart = match self.policy.mode {
+            RestartMode::Never => false,
+            RestartMode::OnFailure => exit_code != Some(0),
+            RestartMode::Always => true,
+        };
+        if !restart {
+            return None;
+        }
+        if ran_for >= STABLE_RUN {
+            self.attempts = 0;
+        }
+        if self.attempts >= self.policy.max_retries.unwrap_or(DEFAULT_MAX_RETRIES) {
+            return None;
+        }
+        let initial = self
+            .policy
+            .initial_backoff_ms
+            .map(Duration::from_millis)
+            .unwrap_or(DEFAULT_INITIAL_BACKOFF);
+        let max = self
+            .policy
+            .max_backoff_ms
+            .map(Duration::from_millis)
+            .unwrap_or(DEFAULT_MAX_BACKOFF);
+        let delay = initial
+            .saturating_mul(2u32.saturating_pow(self.attempts))
+            .min(max);
+        self.attempts += 1;
+        Some(PlannedRestart {
+            attempt: self.attempts,
+            delay,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tracker(mode: RestartMode, max_retries: u32) -> RestartTracker {
+        RestartTracker::new(RestartPolicy {
+            mode,
+            max_retries: Some(max_retries),
+            initial_backoff_ms: Some(100),
+            max_backoff_ms: Some(350),
+        })
+    }
+
+    #[test]
+    fn test_modes() {
+        let quick = Duration::from_millis(10);
+        assert_eq!(tracker(RestartMode::Never, 5).next(Some(1), quick), None);
+        assert_eq!(tracker(RestartMode::OnFailure, 5).next(Some(0), quick), None);
+        assert!(tracker(RestartMode::OnFailure, 5).next(None, quick).is_some());
+        assert!(tracker(RestartMode::Always, 5).next(Some(0), quick).is_some());
+        assert_eq!(RestartTracker::default().next(Some(1), quick), None);
+    }
+
+    #[test]
+    fn test_backoff_and_retry_limit() {
+        let quick = Duration::from_millis(10);
+        let mut tracker = tracker(RestartMode::OnFailure, 4);
+        let delays: Vec<_> = std::iter::from_fn(|| tracker.next(Some(1), quick))
+            .map(|restart| (restart.attempt, restart.delay.as_millis()))
+            .collect();
+        assert_eq!(delays, vec![(1, 100), (2, 200), (3, 350), (4, 350)]);
+
+        // A stable run starts over
+        let restart = tracker.next(Some(1), STABLE_RUN).unwrap();
+        assert_eq!((restart.attempt, restart.delay.as_millis()), (1, 100));
+    }
+}
@@ -0,0 +1,89 @@
+use std::collections::VecDeque;
+
+pub const DEFAULT_SCROLLBACK_BYTES: usize = 1024 * 1024; // 1MB
+
+/// Bounded ring buffer of raw PTY output, oldest bytes are dropped first.
+pub struct ScrollbackBuffer {
+    data: VecDeque<u8>,
+    capacity: usize,
+    /// Whether bytes were dropped, so the oldest kept byte may be mid-sequence.
+    evicted: bool,
+}
+
+impl ScrollbackBuffer {
+    pub fn new(capacity: usize) -> Self {
+        ScrollbackBuffer {
+            data: VecDeque::with_capacity(capacity.min(64 * 1024)),
+            capacity,
+            evicted: false,
+        }
+    }
+
+    pub fn push(&mut self, bytes: &[u8]) {
+        if bytes.len() >= self.capacity {
+            self.evicted |= !self.data.is_empty() || bytes.len() > self.capacity;
+            self.data.clear();
+            self.data.extend(&bytes[bytes.len() - self.capacity..]);
+            return;
+        }
+        let overflow = (self.data.len() + bytes.len()).saturating_sub(self.capacity);
+        self.evicted |= overflow > 0;
+        self.data.drain(..overflow);
+        self.data.extend(bytes);
+    }
+
+    /// The buffered output for replay. Once bytes were dropped it starts at the next line,
+    /// or failing that the next character, so it never opens with a partial escape sequence
+    /// or UTF-8 character.
+    pub fn to_vec(&self) -> Vec<u8> {
+        let (front, back) = self.data.as_slices();
+        let bytes = [front, back].concat();
+        if !self.evicted {
+            return bytes;
+        }
+        let start = match bytes.iter().position(|&byte| byte == b'\n') {
+            Some(newline) => newline + 1,
+            None => bytes.iter().position(|&byte| !is_continuation(byte)).unwrap_or(bytes.len()),
+        };
+        bytes[start..].to_vec()
+    }
+}
+
+fn is_continuation(byte: u8) -> bool {
+    byte & 0xC0 == 0x80
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_keeps_most_recent_bytes() {
+        let mut buffer = ScrollbackBuffer::new(8);
+        buffer.push(b"hello");
+        buffer.push(b" world");
+        assert_eq!(buffer.to_vec(), b"lo world");
+    }
+
+    #[test]
+    fn test_replay_starts_at_a_safe_boundary() {
+        let mut buffer = ScrollbackBuffer::new(16);
+        buffer.push(b"\x1b[38;5;196mred\nnext");
+        buffer.push(b" line");
+        // The colour sequence lost its ESC, so the partial line is skipped
+        assert_eq!(buffer.to_vec(), b"next line");
+
+        let mut buffer = ScrollbackBuffer::new(4);
+        buffer.push("aé".as_bytes());
+        buffer.push(b"xyz");
+        assert_eq!(buffer.to_vec(), b"xyz");
+    }
+
+    #[test]
+    fn test_oversized_push() {
+        let mut buffer = ScrollbackBuffer::new(4);
+        buffer.push(b"ab");
+        buffer.push(b"0123456789");
+        assert_eq!(buffer.to_vec(), b"6789");
+    }
+}
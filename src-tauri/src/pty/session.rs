@@ -1,49 +1,218 @@
-use portable_pty::{native_pty_system, CommandBuilder, MasterPty, PtySize};
+use portable_pty::{native_pty_system, ChildKiller, CommandBuilder, MasterPty, PtySize};
+use std::collections::HashMap;
 use std::io::{Read, Write};
-use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{mpsc, Arc, Condvar, Mutex};
 use std::time::{Duration, Instant};
-use tauri::ipc::Channel;
 
-use crate::db::DbPool;
-use crate::models::session::{PtyOutputEvent, SessionConfig};
+use crate::error::{AppError, AppResult};
+use crate::models::history::NewSessionCommand;
+use crate::models::monitoring::RecordTokenUsage;
+use crate::models::screen::ScreenSnapshot;
+use crate::models::session::{
+    PtyOutputEvent, SessionActivityEvent, SessionConfig, SessionRestartEvent, SessionTitleEvent,
+    TerminalEnvironment,
+};
+use crate::pty::activity::{ActivityTracker, DEFAULT_IDLE_THRESHOLD};
+use crate::pty::coalescer::{OutputCoalescer, DEFAULT_COALESCE_BYTES, DEFAULT_COALESCE_INTERVAL};
+use crate::pty::emulator::TerminalEmulator;
+use crate::pty::persistence::{PersistJob, PersistenceWorker};
+use crate::pty::restart::RestartTracker;
+use crate::pty::scrollback::{ScrollbackBuffer, DEFAULT_SCROLLBACK_BYTES};
+use crate::pty::shell_integration::CommandTracker;
+use crate::pty::sink::{EventSink, OutputSink, SessionEvent};
+use crate::pty::subscribers::{SubscriberId, SubscriberRegistry};
+use crate::pty::terminal_profile;
+use crate::pty::token_parser::UsageParser;
+use crate::pty::usage_rules::UsageRules;
+use crate::tools::{self, ToolAdapter};
 
 const FLUSH_INTERVAL: Duration = Duration::from_secs(5);
 const FLUSH_SIZE: usize = 32 * 1024; // 32KB
+/// Past this much unpersisted output the reader waits for the persistence queue instead of buffering more.
+const MAX_PENDING_LOG_BYTES: usize = 4 * 1024 * 1024; // 4MB
+/// How long the reaper waits for the reader to drain output written just before exit.
+const READER_DRAIN_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// Called with the session id when its process exits: with the backoff delay if it should be
+/// started again, in which case the owner is expected to call [`PtySession::respawn`] once the
+/// delay has passed, or with `None` once the session's process exited for good.
+pub type ExitHook = Arc<dyn Fn(&str, Option<Duration>) + Send + Sync>;
 
 pub struct PtySession {
     master: Arc<Mutex<Box<dyn MasterPty + Send>>>,
     writer: Arc<Mutex<Box<dyn Write + Send>>>,
+    killer: Mutex<Box<dyn ChildKiller + Send + Sync>>,
+    pid: Option<u32>,
+    exit: Arc<ExitSignal>,
+    stop_requested: Arc<AtomicBool>,
+    activity: Arc<Mutex<ActivityTracker>>,
+    adapter: Arc<dyn ToolAdapter>,
+    output: Arc<Mutex<OutputStream>>,
+    emulator: Arc<Mutex<TerminalEmulator>>,
+    log_offset: Arc<AtomicU64>,
+    restarts: Arc<Mutex<RestartTracker>>,
+    persistence: Arc<PersistenceWorker>,
+    terminal: TerminalEnvironment,
+    config: SessionConfig,
     session_id: String,
 }
 
+/// What a restarted process inherits from the one before it, so the session keeps its
+/// subscribers, scrollback, screen and position in the log.
+struct Continuation {
+    output: Arc<Mutex<OutputStream>>,
+    emulator: Arc<Mutex<TerminalEmulator>>,
+    log_offset: Arc<AtomicU64>,
+    restarts: Arc<Mutex<RestartTracker>>,
+}
+
+/// Where live output goes, plus the scrollback replayed to new subscribers.
+/// Kept under one lock so subscribing never drops or duplicates bytes.
+struct OutputStream {
+    subscribers: SubscriberRegistry,
+    scrollback: ScrollbackBuffer,
+    exited: bool,
+    exit_code: Option<i32>,
+    /// The process exited and a restart is scheduled; `exit_code` is its status.
+    restart_pending: bool,
+}
+
+/// Set by the reaper thread once the child has been waited on.
+#[derive(Default)]
+struct ExitSignal {
+    exited: Mutex<bool>,
+    cond: Condvar,
+}
+
+impl ExitSignal {
+    fn notify(&self) {
+        if let Ok(mut exited) = self.exited.lock() {
+            *exited = true;
+        }
+        self.cond.notify_all();
+    }
+
+    /// Returns true if the child exited within `timeout`.
+    fn wait(&self, timeout: Duration) -> bool {
+        let Ok(exited) = self.exited.lock() else {
+            return false;
+        };
+        match self.cond.wait_timeout_while(exited, timeout, |exited| !*exited) {
+            Ok((exited, _)) => *exited,
+            Err(_) => false,
+        }
+    }
+}
+
 impl PtySession {
-    pub fn spawn(config: &SessionConfig, channel: Channel<PtyOutputEvent>, db: Arc<DbPool>) -> Result<Self, String> {
+    /// Spawns the process with `base_env` under the config's own variables. Output is
+    /// buffered in the scrollback until the first subscriber attaches. `on_exit` is called when
+    /// the process exits, with a delay if the config's restart policy restarts it.
+    /// `log_offset` is where the session's stored output stream ends, so a session restored
+    /// under its saved id continues the stream of its earlier runs.
+    pub fn spawn(
+        config: &SessionConfig,
+        base_env: &HashMap<String, String>,
+        log_offset: u64,
+        persistence: Arc<PersistenceWorker>,
+        usage_rules: Arc<UsageRules>,
+        events: Arc<dyn EventSink>,
+        on_exit: ExitHook,
+    ) -> AppResult<Self> {
+        let continuation = Continuation {
+            output: Arc::new(Mutex::new(OutputStream {
+                subscribers: SubscriberRegistry::default(),
+                scrollback: ScrollbackBuffer::new(config.scrollback_bytes.unwrap_or(DEFAULT_SCROLLBACK_BYTES)),
+                exited: false,
+                exit_code: None,
+                restart_pending: false,
+            })),
+            emulator: Arc::new(Mutex::new(TerminalEmulator::new(config.rows, config.cols))),
+            log_offset: Arc::new(AtomicU64::new(log_offset)),
+            restarts: Arc::new(Mutex::new(RestartTracker::new(config.restart.clone().unwrap_or_default()))),
+        };
+        Self::start(config, base_env, persistence, usage_rules, events, on_exit, continuation)
+    }
+
+    /// Starts the process of a session whose restart is pending, under the same id and
+    /// output stream, at the current terminal size.
+    pub fn respawn(
+        &self,
+        base_env: &HashMap<String, String>,
+        persistence: Arc<PersistenceWorker>,
+        usage_rules: Arc<UsageRules>,
+        events: Arc<dyn EventSink>,
+        on_exit: ExitHook,
+    ) -> AppResult<Self> {
+        let continuation = Continuation {
+            output: self.output.clone(),
+            emulator: self.emulator.clone(),
+            log_offset: self.log_offset.clone(),
+            restarts: self.restarts.clone(),
+        };
+        let session = Self::start(&self.config, base_env, persistence, usage_rules, events, on_exit, continuation)?;
+        let mut output = session.output.lock()?;
+        output.restart_pending = false;
+        output.exit_code = None;
+        drop(output);
+        Ok(session)
+    }
+
+    fn start(
+        config: &SessionConfig,
+        base_env: &HashMap<String, String>,
+        persistence: Arc<PersistenceWorker>,
+        usage_rules: Arc<UsageRules>,
+        events: Arc<dyn EventSink>,
+        on_exit: ExitHook,
+        continuation: Continuation,
+    ) -> AppResult<Self> {
         let pty_system = native_pty_system();
 
+        let (rows, cols) = continuation.emulator.lock()?.size();
         let pair = pty_system
             .openpty(PtySize {
-                rows: config.rows,
-                cols: config.cols,
+                rows,
+                cols,
                 pixel_width: 0,
                 pixel_height: 0,
             })
-            .map_err(|e| format!("Failed to open PTY: {e}"))?;
+            .map_err(|e| AppError::Pty(format!("Failed to open PTY: {e}")))?;
 
-        let mut cmd = CommandBuilder::new(&config.command);
-        for arg in &config.args {
+        let adapter = tools::adapter_for(config)?;
+        let launch = tools::launch_command(adapter.as_ref(), config)?;
+        let mut cmd = CommandBuilder::new(&launch.command);
+        for arg in &launch.args {
             cmd.arg(arg);
         }
         if config.working_dir.exists() {
             cmd.cwd(&config.working_dir);
         }
-        for (key, value) in &config.env_vars {
+        let terminal = terminal_profile::resolve(
+            &config.terminal.clone().unwrap_or_default(),
+            base_env,
+            &config.env_vars,
+        );
+        for (key, value) in base_env.iter().chain(&terminal.vars) {
+            cmd.env(key, value);
+        }
+        for key in &terminal.unset {
+            cmd.env_remove(key);
+        }
+        for (key, value) in adapter.default_env().iter().chain(&config.env_vars) {
             cmd.env(key, value);
         }
 
-        let _child = pair
+        let mut child = pair
             .slave
             .spawn_command(cmd)
-            .map_err(|e| format!("Failed to spawn command: {e}"))?;
+            .map_err(|e| AppError::Spawn {
+                command: launch.command.clone(),
+                message: e.to_string(),
+            })?;
+        let killer = child.clone_killer();
+        let pid = child.process_id();
 
         // Drop slave immediately - we only need the master
         drop(pair.slave);
@@ -51,83 +220,361 @@ impl PtySession {
         let writer = pair
             .master
             .take_writer()
-            .map_err(|e| format!("Failed to get PTY writer: {e}"))?;
+            .map_err(|e| AppError::Pty(format!("Failed to get PTY writer: {e}")))?;
 
         let mut reader = pair
             .master
             .try_clone_reader()
-            .map_err(|e| format!("Failed to get PTY reader: {e}"))?;
+            .map_err(|e| AppError::Pty(format!("Failed to get PTY reader: {e}")))?;
 
         let session_id = config.id.clone();
         let channel_session_id = session_id.clone();
+        let Continuation {
+            output,
+            emulator,
+            log_offset,
+            restarts,
+        } = continuation;
+        let reader_output = output.clone();
+        let reader_persistence = persistence.clone();
+        let (reader_done_tx, reader_done_rx) = mpsc::channel::<()>();
+
+        let idle_threshold = config
+            .idle_threshold_ms
+            .map(Duration::from_millis)
+            .unwrap_or(DEFAULT_IDLE_THRESHOLD);
+        let activity = Arc::new(Mutex::new(ActivityTracker::new(idle_threshold)));
+        let reader_activity = activity.clone();
+        let reader_adapter = adapter.clone();
+        let reader_emulator = emulator.clone();
+        let reader_log_offset = log_offset.clone();
+        let reaper_events = events.clone();
+
+        // Spawn forwarder thread: batches reads for the scrollback and subscribers
+        let coalescer = OutputCoalescer::new(
+            config
+                .output_coalesce_ms
+                .map(Duration::from_millis)
+                .unwrap_or(DEFAULT_COALESCE_INTERVAL),
+            config.output_coalesce_bytes.unwrap_or(DEFAULT_COALESCE_BYTES),
+        );
+        let (output_tx, output_rx) = mpsc::channel::<Vec<u8>>();
+        let forwarder_session_id = session_id.clone();
+        let forwarder_output = output.clone();
+        let forwarder = std::thread::spawn(move || {
+            forward_output(output_rx, coalescer, &forwarder_output, &forwarder_session_id);
+        });
 
         // Spawn reader thread with log batching
         std::thread::spawn(move || {
+            let persistence = reader_persistence;
+            let mut read_error = None;
             let mut buf = [0u8; 4096];
-            let mut log_buffer: Vec<u8> = Vec::new();
-            let mut last_flush = Instant::now();
+            // A restarted process continues the previous one's log stream
+            let mut commands = CommandTracker::new(&channel_session_id, reader_log_offset.load(Ordering::SeqCst));
+            let mut pending_log = PendingLog::new(&channel_session_id, reader_adapter, usage_rules, reader_log_offset);
 
             loop {
                 match reader.read(&mut buf) {
                     Ok(0) => {
-                        flush_log(&db, &channel_session_id, &mut log_buffer);
-                        let _ = channel.send(PtyOutputEvent::Exited {
-                            session_id: channel_session_id.clone(),
-                            exit_code: None,
-                        });
+                        pending_log.finish(&persistence);
                         break;
                     }
                     Ok(n) => {
-                        let _ = channel.send(PtyOutputEvent::Data {
-                            session_id: channel_session_id.clone(),
-                            data: buf[..n].to_vec(),
-                        });
+                        if let Ok(mut activity) = reader_activity.lock() {
+                            activity.record_output();
+                        }
+                        let (finished_commands, metadata_change) = match reader_emulator.lock() {
+                            Ok(mut emulator) => (
+                                commands.process(&mut emulator, &buf[..n]),
+                                emulator.take_metadata_change(),
+                            ),
+                            Err(_) => (Vec::new(), None),
+                        };
+                        if let Some(metadata) = metadata_change {
+                            persistence.submit(PersistJob::Metadata {
+                                session_id: channel_session_id.clone(),
+                                title: metadata.title.clone(),
+                                cwd: metadata.cwd.clone(),
+                            });
+                            events.emit(SessionEvent::TitleChanged(SessionTitleEvent {
+                                session_id: channel_session_id.clone(),
+                                title: metadata.title,
+                                cwd: metadata.cwd,
+                            }));
+                        }
 
-                        log_buffer.extend_from_slice(&buf[..n]);
+                        let _ = output_tx.send(buf[..n].to_vec());
 
-                        if log_buffer.len() >= FLUSH_SIZE || last_flush.elapsed() >= FLUSH_INTERVAL {
-                            flush_log(&db, &channel_session_id, &mut log_buffer);
-                            last_flush = Instant::now();
+                        pending_log.push(&buf[..n], finished_commands);
+                        if pending_log.is_due() {
+                            pending_log.flush(&persistence, false);
                         }
                     }
                     Err(e) => {
-                        flush_log(&db, &channel_session_id, &mut log_buffer);
-                        let _ = channel.send(PtyOutputEvent::Error {
-                            session_id: channel_session_id.clone(),
-                            message: format!("Read error: {e}"),
-                        });
+                        pending_log.finish(&persistence);
+                        if !is_hangup(&e) {
+                            read_error = Some(format!("Read error: {e}"));
+                        }
                         break;
                     }
                 }
             }
+
+            // Deliver whatever is still batched before reporting the error or exit
+            drop(output_tx);
+            let _ = forwarder.join();
+            persistence.sync(READER_DRAIN_TIMEOUT);
+            if let Some(message) = read_error {
+                if let Ok(mut output) = reader_output.lock() {
+                    output.subscribers.broadcast(&PtyOutputEvent::Error {
+                        session_id: channel_session_id.clone(),
+                        message,
+                    });
+                }
+            }
+            let _ = reader_done_tx.send(());
+        });
+
+        let exit = Arc::new(ExitSignal::default());
+        let stop_requested = Arc::new(AtomicBool::new(false));
+
+        // Spawn reaper thread: waits for the child and reports its real exit status
+        let reaper_session_id = session_id.clone();
+        let reaper_exit = exit.clone();
+        let reaper_stop_requested = stop_requested.clone();
+        let reaper_output = output.clone();
+        let reaper_restarts = restarts.clone();
+        let reaper_persistence = persistence.clone();
+        let started = Instant::now();
+        std::thread::spawn(move || {
+            let exit_code = child.wait().ok().map(|status| status.exit_code() as i32);
+            // Before anything else, so `stop` never signals the group of a reaped pid
+            reaper_exit.notify();
+
+            // Make sure the final output reaches the UI and the log before Exited
+            let _ = reader_done_rx.recv_timeout(READER_DRAIN_TIMEOUT);
+
+            // Decided under the output lock so it cannot race `stop`, which takes the same
+            // lock after setting `stop_requested`
+            let restart = reaper_output.lock().ok().and_then(|mut output| {
+                if reaper_stop_requested.load(Ordering::SeqCst) {
+                    return None;
+                }
+                let restart = reaper_restarts.lock().ok()?.next(exit_code, started.elapsed())?;
+                output.restart_pending = true;
+                output.exit_code = exit_code;
+                Some(restart)
+            });
+            if let Some(restart) = restart {
+                reaper_persistence.submit(PersistJob::Restart {
+                    session_id: reaper_session_id.clone(),
+                    attempt: restart.attempt,
+                    exit_code,
+                });
+                reaper_events.emit(SessionEvent::Restarting(SessionRestartEvent {
+                    session_id: reaper_session_id.clone(),
+                    attempt: restart.attempt,
+                    exit_code,
+                    delay_ms: restart.delay.as_millis() as u64,
+                }));
+                on_exit(&reaper_session_id, Some(restart.delay));
+                return;
+            }
+
+            let status = if reaper_stop_requested.load(Ordering::SeqCst) || exit_code == Some(0) {
+                "stopped"
+            } else {
+                "failed"
+            };
+            reaper_persistence.submit(PersistJob::Status {
+                session_id: reaper_session_id.clone(),
+                status: status.to_string(),
+            });
+
+            if let Ok(mut output) = reaper_output.lock() {
+                output.exited = true;
+                output.exit_code = exit_code;
+                output.subscribers.broadcast(&PtyOutputEvent::Exited {
+                    session_id: reaper_session_id.clone(),
+                    exit_code,
+                });
+            }
+            on_exit(&reaper_session_id, None);
         });
 
         Ok(PtySession {
             master: Arc::new(Mutex::new(pair.master)),
             writer: Arc::new(Mutex::new(writer)),
+            killer: Mutex::new(killer),
+            pid,
+            exit,
+            stop_requested,
+            activity,
+            adapter,
+            output,
+            emulator,
+            log_offset,
+            restarts,
+            persistence,
+            terminal,
+            config: config.clone(),
             session_id,
         })
     }
 
-    pub fn write(&self, data: &[u8]) -> Result<(), String> {
+    pub fn write(&self, data: &[u8]) -> AppResult<()> {
         self.writer
-            .lock()
-            .map_err(|e| format!("Lock error: {e}"))?
+            .lock()?
             .write_all(data)
-            .map_err(|e| format!("Write error: {e}"))
+            .map_err(|e| AppError::Pty(format!("Write error: {e}")))
     }
 
-    pub fn resize(&self, cols: u16, rows: u16) -> Result<(), String> {
+    pub fn resize(&self, cols: u16, rows: u16) -> AppResult<()> {
+        self.emulator.lock()?.resize(rows, cols);
         self.master
-            .lock()
-            .map_err(|e| format!("Lock error: {e}"))?
+            .lock()?
             .resize(PtySize {
                 rows,
                 cols,
                 pixel_width: 0,
                 pixel_height: 0,
             })
-            .map_err(|e| format!("Resize error: {e}"))
+            .map_err(|e| AppError::Pty(format!("Resize error: {e}")))
+    }
+
+    /// Gracefully stops the process: SIGHUP and SIGTERM to its process group,
+    /// then SIGKILL if it is still alive after `grace_period`.
+    /// Blocks for at most `grace_period`.
+    pub fn stop(&self, grace_period: Duration) -> AppResult<()> {
+        self.stop_requested.store(true, Ordering::SeqCst);
+        if self.finish_pending_restart(None)? {
+            return Ok(());
+        }
+
+        #[cfg(unix)]
+        if let Some(pid) = self.pid {
+            // Once the child is reaped its pid, and so its group, may belong to another process
+            for signal in [libc::SIGHUP, libc::SIGTERM] {
+                if self.exit.wait(Duration::ZERO) {
+                    return Ok(());
+                }
+                signal_process_group(pid, signal);
+            }
+            if !self.exit.wait(grace_period) {
+                signal_process_group(pid, libc::SIGKILL);
+            }
+            return Ok(());
+        }
+
+        #[cfg(not(unix))]
+        let _ = (self.pid, grace_period);
+
+        if self.exit.wait(Duration::ZERO) {
+            return Ok(());
+        }
+        self.killer
+            .lock()?
+            .kill()
+            .map_err(|e| AppError::Pty(format!("Kill error: {e}")))
+    }
+
+    /// Whether the session's process exited for good.
+    pub fn has_exited(&self) -> bool {
+        self.output.lock().is_ok_and(|output| output.exited)
+    }
+
+    /// Whether the process exited and is waiting to be restarted.
+    pub fn restart_pending(&self) -> bool {
+        self.output.lock().is_ok_and(|output| output.restart_pending)
+    }
+
+    /// Gives up a pending restart, reporting `error` (if any) and then the exit that
+    /// triggered it to subscribers. Returns false if no restart was pending.
+    pub fn finish_pending_restart(&self, error: Option<String>) -> AppResult<bool> {
+        let mut output = self.output.lock()?;
+        if !output.restart_pending {
+            return Ok(false);
+        }
+        // The reaper left the status to the restart, which will not happen now
+        self.persistence.submit(PersistJob::Status {
+            session_id: self.session_id.clone(),
+            status: if error.is_some() { "failed" } else { "stopped" }.to_string(),
+        });
+        output.restart_pending = false;
+        output.exited = true;
+        if let Some(message) = error {
+            output.subscribers.broadcast(&PtyOutputEvent::Error {
+                session_id: self.session_id.clone(),
+                message,
+            });
+        }
+        let exit_code = output.exit_code;
+        output.subscribers.broadcast(&PtyOutputEvent::Exited {
+            session_id: self.session_id.clone(),
+            exit_code,
+        });
+        Ok(true)
+    }
+
+    /// The terminal variables the process was started with.
+    pub fn terminal_environment(&self) -> &TerminalEnvironment {
+        &self.terminal
+    }
+
+    pub fn screen_snapshot(&self, include_cells: bool) -> AppResult<ScreenSnapshot> {
+        Ok(self.emulator.lock()?.snapshot(&self.session_id, include_cells))
+    }
+
+    /// Adds `sink` to the live output subscribers, optionally replaying the scrollback first.
+    /// If the process already exited, the sink also receives the Exited event.
+    pub fn subscribe(&self, id: SubscriberId, sink: impl OutputSink, replay: bool) -> AppResult<()> {
+        let mut output = self.output.lock()?;
+        let scrollback = output.scrollback.to_vec();
+        if replay && !scrollback.is_empty() {
+            sink.send(PtyOutputEvent::Data {
+                session_id: self.session_id.clone(),
+                data: scrollback,
+            })?;
+        }
+        if output.exited {
+            let _ = sink.send(PtyOutputEvent::Exited {
+                session_id: self.session_id.clone(),
+                exit_code: output.exit_code,
+            });
+        }
+        output.subscribers.insert(id, Box::new(sink));
+        Ok(())
+    }
+
+    pub fn unsubscribe(&self, id: SubscriberId) -> AppResult<bool> {
+        let mut output = self.output.lock()?;
+        Ok(output.subscribers.remove(id))
+    }
+
+    /// Replaces every subscriber with `sink`, replaying the scrollback to it.
+    pub fn attach(&self, id: SubscriberId, sink: impl OutputSink) -> AppResult<()> {
+        self.output.lock()?.subscribers.clear();
+        self.subscribe(id, sink, true)
+    }
+
+    /// Returns an activity event if the session flipped between Working and Idle since the last poll.
+    /// Exited sessions never report activity.
+    pub fn poll_activity(&self) -> Option<SessionActivityEvent> {
+        if self.exit.wait(Duration::ZERO) {
+            return None;
+        }
+        let awaiting_input = || {
+            self.emulator
+                .lock()
+                .is_ok_and(|emulator| self.adapter.is_awaiting_input(&emulator.screen_text()))
+        };
+        let (state, since) = self.activity.lock().ok()?.poll(Instant::now(), awaiting_input)?;
+        Some(SessionActivityEvent {
+            session_id: self.session_id.clone(),
+            state,
+            since,
+        })
     }
 
     pub fn session_id(&self) -> &str {
@@ -135,26 +582,203 @@ impl PtySession {
     }
 }
 
-fn flush_log(db: &Arc<DbPool>, session_id: &str, buffer: &mut Vec<u8>) {
-    if buffer.is_empty() {
-        return;
+/// Whether a read from the PTY master failed only because the child side closed. Linux
+/// reports that as EIO rather than end of file.
+fn is_hangup(error: &std::io::Error) -> bool {
+    #[cfg(unix)]
+    {
+        error.raw_os_error() == Some(libc::EIO)
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = error;
+        false
     }
+}
 
-    // Scan for token usage patterns before flushing
-    if let Ok(text) = std::str::from_utf8(buffer) {
-        let records = super::token_parser::extract_token_usage(text, session_id);
-        if !records.is_empty() {
-            let _ = db.with_conn(|conn| {
-                for record in &records {
-                    let _ = crate::db::monitoring_repo::record_usage(conn, record);
-                }
-                Ok(())
-            });
+/// The child is spawned as a session leader, so its pid is also its process group id.
+#[cfg(unix)]
+fn signal_process_group(pid: u32, signal: libc::c_int) {
+    unsafe {
+        libc::killpg(pid as libc::pid_t, signal);
+    }
+}
+
+/// Drains reads from the reader thread, publishing them in coalesced batches until the reader hangs up.
+fn forward_output(
+    reads: mpsc::Receiver<Vec<u8>>,
+    mut coalescer: OutputCoalescer,
+    output: &Mutex<OutputStream>,
+    session_id: &str,
+) {
+    loop {
+        let received = match coalescer.deadline() {
+            Some(deadline) => reads.recv_timeout(deadline.saturating_duration_since(Instant::now())),
+            None => reads.recv().map_err(|_| mpsc::RecvTimeoutError::Disconnected),
+        };
+        let (batch, done) = match received {
+            Ok(read) => (coalescer.push(&read, Instant::now()), false),
+            Err(mpsc::RecvTimeoutError::Timeout) => (coalescer.take(), false),
+            Err(mpsc::RecvTimeoutError::Disconnected) => (coalescer.take(), true),
+        };
+        if let Some(data) = batch {
+            if let Ok(mut output) = output.lock() {
+                output.scrollback.push(&data);
+                output.subscribers.broadcast(&PtyOutputEvent::Data {
+                    session_id: session_id.to_string(),
+                    data,
+                });
+            }
         }
+        if done {
+            break;
+        }
+    }
+}
+
+/// Output (and finished commands and usage) not yet handed to the persistence worker.
+struct PendingLog {
+    session_id: String,
+    usage_parser: UsageParser,
+    content: Vec<u8>,
+    usage: Vec<RecordTokenUsage>,
+    /// Shared with later processes of the session, which continue the same log stream.
+    stream_offset: Arc<AtomicU64>,
+    commands: Vec<NewSessionCommand>,
+    last_flush: Instant,
+}
+
+impl PendingLog {
+    fn new(
+        session_id: &str,
+        adapter: Arc<dyn ToolAdapter>,
+        usage_rules: Arc<UsageRules>,
+        stream_offset: Arc<AtomicU64>,
+    ) -> Self {
+        PendingLog {
+            session_id: session_id.to_string(),
+            usage_parser: UsageParser::new(session_id, adapter, usage_rules, stream_offset.load(Ordering::SeqCst)),
+            content: Vec::new(),
+            usage: Vec::new(),
+            stream_offset,
+            commands: Vec::new(),
+            last_flush: Instant::now(),
+        }
+    }
+
+    fn push(&mut self, output: &[u8], commands: Vec<NewSessionCommand>) {
+        self.content.extend_from_slice(output);
+        self.usage.extend(self.usage_parser.feed(output));
+        self.commands.extend(commands);
+    }
+
+    /// Finished commands flush right away so their output range is queryable.
+    fn is_due(&self) -> bool {
+        !self.commands.is_empty()
+            || self.content.len() >= FLUSH_SIZE
+            || self.last_flush.elapsed() >= FLUSH_INTERVAL
+    }
+
+    /// Hands the pending output to the worker. If the queue is full it stays buffered for the
+    /// next attempt, unless `wait` is set or it has grown past `MAX_PENDING_LOG_BYTES`, in which
+    /// case the reader waits for room rather than dropping history.
+    fn flush(&mut self, persistence: &PersistenceWorker, wait: bool) {
+        self.submit(persistence, wait, incomplete_utf8_tail(&self.content));
+    }
+
+    /// Flushes everything once the process's output ended, including usage on its last line.
+    fn finish(&mut self, persistence: &PersistenceWorker) {
+        self.usage.extend(self.usage_parser.finish());
+        self.submit(persistence, true, 0);
+    }
+
+    /// Submits all pending output except its last `hold_back` bytes, which are kept for the
+    /// next flush so a character split across reads is stored whole.
+    fn submit(&mut self, persistence: &PersistenceWorker, wait: bool, hold_back: usize) {
+        let tail = self.content.split_off(self.content.len() - hold_back);
+        if self.content.is_empty() && self.commands.is_empty() && self.usage.is_empty() {
+            self.content = tail;
+            return;
+        }
+        let len = self.content.len();
+        let job = PersistJob::Output {
+            session_id: self.session_id.clone(),
+            usage: std::mem::take(&mut self.usage),
+            content: std::mem::replace(&mut self.content, tail),
+            stream_offset: self.stream_offset.load(Ordering::SeqCst),
+            commands: std::mem::take(&mut self.commands),
+        };
+        if wait || len >= MAX_PENDING_LOG_BYTES {
+            persistence.submit(job);
+        } else if let Err(PersistJob::Output { mut content, usage, commands, .. }) = persistence.try_submit(job) {
+            content.append(&mut self.content);
+            self.content = content;
+            self.usage = usage;
+            self.commands = commands;
+            return;
+        }
+        self.stream_offset.fetch_add(len as u64, Ordering::SeqCst);
+        self.last_flush = Instant::now();
     }
+}
 
-    let _ = db.with_conn(|conn| {
-        crate::db::history_repo::insert_log(conn, session_id, buffer)
-    });
-    buffer.clear();
+/// Length of the UTF-8 sequence cut off at the end of `bytes`, or 0 if it ends on a
+/// character boundary.
+fn incomplete_utf8_tail(bytes: &[u8]) -> usize {
+    (bytes.len().saturating_sub(3)..bytes.len())
+        .find(|&start| {
+            matches!(std::str::from_utf8(&bytes[start..]), Err(e) if e.valid_up_to() == 0 && e.error_len().is_none())
+        })
+        .map_or(0, |start| bytes.len() - start)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::{history_repo, migrations, DbPool};
+    use crate::tools::CustomAdapter;
+
+    #[test]
+    fn test_keeps_characters_split_across_flushes() {
+        let dir = std::env::temp_dir().join(format!("agentdesk-session-{}-utf8", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let db = Arc::new(DbPool::new(&dir).unwrap());
+        db.with_conn(migrations::run_migrations).unwrap();
+        db.with_conn(|conn| {
+            conn.execute(
+                "INSERT INTO sessions (id, name, tool, command, working_dir) VALUES ('s', 's', 'Custom', 'sh', '')",
+                [],
+            )
+        })
+        .unwrap();
+        let persistence = PersistenceWorker::start(db.clone(), 16);
+        let adapter = Arc::new(CustomAdapter::new("sh", &[]));
+        let mut log = PendingLog::new("s", adapter, Arc::new(UsageRules::default()), Arc::new(AtomicU64::new(0)));
+
+        log.push("caf\u{e9}".as_bytes().split_last().unwrap().1, Vec::new());
+        log.flush(&persistence, true);
+        let command = NewSessionCommand {
+            session_id: "s".to_string(),
+            command: "echo".to_string(),
+            exit_code: Some(0),
+            output_start: 3,
+            output_end: 8,
+            started_at: String::new(),
+            ended_at: String::new(),
+            duration_ms: 0,
+        };
+        log.push(b"\xa9 \xff!", vec![command]);
+        log.finish(&persistence);
+        assert!(persistence.sync(Duration::from_secs(5)));
+
+        let text = db.with_conn(|conn| history_repo::get_session_log(conn, "s")).unwrap();
+        assert_eq!(text, "caf\u{e9} ?!");
+        let output = db
+            .with_conn(|conn| {
+                let id = history_repo::list_commands(conn, "s")?[0].id;
+                history_repo::get_command_output(conn, id)
+            })
+            .unwrap();
+        assert_eq!(output, "\u{e9} ?!");
+    }
 }
@@ -0,0 +1,194 @@
+use std::collections::HashMap;
+use std::process::Command;
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::Duration;
+
+use crate::error::{AppError, AppResult};
+use crate::models::session::LoginEnvironment;
+use crate::process;
+
+/// How long the login shell gets to print its environment before it is killed.
+pub const CAPTURE_TIMEOUT: Duration = Duration::from_secs(5);
+/// Printed before `env` so anything the profile scripts echo can be skipped.
+const ENV_MARKER: &str = "__AGENTDESK_ENV__";
+/// Variables that describe the capturing shell itself rather than the user's setup.
+const SHELL_LOCAL_VARS: &[&str] = &["PWD", "OLDPWD", "SHLVL", "_"];
+
+#[derive(Default)]
+struct CacheState {
+    vars: Arc<HashMap<String, String>>,
+    captured_at: Option<String>,
+    error: Option<String>,
+    /// Generation of the most recently started capture.
+    started: u64,
+    /// Generation of the most recent capture whose result was kept; older ones finishing
+    /// later are discarded.
+    finished: u64,
+}
+
+impl CacheState {
+    fn capturing(&self) -> bool {
+        self.finished < self.started
+    }
+}
+
+/// The user's login-shell environment. Apps started from a desktop launcher inherit a
+/// minimal environment, so sessions get this merged under their own `env_vars`.
+#[derive(Default)]
+pub struct ShellEnvironment {
+    state: Mutex<CacheState>,
+    captured: Condvar,
+}
+
+impl ShellEnvironment {
+    /// Captures the environment on a background thread. Sessions spawned in the meantime
+    /// wait for it (bounded by [`CAPTURE_TIMEOUT`]).
+    pub fn refresh_in_background(self: &Arc<Self>) {
+        let Ok(generation) = self.begin_capture() else {
+            return;
+        };
+        let environment = self.clone();
+        std::thread::spawn(move || {
+            let _ = environment.capture(generation);
+        });
+    }
+
+    /// Re-runs the login shell and replaces the cached environment. On failure the
+    /// previous environment is kept and the error is recorded.
+    pub fn refresh(&self) -> AppResult<LoginEnvironment> {
+        let generation = self.begin_capture()?;
+        self.capture(generation)
+    }
+
+    fn begin_capture(&self) -> AppResult<u64> {
+        let mut state = self.state.lock()?;
+        state.started += 1;
+        Ok(state.started)
+    }
+
+    fn capture(&self, generation: u64) -> AppResult<LoginEnvironment> {
+        let result = match login_shell() {
+            Some(shell) => capture_environment(&shell),
+            None => Ok(HashMap::new()),
+        };
+        let mut state = self.state.lock()?;
+        // Unless a capture started after this one already replaced the environment
+        if generation > state.finished {
+            state.finished = generation;
+            match &result {
+                Ok(vars) => {
+                    state.vars = Arc::new(vars.clone());
+                    state.captured_at = Some(chrono::Utc::now().format("%Y-%m-%d %H:%M:%S").to_string());
+                    state.error = None;
+                }
+                Err(e) => state.error = Some(e.to_string()),
+            }
+            self.captured.notify_all();
+        }
+        drop(state);
+        result.map(|_| self.info())
+    }
+
+    /// The cached variables, waiting for the latest capture in progress to finish.
+    pub fn vars(&self) -> Arc<HashMap<String, String>> {
+        let Ok(state) = self.state.lock() else {
+            return Arc::default();
+        };
+        match self
+            .captured
+            .wait_timeout_while(state, CAPTURE_TIMEOUT, |state| state.capturing())
+        {
+            Ok((state, _)) => state.vars.clone(),
+            Err(_) => Arc::default(),
+        }
+    }
+
+    pub fn info(&self) -> LoginEnvironment {
+        let vars = self.vars();
+        let state = self.state.lock();
+        LoginEnvironment {
+            shell: login_shell(),
+            vars: (*vars).clone(),
+            captured_at: state.as_ref().ok().and_then(|state| state.captured_at.clone()),
+            error: state.as_ref().ok().and_then(|state| state.error.clone()),
+        }
+    }
+}
+
+/// The shell whose login environment is captured. `None` on Windows, where GUI apps
+/// already inherit the user's environment.
+pub fn login_shell() -> Option<String> {
+    if cfg!(windows) {
+        return None;
+    }
+    let fallback = if cfg!(target_os = "macos") { "/bin/zsh" } else { "/bin/sh" };
+    Some(std::env::var("SHELL").unwrap_or_else(|_| fallback.to_string()))
+}
+
+/// Runs `shell` as a non-interactive login shell and returns the environment it ends up with.
+pub fn capture_environment(shell: &str) -> AppResult<HashMap<String, String>> {
+    let output = process::output_with_timeout(
+        Command::new(shell).args(["-l", "-c", &format!("printf '{ENV_MARKER}'; env -0")]),
+        CAPTURE_TIMEOUT,
+    )?;
+    if !output.status.success() {
+        return Err(AppError::Internal(format!("{shell} exited with {}", output.status)));
+    }
+    parse_env_output(&output.stdout)
+        .ok_or_else(|| AppError::Internal(format!("{shell} did not print an environment")))
+}
+
+/// Parses the NUL-separated `env -0` output that follows [`ENV_MARKER`].
+fn parse_env_output(output: &[u8]) -> Option<HashMap<String, String>> {
+    let text = String::from_utf8_lossy(output);
+    let (_, env) = text.rsplit_once(ENV_MARKER)?;
+    Some(
+        env.split('\0')
+            .filter_map(|entry| entry.split_once('='))
+            .filter(|(name, _)| !name.is_empty() && !SHELL_LOCAL_VARS.contains(name))
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parses_env_after_marker() {
+        let output = format!("motd noise\n{ENV_MARKER}PATH=/usr/bin\0NOTE=a=b\nc\0SHLVL=2\0");
+        let vars = parse_env_output(output.as_bytes()).unwrap();
+        assert_eq!(vars.len(), 2);
+        assert_eq!(vars["PATH"], "/usr/bin");
+        assert_eq!(vars["NOTE"], "a=b\nc");
+        assert!(parse_env_output(b"no marker").is_none());
+    }
+
+    #[test]
+    fn test_vars_wait_for_the_latest_capture() {
+        let environment = Arc::new(ShellEnvironment::default());
+        let first = environment.begin_capture().unwrap();
+        let second = environment.begin_capture().unwrap();
+        let capture = environment.clone();
+        std::thread::spawn(move || {
+            for (generation, path) in [(first, "/first/bin"), (second, "/second/bin")] {
+                std::thread::sleep(Duration::from_millis(50));
+                let mut state = capture.state.lock().unwrap();
+                state.vars = Arc::new(HashMap::from([("PATH".to_string(), path.to_string())]));
+                state.finished = generation;
+                capture.captured.notify_all();
+            }
+        });
+        // The first capture finishing does not end the wait for the second
+        assert_eq!(environment.vars()["PATH"], "/second/bin");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_captures_login_shell_environment() {
+        let vars = capture_environment("/bin/sh").unwrap();
+        assert!(vars.contains_key("PATH"));
+        assert!(!vars.contains_key("SHLVL"));
+    }
+}
@@ -0,0 +1,250 @@
+use std::time::Instant;
+
+use chrono::{DateTime, Utc};
+
+use crate::models::history::NewSessionCommand;
+use crate::pty::emulator::TerminalEmulator;
+
+/// OSC bodies longer than this cannot be a prompt mark, so they are not buffered.
+const MAX_MARK_LEN: usize = 256;
+
+/// FinalTerm / OSC 133 shell-integration marks.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PromptMark {
+    /// `A`: the prompt is about to be drawn.
+    PromptStart,
+    /// `B`: the prompt ended, user input follows.
+    CommandStart,
+    /// `C`: the command was submitted, its output follows.
+    OutputStart,
+    /// `D[;exit]`: the command finished.
+    CommandFinished { exit_code: Option<i32> },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScannedMark {
+    pub mark: PromptMark,
+    /// Stream offset of the ESC that opens the sequence.
+    pub start: u64,
+    /// Stream offset just past the sequence terminator.
+    pub end: u64,
+    /// Index just past the terminator within the chunk that completed the sequence.
+    pub chunk_end: usize,
+}
+
+#[derive(Default)]
+enum ScanState {
+    #[default]
+    Ground,
+    Escape,
+    Osc,
+    OscEscape,
+}
+
+/// Finds OSC 133 marks in raw output, tracking exact stream offsets across chunk boundaries.
+#[derive(Default)]
+pub struct PromptMarkScanner {
+    state: ScanState,
+    offset: u64,
+    sequence_start: u64,
+    body: Vec<u8>,
+    overflowed: bool,
+}
+
+impl PromptMarkScanner {
+    /// A scanner for output that starts at `offset` in the session's output stream.
+    pub fn starting_at(offset: u64) -> Self {
+        PromptMarkScanner {
+            offset,
+            ..Default::default()
+        }
+    }
+
+    pub fn feed(&mut self, chunk: &[u8]) -> Vec<ScannedMark> {
+        let mut marks = Vec::new();
+        for (i, &byte) in chunk.iter().enumerate() {
+            let position = self.offset + i as u64;
+            match self.state {
+                ScanState::Ground => {
+                    if byte == 0x1b {
+                        self.state = ScanState::Escape;
+                        self.sequence_start = position;
+                    }
+                }
+                ScanState::Escape => self.after_escape(byte, position),
+                ScanState::Osc => match byte {
+                    0x07 => marks.extend(self.finish(position + 1, i + 1)),
+                    0x1b => self.state = ScanState::OscEscape,
+                    _ if self.body.len() < MAX_MARK_LEN => self.body.push(byte),
+                    _ => self.overflowed = true,
+                },
+                ScanState::OscEscape => {
+                    if byte == b'\\' {
+                        marks.extend(self.finish(position + 1, i + 1));
+                    } else {
+                        // The ESC aborted the OSC and starts a new sequence
+                        self.sequence_start = position - 1;
+                        self.after_escape(byte, position);
+                    }
+                }
+            }
+        }
+        self.offset += chunk.len() as u64;
+        marks
+    }
+
+    fn after_escape(&mut self, byte: u8, position: u64) {
+        match byte {
+            b']' => {
+                self.state = ScanState::Osc;
+                self.body.clear();
+                self.overflowed = false;
+            }
+            0x1b => self.sequence_start = position,
+            _ => self.state = ScanState::Ground,
+        }
+    }
+
+    fn finish(&mut self, end: u64, chunk_end: usize) -> Option<ScannedMark> {
+        self.state = ScanState::Ground;
+        if self.overflowed {
+            return None;
+        }
+        let mark = parse_mark(&self.body)?;
+        Some(ScannedMark {
+            mark,
+            start: self.sequence_start,
+            end,
+            chunk_end,
+        })
+    }
+}
+
+fn parse_mark(body: &[u8]) -> Option<PromptMark> {
+    let body = std::str::from_utf8(body).ok()?;
+    let mut params = body.strip_prefix("133;")?.split(';');
+    match params.next()? {
+        "A" => Some(PromptMark::PromptStart),
+        "B" => Some(PromptMark::CommandStart),
+        "C" => Some(PromptMark::OutputStart),
+        "D" => Some(PromptMark::CommandFinished {
+            exit_code: params.next().and_then(|code| code.parse().ok()),
+        }),
+        _ => None,
+    }
+}
+
+struct RunningCommand {
+    command: String,
+    output_start: u64,
+    started_at: DateTime<Utc>,
+    started: Instant,
+}
+
+/// Turns prompt marks into command records, reading the typed command line off the emulator screen.
+pub struct CommandTracker {
+    session_id: String,
+    scanner: PromptMarkScanner,
+    input_start: Option<(u16, u16)>,
+    running: Option<RunningCommand>,
+}
+
+impl CommandTracker {
+    /// `offset` is where the output about to be processed starts in the session's output
+    /// stream, which later processes of the session continue.
+    pub fn new(session_id: &str, offset: u64) -> Self {
+        CommandTracker {
+            session_id: session_id.to_string(),
+            scanner: PromptMarkScanner::starting_at(offset),
+            input_start: None,
+            running: None,
+        }
+    }
+
+    /// Feeds `chunk` to the emulator, pausing at each mark so the screen reflects
+    /// exactly what preceded it. Returns the commands that finished in this chunk.
+    pub fn process(&mut self, emulator: &mut TerminalEmulator, chunk: &[u8]) -> Vec<NewSessionCommand> {
+        let mut finished = Vec::new();
+        let mut processed = 0;
+        for scanned in self.scanner.feed(chunk) {
+            emulator.process(&chunk[processed..scanned.chunk_end]);
+            processed = scanned.chunk_end;
+            finished.extend(self.handle(&scanned, emulator));
+        }
+        emulator.process(&chunk[processed..]);
+        finished
+    }
+
+    fn handle(&mut self, scanned: &ScannedMark, emulator: &TerminalEmulator) -> Option<NewSessionCommand> {
+        match scanned.mark {
+            PromptMark::PromptStart => {
+                self.input_start = None;
+                self.running = None;
+                None
+            }
+            PromptMark::CommandStart => {
+                self.input_start = Some(emulator.cursor_position());
+                None
+            }
+            PromptMark::OutputStart => {
+                let command = self
+                    .input_start
+                    .take()
+                    .map(|start| emulator.text_between(start, emulator.cursor_position()))
+                    .unwrap_or_default();
+                self.running = Some(RunningCommand {
+                    command: command.trim().to_string(),
+                    output_start: scanned.end,
+                    started_at: Utc::now(),
+                    started: Instant::now(),
+                });
+                None
+            }
+            PromptMark::CommandFinished { exit_code } => {
+                let running = self.running.take()?;
+                Some(NewSessionCommand {
+                    session_id: self.session_id.clone(),
+                    command: running.command,
+                    exit_code,
+                    output_start: running.output_start as i64,
+                    output_end: scanned.start as i64,
+                    started_at: running.started_at.format("%Y-%m-%d %H:%M:%S").to_string(),
+                    ended_at: Utc::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+                    duration_ms: running.started.elapsed().as_millis() as i64,
+                })
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scanner_offsets_across_chunks() {
+        let mut scanner = PromptMarkScanner::default();
+        assert!(scanner.feed(b"out\x1b]13").is_empty());
+        let marks = scanner.feed(b"3;D;2\x1b\\x\x1b]133;A\x07");
+        assert_eq!(marks.len(), 2);
+        assert_eq!(marks[0].mark, PromptMark::CommandFinished { exit_code: Some(2) });
+        assert_eq!((marks[0].start, marks[0].end, marks[0].chunk_end), (3, 14, 7));
+        assert_eq!(marks[1].mark, PromptMark::PromptStart);
+        assert_eq!((marks[1].start, marks[1].end), (15, 23));
+    }
+
+    #[test]
+    fn test_tracks_command_line_and_output_range() {
+        let mut emulator = TerminalEmulator::new(10, 40);
+        let mut tracker = CommandTracker::new("s", 0);
+        let transcript: &[u8] =
+            b"\x1b]133;A\x07$ \x1b]133;B\x07ls -la\r\n\x1b]133;C\x07file.txt\r\n\x1b]133;D;0\x07\x1b]133;A\x07$ ";
+        let commands = tracker.process(&mut emulator, transcript);
+        assert_eq!(commands.len(), 1);
+        let command = &commands[0];
+        assert_eq!(command.command, "ls -la");
+        assert_eq!(command.exit_code, Some(0));
+        let output = &transcript[command.output_start as usize..command.output_end as usize];
+        assert_eq!(output, b"file.txt\r\n");
+    }
+}
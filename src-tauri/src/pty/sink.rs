@@ -0,0 +1,147 @@
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+use tauri::ipc::Channel;
+use tauri::{AppHandle, Emitter};
+
+use crate::error::{AppError, AppResult};
+use crate::models::session::{PtyOutputEvent, SessionActivityEvent, SessionRestartEvent, SessionTitleEvent};
+
+/// Receives a session's output stream. The app uses Tauri channels; tests use [`MemorySink`].
+pub trait OutputSink: Send + 'static {
+    fn send(&self, event: PtyOutputEvent) -> AppResult<()>;
+}
+
+impl OutputSink for Channel<PtyOutputEvent> {
+    fn send(&self, event: PtyOutputEvent) -> AppResult<()> {
+        Channel::send(self, event).map_err(|e| AppError::Pty(format!("Failed to send output: {e}")))
+    }
+}
+
+/// Session-level notifications that are not part of the output stream.
+#[derive(Debug, Clone)]
+pub enum SessionEvent {
+    ActivityChanged(SessionActivityEvent),
+    TitleChanged(SessionTitleEvent),
+    Restarting(SessionRestartEvent),
+}
+
+/// Receives session events. The app forwards them as Tauri events.
+pub trait EventSink: Send + Sync + 'static {
+    fn emit(&self, event: SessionEvent);
+}
+
+impl EventSink for AppHandle {
+    fn emit(&self, event: SessionEvent) {
+        let _ = match event {
+            SessionEvent::ActivityChanged(event) => Emitter::emit(self, "session-activity-change", event),
+            SessionEvent::TitleChanged(event) => Emitter::emit(self, "session-title-changed", event),
+            SessionEvent::Restarting(event) => Emitter::emit(self, "session-restarting", event),
+        };
+    }
+}
+
+/// Collects events in memory so sessions can be driven without a running app.
+#[derive(Clone)]
+pub struct MemorySink<T> {
+    inner: Arc<(Mutex<Vec<T>>, Condvar)>,
+}
+
+impl<T> Default for MemorySink<T> {
+    fn default() -> Self {
+        MemorySink {
+            inner: Arc::new((Mutex::new(Vec::new()), Condvar::new())),
+        }
+    }
+}
+
+impl<T: Clone> MemorySink<T> {
+    fn push(&self, event: T) {
+        let (events, cond) = &*self.inner;
+        if let Ok(mut events) = events.lock() {
+            events.push(event);
+        }
+        cond.notify_all();
+    }
+
+    pub fn events(&self) -> Vec<T> {
+        self.inner.0.lock().map(|events| events.clone()).unwrap_or_default()
+    }
+
+    /// Blocks until `predicate` holds for the collected events or `timeout` elapses.
+    /// Returns whether the predicate was satisfied.
+    pub fn wait_until(&self, timeout: Duration, predicate: impl Fn(&[T]) -> bool) -> bool {
+        let (events, cond) = &*self.inner;
+        let deadline = Instant::now() + timeout;
+        let Ok(mut guard) = events.lock() else {
+            return false;
+        };
+        loop {
+            if predicate(&guard) {
+                return true;
+            }
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return false;
+            }
+            guard = match cond.wait_timeout(guard, remaining) {
+                Ok((guard, _)) => guard,
+                Err(_) => return false,
+            };
+        }
+    }
+}
+
+impl MemorySink<PtyOutputEvent> {
+    /// All output bytes received so far, concatenated.
+    pub fn output(&self) -> Vec<u8> {
+        output_of(&self.events())
+    }
+
+    /// `Some(exit_code)` once the Exited event has arrived.
+    pub fn exit_code(&self) -> Option<Option<i32>> {
+        exit_code_of(&self.events())
+    }
+
+    pub fn wait_for_output(&self, needle: &str, timeout: Duration) -> bool {
+        self.wait_until(timeout, |events| {
+            String::from_utf8_lossy(&output_of(events)).contains(needle)
+        })
+    }
+
+    pub fn wait_for_exit(&self, timeout: Duration) -> Option<Option<i32>> {
+        self.wait_until(timeout, |events| exit_code_of(events).is_some());
+        self.exit_code()
+    }
+}
+
+impl OutputSink for MemorySink<PtyOutputEvent> {
+    fn send(&self, event: PtyOutputEvent) -> AppResult<()> {
+        self.push(event);
+        Ok(())
+    }
+}
+
+impl EventSink for MemorySink<SessionEvent> {
+    fn emit(&self, event: SessionEvent) {
+        self.push(event);
+    }
+}
+
+fn output_of(events: &[PtyOutputEvent]) -> Vec<u8> {
+    events
+        .iter()
+        .filter_map(|event| match event {
+            PtyOutputEvent::Data { data, .. } => Some(data.as_slice()),
+            _ => None,
+        })
+        .flatten()
+        .copied()
+        .collect()
+}
+
+fn exit_code_of(events: &[PtyOutputEvent]) -> Option<Option<i32>> {
+    events.iter().find_map(|event| match event {
+        PtyOutputEvent::Exited { exit_code, .. } => Some(*exit_code),
+        _ => None,
+    })
+}
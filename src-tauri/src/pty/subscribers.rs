@@ -0,0 +1,31 @@
+use std::collections::HashMap;
+
+use crate::models::session::PtyOutputEvent;
+use crate::pty::sink::OutputSink;
+
+pub type SubscriberId = u64;
+
+/// Sinks currently receiving a session's output.
+#[derive(Default)]
+pub struct SubscriberRegistry {
+    sinks: HashMap<SubscriberId, Box<dyn OutputSink>>,
+}
+
+impl SubscriberRegistry {
+    pub fn insert(&mut self, id: SubscriberId, sink: Box<dyn OutputSink>) {
+        self.sinks.insert(id, sink);
+    }
+
+    pub fn remove(&mut self, id: SubscriberId) -> bool {
+        self.sinks.remove(&id).is_some()
+    }
+
+    pub fn clear(&mut self) {
+        self.sinks.clear();
+    }
+
+    /// Sends the event to every subscriber, dropping those whose sink is gone.
+    pub fn broadcast(&mut self, event: &PtyOutputEvent) {
+        self.sinks.retain(|_, sink| sink.send(event.clone()).is_ok());
+    }
+}
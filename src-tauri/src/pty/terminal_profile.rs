@@ -0,0 +1,120 @@
+use std::collections::HashMap;
+
+use crate::models::session::{TerminalEnvironment, TerminalProfile};
+
+pub const DEFAULT_TERM: &str = "xterm-256color";
+/// Used when neither the profile nor the inherited environment names a UTF-8 locale.
+pub const DEFAULT_LOCALE: &str = "en_US.UTF-8";
+pub const TERM_PROGRAM: &str = "AgentDesk";
+
+/// Resolves the terminal variables for a session. `inherited` is the environment the
+/// process would otherwise get (login shell over the app's own); `overrides` are the
+/// session's `env_vars`, which take precedence over everything here.
+pub fn resolve(
+    profile: &TerminalProfile,
+    inherited: &HashMap<String, String>,
+    overrides: &HashMap<String, String>,
+) -> TerminalEnvironment {
+    let mut env = TerminalEnvironment::default();
+    let lookup = |name: &str| {
+        inherited
+            .get(name)
+            .cloned()
+            .or_else(|| std::env::var(name).ok())
+    };
+
+    env.vars.insert(
+        "TERM".to_string(),
+        profile.term.clone().unwrap_or_else(|| DEFAULT_TERM.to_string()),
+    );
+    if profile.truecolor.unwrap_or(true) {
+        env.vars.insert("COLORTERM".to_string(), "truecolor".to_string());
+    } else {
+        env.unset.push("COLORTERM".to_string());
+    }
+
+    let inherited_lc_all = lookup("LC_ALL").filter(|value| !value.is_empty());
+    let locale = profile.locale.clone().unwrap_or_else(|| {
+        [inherited_lc_all.clone(), lookup("LANG")]
+            .into_iter()
+            .flatten()
+            .find(|locale| is_utf8_locale(locale))
+            .unwrap_or_else(|| DEFAULT_LOCALE.to_string())
+    });
+    // LC_ALL beats LANG, so an inherited non-UTF-8 value (often `C`) has to be replaced too
+    let replace_lc_all = profile.locale.is_some()
+        || inherited_lc_all.is_some_and(|lc_all| !is_utf8_locale(&lc_all));
+    if replace_lc_all {
+        env.vars.insert("LC_ALL".to_string(), locale.clone());
+    }
+    env.vars.insert("LANG".to_string(), locale);
+
+    env.vars.insert("TERM_PROGRAM".to_string(), TERM_PROGRAM.to_string());
+    env.vars.insert(
+        "TERM_PROGRAM_VERSION".to_string(),
+        env!("CARGO_PKG_VERSION").to_string(),
+    );
+    for (name, value) in &profile.extra_env {
+        env.vars.insert(name.clone(), value.clone());
+    }
+
+    for (name, value) in overrides {
+        if env.vars.contains_key(name) || env.unset.contains(name) {
+            env.unset.retain(|unset| unset != name);
+            env.vars.insert(name.clone(), value.clone());
+        }
+    }
+    env
+}
+
+fn is_utf8_locale(locale: &str) -> bool {
+    let lower = locale.to_ascii_lowercase();
+    lower.ends_with(".utf-8") || lower.ends_with(".utf8") || lower.contains(".utf-8@") || lower.contains(".utf8@")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn env(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+    }
+
+    #[test]
+    fn test_defaults() {
+        let inherited = env(&[("LANG", "de_DE.UTF-8"), ("LC_ALL", "")]);
+        let resolved = resolve(&TerminalProfile::default(), &inherited, &HashMap::new());
+        assert_eq!(resolved.vars["TERM"], DEFAULT_TERM);
+        assert_eq!(resolved.vars["COLORTERM"], "truecolor");
+        assert_eq!(resolved.vars["LANG"], "de_DE.UTF-8");
+        assert!(!resolved.vars.contains_key("LC_ALL"));
+        assert_eq!(resolved.vars["TERM_PROGRAM"], "AgentDesk");
+        assert!(resolved.unset.is_empty());
+    }
+
+    #[test]
+    fn test_replaces_non_utf8_locale() {
+        let inherited = env(&[("LANG", "C"), ("LC_ALL", "POSIX")]);
+        let resolved = resolve(&TerminalProfile::default(), &inherited, &HashMap::new());
+        assert_eq!(resolved.vars["LANG"], DEFAULT_LOCALE);
+        assert_eq!(resolved.vars["LC_ALL"], DEFAULT_LOCALE);
+    }
+
+    #[test]
+    fn test_profile_and_env_vars_override_defaults() {
+        let profile = TerminalProfile {
+            term: Some("screen-256color".to_string()),
+            truecolor: Some(false),
+            locale: Some("ja_JP.UTF-8".to_string()),
+            extra_env: env(&[("AGENTDESK_PANE", "left")]),
+        };
+        let overrides = env(&[("TERM", "dumb"), ("EDITOR", "vi")]);
+        let resolved = resolve(&profile, &HashMap::new(), &overrides);
+        assert_eq!(resolved.vars["TERM"], "dumb");
+        assert_eq!(resolved.vars["LC_ALL"], "ja_JP.UTF-8");
+        assert_eq!(resolved.vars["AGENTDESK_PANE"], "left");
+        assert_eq!(resolved.unset, vec!["COLORTERM"]);
+        // Unrelated session variables are not part of the terminal profile
+        assert!(!resolved.vars.contains_key("EDITOR"));
+    }
+}
@@ -1,169 +1,289 @@
-use crate::models::monitoring::RecordTokenUsage;
+use std::sync::Arc;
 
-/// Scans text output for token usage patterns from various AI CLI tools.
-/// Returns extracted token records when patterns are detected.
-pub fn extract_token_usage(text: &str, session_id: &str) -> Vec<RecordTokenUsage> {
-    let mut results = Vec::new();
+use regex::{Captures, Regex};
 
-    // Claude Code patterns:
-    //   "Total cost: $0.1234"
-    //   "Total input tokens: 12345" / "Total output tokens: 6789"
-    //   "Session cost: $0.12 (12K input, 6K output)"
-    if let Some(record) = parse_claude_code(text, session_id) {
-        results.push(record);
-    }
+use crate::models::monitoring::RecordTokenUsage;
+use crate::pty::usage_rules::UsageRules;
+use crate::tools::ToolAdapter;
 
-    // Aider pattern:
-    //   "Tokens: 12.3k sent, 4.5k received. Cost: $0.04"
-    if let Some(record) = parse_aider(text, session_id) {
-        results.push(record);
-    }
+/// A block of output is parsed after this many lines even if no blank line ended it.
+const MAX_BLOCK_LINES: usize = 32;
+/// Bytes of a line past this are dropped; no usage line is anywhere near as long.
+const MAX_LINE_LEN: usize = 4096;
+/// CSI parameters past this are dropped; only short ones are ever interpreted.
+const MAX_CSI_LEN: usize = 16;
 
-    results
+#[derive(Default)]
+enum EscapeState {
+    #[default]
+    Ground,
+    Escape,
+    /// `ESC` followed by intermediate bytes, e.g. `ESC ( B`.
+    EscapeIntermediate,
+    Csi,
+    /// OSC, DCS, SOS, PM and APC strings, up to BEL or ST.
+    String,
+    StringEscape,
 }
 
-fn parse_claude_code(text: &str, session_id: &str) -> Option<RecordTokenUsage> {
-    // Pattern: "Total cost: $X.XX" with nearby token counts
-    let cost = extract_dollar_amount(text, "total cost")?;
+/// Scans a session's raw output for the token usage patterns of its tool: the usage rules
+/// that apply to it, and whatever its adapter parses itself.
+///
+/// Output arrives in arbitrary chunks, so the parser keeps the unfinished line between
+/// them. Escape sequences are stripped and a carriage return followed by more text
+/// replaces the line, as a redraw does on screen. Complete lines are handed to the tool in
+/// blocks ending at a blank line, so multi-line summaries are parsed together, and the
+/// readings only depend on the output, never on where it was split.
+pub struct UsageParser {
+    session_id: String,
+    adapter: Arc<dyn ToolAdapter>,
+    rules: Arc<UsageRules>,
+    state: EscapeState,
+    csi: Vec<u8>,
+    line: Vec<u8>,
+    /// A carriage return was written; the next text starts the line over.
+    line_rewound: bool,
+    block: String,
+    block_lines: usize,
+    /// Position in the session's output of the next byte fed.
+    position: u64,
+    line_start: u64,
+    block_start: u64,
+}
 
-    let input_tokens = extract_token_count(text, &["input tokens", "input"])
-        .unwrap_or(0);
-    let output_tokens = extract_token_count(text, &["output tokens", "output"])
-        .unwrap_or(0);
+impl UsageParser {
+    /// `position` is where the output about to be fed starts in the session's output stream.
+    pub fn new(session_id: &str, adapter: Arc<dyn ToolAdapter>, rules: Arc<UsageRules>, position: u64) -> Self {
+        UsageParser {
+            session_id: session_id.to_string(),
+            adapter,
+            rules,
+            state: EscapeState::Ground,
+            csi: Vec::new(),
+            line: Vec::new(),
+            line_rewound: false,
+            block: String::new(),
+            block_lines: 0,
+            position,
+            line_start: position,
+            block_start: position,
+        }
+    }
 
-    Some(RecordTokenUsage {
-        session_id: session_id.to_string(),
-        input_tokens,
-        output_tokens,
-        model: "claude".to_string(),
-        cost_usd: cost,
-    })
-}
+    /// Returns the readings of the blocks `chunk` completed.
+    pub fn feed(&mut self, chunk: &[u8]) -> Vec<RecordTokenUsage> {
+        let mut usage = Vec::new();
+        for &byte in chunk {
+            match self.state {
+                EscapeState::Ground => self.text(byte, &mut usage),
+                EscapeState::Escape => self.after_escape(byte),
+                EscapeState::EscapeIntermediate => {
+                    if !(0x20..=0x2f).contains(&byte) {
+                        self.state = EscapeState::Ground;
+                    }
+                }
+                EscapeState::Csi => match byte {
+                    0x40..=0x7e => {
+                        self.state = EscapeState::Ground;
+                        self.csi_final(byte);
+                    }
+                    0x1b => self.state = EscapeState::Escape,
+                    _ if self.csi.len() < MAX_CSI_LEN => self.csi.push(byte),
+                    _ => {}
+                },
+                EscapeState::String => match byte {
+                    0x07 => self.state = EscapeState::Ground,
+                    0x1b => self.state = EscapeState::StringEscape,
+                    _ => {}
+                },
+                EscapeState::StringEscape => {
+                    if byte == b'\\' {
+                        self.state = EscapeState::Ground;
+                    } else {
+                        // The ESC aborted the string and starts a new sequence
+                        self.after_escape(byte);
+                    }
+                }
+            }
+            self.position += 1;
+        }
+        usage
+    }
 
-fn parse_aider(text: &str, session_id: &str) -> Option<RecordTokenUsage> {
-    // Pattern: "Tokens: 12.3k sent, 4.5k received. Cost: $0.04"
-    let lower = text.to_lowercase();
-    if !lower.contains("tokens:") || !lower.contains("sent") || !lower.contains("cost:") {
-        return None;
+    /// Parses what is left once the output ended, including an unterminated last line.
+    pub fn finish(&mut self) -> Vec<RecordTokenUsage> {
+        let mut usage = Vec::new();
+        if !self.line.is_empty() {
+            self.end_line(&mut usage);
+        }
+        self.end_block(&mut usage);
+        self.state = EscapeState::Ground;
+        usage
     }
 
-    let cost = extract_dollar_amount(text, "cost")?;
+    fn text(&mut self, byte: u8, usage: &mut Vec<RecordTokenUsage>) {
+        match byte {
+            0x1b => self.state = EscapeState::Escape,
+            b'\n' => self.end_line(usage),
+            b'\r' => self.line_rewound = true,
+            0x08 => {
+                // Backspace: drop the last character, continuation bytes first
+                while self.line.pop().is_some_and(|b| b & 0xc0 == 0x80) {}
+            }
+            b'\t' | 0x20..=0x7e | 0x80.. => {
+                if std::mem::take(&mut self.line_rewound) {
+                    self.line.clear();
+                }
+                if self.line.len() < MAX_LINE_LEN {
+                    self.line.push(byte);
+                }
+            }
+            _ => {}
+        }
+    }
 
-    // Parse "12.3k sent"
-    let input_tokens = parse_k_number(&lower, "sent").unwrap_or(0);
-    // Parse "4.5k received"
-    let output_tokens = parse_k_number(&lower, "received").unwrap_or(0);
+    fn after_escape(&mut self, byte: u8) {
+        self.state = match byte {
+            b'[' => {
+                self.csi.clear();
+                EscapeState::Csi
+            }
+            b']' | b'P' | b'X' | b'^' | b'_' => EscapeState::String,
+            0x1b => EscapeState::Escape,
+            0x20..=0x2f => EscapeState::EscapeIntermediate,
+            _ => EscapeState::Ground,
+        };
+    }
 
-    Some(RecordTokenUsage {
-        session_id: session_id.to_string(),
-        input_tokens,
-        output_tokens,
-        model: "aider".to_string(),
-        cost_usd: cost,
-    })
-}
+    /// Only the cursor moves that matter for reading a line back are applied.
+    fn csi_final(&mut self, byte: u8) {
+        match byte {
+            // Cursor forward stands in for spaces
+            b'C' if !self.line_rewound && self.line.len() < MAX_LINE_LEN => self.line.push(b' '),
+            // Erasing the whole line, or moving to its start, is a redraw like a carriage return
+            b'K' if self.csi == b"2" => self.line_rewound = true,
+            b'G' if matches!(self.csi.as_slice(), b"" | b"1" | b"0") => self.line_rewound = true,
+            _ => {}
+        }
+    }
 
-/// Extract a dollar amount after a keyword, e.g., "total cost: $1.23"
-fn extract_dollar_amount(text: &str, keyword: &str) -> Option<f64> {
-    let lower = text.to_lowercase();
-    let pos = lower.find(keyword)?;
-    let after = &text[pos + keyword.len()..];
-    // Find the $ sign
-    let dollar_pos = after.find('$')?;
-    let number_start = dollar_pos + 1;
-    let number_str: String = after[number_start..]
-        .chars()
-        .take_while(|c| c.is_ascii_digit() || *c == '.')
-        .collect();
-    number_str.parse::<f64>().ok()
-}
+    fn end_line(&mut self, usage: &mut Vec<RecordTokenUsage>) {
+        let line = String::from_utf8_lossy(&self.line).into_owned();
+        let line_start = std::mem::replace(&mut self.line_start, self.position + 1);
+        self.line.clear();
+        self.line_rewound = false;
+        if line.trim().is_empty() {
+            self.end_block(usage);
+            return;
+        }
+        if self.block.is_empty() {
+            self.block_start = line_start;
+        }
+        self.block.push_str(&line);
+        self.block.push('\n');
+        self.block_lines += 1;
+        if self.block_lines >= MAX_BLOCK_LINES {
+            self.end_block(usage);
+        }
+    }
 
-/// Extract token count near a keyword. Handles "12,345" and "12345" and "12K" and "12.3K"
-fn extract_token_count(text: &str, keywords: &[&str]) -> Option<i64> {
-    let lower = text.to_lowercase();
-    for keyword in keywords {
-        if let Some(pos) = lower.find(keyword) {
-            // Look before the keyword for a number
-            let before = &text[..pos];
-            if let Some(n) = parse_nearby_number(before, true) {
-                return Some(n);
-            }
-            // Look after the keyword for a number
-            let after = &text[pos + keyword.len()..];
-            if let Some(n) = parse_nearby_number(after, false) {
-                return Some(n);
-            }
+    fn end_block(&mut self, usage: &mut Vec<RecordTokenUsage>) {
+        if !self.block.is_empty() {
+            usage.extend(self.rules.parse(self.adapter.as_ref(), &self.block, &self.session_id, self.block_start));
+            usage.extend(self.adapter.parse_usage(&self.block, &self.session_id));
         }
+        self.block.clear();
+        self.block_lines = 0;
     }
-    None
 }
 
-/// Parse a number near the boundary of a string (before keyword or after keyword)
-fn parse_nearby_number(text: &str, look_backwards: bool) -> Option<i64> {
-    let trimmed = text.trim().trim_matches(':').trim();
-    if look_backwards {
-        // Get the last number-like sequence
-        let num_str: String = trimmed
-            .chars()
-            .rev()
-            .take_while(|c| c.is_ascii_digit() || *c == ',' || *c == '.' || *c == 'k' || *c == 'K')
-            .collect::<String>()
-            .chars()
-            .rev()
-            .collect();
-        parse_token_number(&num_str)
-    } else {
-        // Get the first number-like sequence
-        let start = trimmed.find(|c: char| c.is_ascii_digit())?;
-        let num_str: String = trimmed[start..]
-            .chars()
-            .take_while(|c| c.is_ascii_digit() || *c == ',' || *c == '.' || *c == 'k' || *c == 'K')
-            .collect();
-        parse_token_number(&num_str)
+/// Usage captured by a user-supplied regex with the named groups `input`, `output`, `cost`
+/// and `model`, one record per match. `model` defaults to `default_model`.
+pub fn parse_pattern(pattern: &Regex, text: &str, session_id: &str, default_model: &str) -> Vec<RecordTokenUsage> {
+    pattern
+        .captures_iter(text)
+        .map(|captures| usage_from_captures(&captures, session_id, default_model))
+        .collect()
+}
+
+/// The reading in one match of a usage regex. Besides `input`, `output`, `cost` and `model`
+/// it may capture `cache_read` and `cache_creation`; missing groups count as zero.
+pub fn usage_from_captures(captures: &Captures, session_id: &str, default_model: &str) -> RecordTokenUsage {
+    let group = |name: &str| captures.name(name).map(|m| m.as_str());
+    let tokens = |name: &str| group(name).and_then(parse_token_number).unwrap_or(0);
+    RecordTokenUsage {
+        session_id: session_id.to_string(),
+        input_tokens: tokens("input"),
+        output_tokens: tokens("output"),
+        cache_read_tokens: tokens("cache_read"),
+        cache_creation_tokens: tokens("cache_creation"),
+        model: group("model").unwrap_or(default_model).to_string(),
+        cost_usd: group("cost")
+            .and_then(|cost| cost.trim_start_matches('$').replace(',', "").parse().ok())
+            .unwrap_or(0.0),
+        ..Default::default()
     }
 }
 
-/// Parse numbers like "12345", "12,345", "12K", "12.3k"
+/// Parse numbers like "12345", "12,345", "12K", "12.3k", "1.2M"
 fn parse_token_number(s: &str) -> Option<i64> {
     let s = s.trim();
-    if s.is_empty() {
-        return None;
-    }
-    let lower = s.to_lowercase();
-    if lower.ends_with('k') {
-        let num_part = &lower[..lower.len() - 1].replace(',', "");
-        let val: f64 = num_part.parse().ok()?;
-        Some((val * 1000.0) as i64)
+    let (number, scale) = match s.chars().last()? {
+        'k' | 'K' => (&s[..s.len() - 1], 1_000.0),
+        'm' | 'M' => (&s[..s.len() - 1], 1_000_000.0),
+        _ => (s, 1.0),
+    };
+    let cleaned = number.replace(',', "");
+    // If it has a decimal or a suffix, parse as float first
+    if cleaned.contains('.') || scale > 1.0 {
+        let val: f64 = cleaned.parse().ok()?;
+        Some((val * scale).round() as i64)
     } else {
-        let cleaned = s.replace(',', "");
-        // If it has a decimal, parse as float first
-        if cleaned.contains('.') {
-            let val: f64 = cleaned.parse().ok()?;
-            Some(val as i64)
-        } else {
-            cleaned.parse::<i64>().ok()
-        }
+        cleaned.parse::<i64>().ok()
     }
 }
 
-/// Parse "12.3k" before a keyword like "sent" or "received"
-fn parse_k_number(text: &str, keyword: &str) -> Option<i64> {
-    let pos = text.find(keyword)?;
-    let before = text[..pos].trim();
-    // Get the number right before the keyword
-    let parts: Vec<&str> = before.split_whitespace().collect();
-    let num_str = parts.last()?;
-    parse_token_number(num_str)
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::tools::{AiderAdapter, ClaudeCodeAdapter, CodexAdapter, CustomAdapter};
+
+    const AIDER_SESSION: &str = include_str!("../../tests/fixtures/terminal/aider_session.txt");
+    const AIDER_SESSION_ANSI: &[u8] = include_bytes!("../../tests/fixtures/terminal/aider_session_ansi.txt");
+
+    fn extract_token_usage(adapter: impl ToolAdapter + 'static, text: &str, session_id: &str) -> Vec<RecordTokenUsage> {
+        feed_chunks(adapter, &[text.as_bytes()], session_id)
+    }
+
+    fn feed_chunks(adapter: impl ToolAdapter + 'static, chunks: &[&[u8]], session_id: &str) -> Vec<RecordTokenUsage> {
+        let mut parser = UsageParser::new(session_id, Arc::new(adapter), Arc::default(), 0);
+        let mut usage: Vec<_> = chunks.iter().flat_map(|chunk| parser.feed(chunk)).collect();
+        usage.extend(parser.finish());
+        usage
+    }
+
+    /// Splits `bytes` at random points, reproducibly for a seed (xorshift).
+    fn random_chunks(bytes: &[u8], seed: u64) -> Vec<&[u8]> {
+        let mut state = seed.wrapping_mul(0x9e37_79b9_7f4a_7c15) | 1;
+        let mut chunks = Vec::new();
+        let mut rest = bytes;
+        while !rest.is_empty() {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            // Mostly short reads, now and then a large one
+            let max = if state.is_multiple_of(8) { 4096 } else { 24 };
+            let (chunk, tail) = rest.split_at(((state >> 8) as usize % max + 1).min(rest.len()));
+            chunks.push(chunk);
+            rest = tail;
+        }
+        chunks
+    }
 
     #[test]
     fn test_claude_code_cost() {
         let text = "Total cost: $1.23\nTotal input tokens: 12345\nTotal output tokens: 6789";
-        let results = extract_token_usage(text, "test-session");
+        let results = extract_token_usage(CustomAdapter::default(), text, "test-session");
         assert_eq!(results.len(), 1);
         assert_eq!(results[0].cost_usd, 1.23);
         assert_eq!(results[0].input_tokens, 12345);
@@ -173,7 +293,7 @@ mod tests {
     #[test]
     fn test_aider_pattern() {
         let text = "Tokens: 12.3k sent, 4.5k received. Cost: $0.04";
-        let results = extract_token_usage(text, "test-session");
+        let results = extract_token_usage(AiderAdapter, text, "test-session");
         assert!(!results.is_empty());
         let aider = results.iter().find(|r| r.model == "aider").unwrap();
         assert_eq!(aider.cost_usd, 0.04);
@@ -184,15 +304,101 @@ mod tests {
     #[test]
     fn test_no_match() {
         let text = "Hello world, this is normal output";
-        let results = extract_token_usage(text, "test-session");
+        let results = extract_token_usage(CustomAdapter::default(), text, "test-session");
         assert!(results.is_empty());
     }
 
+    #[test]
+    fn test_codex_pattern() {
+        let text = "Token usage: total=12,345 input=10,000 (+ 8,000 cached) output=2,345\n";
+        let results = extract_token_usage(CustomAdapter::default(), text, "test-session");
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].input_tokens, 10000);
+        assert_eq!(results[0].output_tokens, 2345);
+    }
+
+    #[test]
+    fn test_only_the_session_tool_is_parsed() {
+        let text = "Tokens: 12.3k sent, 4.5k received. Cost: $0.04";
+        assert!(extract_token_usage(ClaudeCodeAdapter, text, "test-session").is_empty());
+        // Claude Code and Codex usage comes from their transcripts
+        assert!(extract_token_usage(ClaudeCodeAdapter, "Total cost: $1.23", "test-session").is_empty());
+        let codex = "Token usage: total=12,345 input=10,000 (+ 8,000 cached) output=2,345\n";
+        assert!(extract_token_usage(CodexAdapter, codex, "test-session").is_empty());
+        assert_eq!(extract_token_usage(CustomAdapter::default(), text, "test-session").len(), 1);
+    }
+
+    #[test]
+    fn test_strips_escape_sequences_and_redraws() {
+        // Keyed by position, which the escape sequences shift
+        let unkeyed = |usage: Vec<RecordTokenUsage>| -> Vec<_> {
+            usage.into_iter().map(|usage| RecordTokenUsage { message_id: None, ..usage }).collect()
+        };
+        let clean = unkeyed(extract_token_usage(AiderAdapter, AIDER_SESSION, "s"));
+        assert_eq!(clean.len(), 3);
+        assert_eq!(unkeyed(feed_chunks(AiderAdapter, &[AIDER_SESSION_ANSI], "s")), clean);
+
+        let text = "\x1b[1mTotal cost:\x1b[0m $0.10\rTotal cost:\x1b[1C$0.25\r\n";
+        let results = extract_token_usage(CustomAdapter::default(), text, "s");
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].cost_usd, 0.25);
+    }
+
+    #[test]
+    fn test_patterns_split_across_chunks() {
+        let chunks: [&[u8]; 3] = [b"Total cost: $1.2", b"3\nTotal input tokens: 12", b"345\nTotal output tokens: 6789\n\n"];
+        let results = feed_chunks(CustomAdapter::default(), &chunks, "s");
+        assert_eq!(results.len(), 1);
+        assert_eq!((results[0].cost_usd, results[0].input_tokens, results[0].output_tokens), (1.23, 12345, 6789));
+
+        // A character split between reads no longer hides the rest of the output
+        let text = "⟶ Tokens: 4.2k sent, 180 received. Cost: $0.02 message, $0.02 session.\n".as_bytes();
+        let results = feed_chunks(AiderAdapter, &[&text[..1], &text[1..]], "s");
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].input_tokens, 4200);
+    }
+
+    #[test]
+    fn test_identical_usage_lines_are_separate_readings() {
+        let line = "Tokens: 1.2k sent, 80 received. Cost: $0.00 message, $0.01 session.\n";
+        let results = extract_token_usage(AiderAdapter, &format!("{line}{line}\n{line}"), "s");
+        assert_eq!(results.len(), 3);
+        let mut ids: Vec<_> = results.iter().map(|usage| usage.message_id.clone().unwrap()).collect();
+        ids.sort();
+        ids.dedup();
+        assert_eq!(ids.len(), 3);
+
+        // Later processes of the session continue the same stream
+        let mut parser = UsageParser::new("s", Arc::new(AiderAdapter), Arc::default(), 1000);
+        let mut later = parser.feed(line.as_bytes());
+        later.extend(parser.finish());
+        assert!(!ids.contains(later[0].message_id.as_ref().unwrap()));
+    }
+
+    #[test]
+    fn test_random_chunk_boundaries_give_identical_readings() {
+        let expected = feed_chunks(AiderAdapter, &[AIDER_SESSION_ANSI], "s");
+        for seed in 0..200 {
+            let chunks = random_chunks(AIDER_SESSION_ANSI, seed);
+            assert_eq!(feed_chunks(AiderAdapter, &chunks, "s"), expected, "seed {seed}");
+        }
+
+        let claude = include_bytes!("../../tests/fixtures/terminal/claude_cost.txt");
+        let expected = feed_chunks(CustomAdapter::default(), &[claude], "s");
+        assert_eq!(expected.len(), 4);
+        for seed in 0..200 {
+            let chunks = random_chunks(claude, seed);
+            assert_eq!(feed_chunks(CustomAdapter::default(), &chunks, "s"), expected, "seed {seed}");
+        }
+    }
+
     #[test]
     fn test_parse_k_numbers() {
         assert_eq!(parse_token_number("12.3k"), Some(12300));
         assert_eq!(parse_token_number("12K"), Some(12000));
         assert_eq!(parse_token_number("12,345"), Some(12345));
         assert_eq!(parse_token_number("12345"), Some(12345));
+        assert_eq!(parse_token_number("1.2M"), Some(1_200_000));
+        assert_eq!(parse_token_number("0.29k"), Some(290));
     }
 }
@@ -0,0 +1,462 @@
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::pty::persistence::{PersistJob, PersistenceWorker};
+use crate::tools::{ToolAdapter, TranscriptContext, TranscriptRecord};
+
+pub const IMPORT_POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Follows a file that is only ever appended to, handing out lines once they are complete.
+pub struct TranscriptTail {
+    path: PathBuf,
+    offset: u64,
+    partial: Vec<u8>,
+}
+
+impl TranscriptTail {
+    pub fn new(path: PathBuf) -> Self {
+        TranscriptTail {
+            path,
+            offset: 0,
+            partial: Vec::new(),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Lines completed since the last read, with the byte offset each starts at. A last line
+    /// without its newline is held back until the rest of it is written. A file that shrank
+    /// was rewritten and is read again from the start.
+    pub fn read_lines(&mut self) -> std::io::Result<Vec<(u64, String)>> {
+        let mut file = File::open(&self.path)?;
+        let len = file.metadata()?.len();
+        if len < self.offset {
+            self.offset = 0;
+            self.partial.clear();
+        }
+        let mut line_start = self.offset - self.partial.len() as u64;
+        file.seek(SeekFrom::Start(self.offset))?;
+        let read = file
+            .take(len - self.offset)
+            .read_to_end(&mut self.partial)?;
+        self.offset += read as u64;
+
+        let Some(end) = self.partial.iter().rposition(|&b| b == b'\n') else {
+            return Ok(Vec::new());
+        };
+        let complete: Vec<u8> = self.partial.drain(..=end).collect();
+        let mut lines = Vec::new();
+        for line in complete[..end].split(|&b| b == b'\n') {
+            let text = String::from_utf8_lossy(line);
+            if !text.trim().is_empty() {
+                lines.push((line_start, text.trim_end_matches('\r').to_string()));
+            }
+            line_start += line.len() as u64 + 1;
+        }
+        Ok(lines)
+    }
+}
+
+/// A transcript file being imported for a session.
+struct Transcript {
+    tail: TranscriptTail,
+    context: TranscriptContext,
+    /// Where the session's part of the file begins. Lines before it are still parsed, since
+    /// later lines may depend on them, but what they record is not imported.
+    start: u64,
+}
+
+/// A running session whose tool's transcripts are being imported.
+struct Watch {
+    session_id: String,
+    adapter: Arc<dyn ToolAdapter>,
+    working_dir: PathBuf,
+    env: HashMap<String, String>,
+    /// Sizes of the transcripts that already existed when the session started. Only what is
+    /// appended to them afterwards belongs to the session.
+    baseline: HashMap<PathBuf, u64>,
+    transcripts: Vec<Transcript>,
+    persistence: Arc<PersistenceWorker>,
+}
+
+impl Watch {
+    /// Whether `path` looks like it was written by this session: `Some(true)` if it is new
+    /// since the session started, `Some(false)` if it existed but has grown.
+    fn claim(&self, path: &Path, len: u64) -> Option<bool> {
+        match self.baseline.get(path) {
+            None => Some(true),
+            Some(&baseline) if len > baseline => Some(false),
+            Some(_) => None,
+        }
+    }
+}
+
+#[derive(Default)]
+struct Watches {
+    /// In the order the sessions started.
+    watches: Vec<Watch>,
+    /// Transcripts being read by some session.
+    claimed: HashSet<PathBuf>,
+}
+
+/// Imports the usage and conversation tools write to their own transcript files; their
+/// counts are exact where scraping the terminal is not. Each transcript is linked to the session running in its
+/// project directory when it appeared; when several sessions share a directory, a new
+/// transcript goes to the session started last.
+#[derive(Default)]
+pub struct UsageImporter {
+    state: Mutex<Watches>,
+}
+
+impl UsageImporter {
+    /// Starts the background thread that imports new transcript lines every `interval`.
+    pub fn start(self: &Arc<Self>, interval: Duration) {
+        let importer = Arc::downgrade(self);
+        std::thread::spawn(move || loop {
+            std::thread::sleep(interval);
+            let Some(importer) = importer.upgrade() else {
+                break;
+            };
+            importer.poll();
+        });
+    }
+
+    /// Begins importing transcripts for a session just started in `working_dir` with the
+    /// environment `env`.
+    pub fn watch(
+        &self,
+        session_id: &str,
+        adapter: Arc<dyn ToolAdapter>,
+        working_dir: &Path,
+        env: HashMap<String, String>,
+        persistence: Arc<PersistenceWorker>,
+    ) {
+        // Tools see the real path, without symlinks, as their working directory
+        let working_dir = if working_dir.as_os_str().is_empty() {
+            std::env::current_dir().unwrap_or_default()
+        } else {
+            working_dir.to_path_buf()
+        };
+        let working_dir = std::fs::canonicalize(&working_dir).unwrap_or(working_dir);
+        let baseline = adapter
+            .transcript_files(&working_dir, &env)
+            .into_iter()
+            .filter_map(|path| Some((path.clone(), std::fs::metadata(&path).ok()?.len())))
+            .collect();
+        if let Ok(mut state) = self.state.lock() {
+            state.watches.push(Watch {
+                session_id: session_id.to_string(),
+                adapter,
+                working_dir,
+                env,
+                baseline,
+                transcripts: Vec::new(),
+                persistence,
+            });
+        }
+    }
+
+    /// Imports what the session's transcripts still hold and stops following them.
+    pub fn unwatch(&self, session_id: &str) {
+        self.poll();
+        if let Ok(mut state) = self.state.lock() {
+            let Watches { watches, claimed } = &mut *state;
+            watches.retain(|watch| {
+                if watch.session_id != session_id {
+                    return true;
+                }
+                for transcript in &watch.transcripts {
+                    claimed.remove(transcript.tail.path());
+                }
+                false
+            });
+        }
+    }
+
+    /// Links new transcripts to their sessions and submits what was appended to them since
+    /// the last poll.
+    pub fn poll(&self) {
+        let Ok(mut state) = self.state.lock() else {
+            return;
+        };
+        let Watches { watches, claimed } = &mut *state;
+        Self::link_transcripts(watches, claimed);
+
+        for watch in watches.iter_mut() {
+            let mut usage = Vec::new();
+            let mut messages = Vec::new();
+            for transcript in &mut watch.transcripts {
+                let Ok(lines) = transcript.tail.read_lines() else {
+                    continue;
+                };
+                for (offset, line) in lines {
+                    transcript.context.offset = offset;
+                    let records =
+                        watch
+                            .adapter
+                            .parse_transcript_line(&line, &mut transcript.context, &watch.session_id);
+                    if offset < transcript.start {
+                        continue;
+                    }
+                    for record in records {
+                        match record {
+                            TranscriptRecord::Usage(record) => usage.push(record),
+                            TranscriptRecord::Message(message) => messages.push(message),
+                        }
+                    }
+                }
+            }
+            if !usage.is_empty() || !messages.is_empty() {
+                watch.persistence.submit(PersistJob::Transcript { usage, messages });
+            }
+        }
+    }
+
+    fn link_transcripts(watches: &mut [Watch], claimed: &mut HashSet<PathBuf>) {
+        let listings: Vec<Vec<PathBuf>> = watches
+            .iter()
+            .map(|watch| {
+                watch
+                    .adapter
+                    .transcript_files(&watch.working_dir, &watch.env)
+            })
+            .collect();
+        let candidates: HashSet<&PathBuf> = listings
+            .iter()
+            .flatten()
+            .filter(|path| !claimed.contains(*path))
+            .collect();
+        for path in candidates {
+            let Ok(metadata) = std::fs::metadata(path) else {
+                continue;
+            };
+            // Prefer a session the transcript is new to, then the one started last
+            let owner = watches
+                .iter()
+                .zip(&listings)
+                .enumerate()
+                .filter(|(_, (_, listing))| listing.contains(path))
+                .filter_map(|(index, (watch, _))| Some((watch.claim(path, metadata.len())?, index)))
+                .max();
+            if let Some((_, index)) = owner {
+                let watch = &mut watches[index];
+                watch.transcripts.push(Transcript {
+                    tail: TranscriptTail::new(path.clone()),
+                    context: TranscriptContext {
+                        source: path.file_stem().unwrap_or_default().to_string_lossy().into_owned(),
+                        ..Default::default()
+                    },
+                    start: watch.baseline.get(path).copied().unwrap_or(0),
+                });
+                claimed.insert(path.clone());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::{history_repo, migrations, monitoring_repo, DbPool};
+    use crate::tools::{ClaudeCodeAdapter, CodexAdapter};
+    use chrono::{Datelike, Local};
+    use std::io::Write;
+
+    const TRANSCRIPT: &str = include_str!("../../tests/fixtures/claude_code/transcript.jsonl");
+
+    struct Fixture {
+        dir: PathBuf,
+        db: Arc<DbPool>,
+        persistence: Arc<PersistenceWorker>,
+        env: HashMap<String, String>,
+        project: PathBuf,
+    }
+
+    impl Fixture {
+        fn new(name: &str) -> Self {
+            let dir =
+                std::env::temp_dir().join(format!("agentdesk-usage-{}-{name}", std::process::id()));
+            let _ = std::fs::remove_dir_all(&dir);
+            let db = Arc::new(DbPool::new(&dir.join("db")).unwrap());
+            db.with_conn(migrations::run_migrations).unwrap();
+            for id in ["a", "b"] {
+                db.with_conn(|conn| {
+                    conn.execute(
+                        "INSERT INTO sessions (id, name, tool, command, working_dir) VALUES (?1, ?1, 'ClaudeCode', 'claude', '/work/app')",
+                        [id],
+                    )
+                })
+                .unwrap();
+            }
+            let project = dir.join("claude").join("projects").join("-work-app");
+            std::fs::create_dir_all(&project).unwrap();
+            Fixture {
+                env: HashMap::from([(
+                    "CLAUDE_CONFIG_DIR".to_string(),
+                    dir.join("claude").display().to_string(),
+                )]),
+                persistence: Arc::new(PersistenceWorker::start(db.clone(), 16)),
+                db,
+                dir,
+                project,
+            }
+        }
+
+        fn watch(&self, importer: &UsageImporter, session_id: &str) {
+            importer.watch(
+                session_id,
+                Arc::new(ClaudeCodeAdapter),
+                Path::new("/work/app"),
+                self.env.clone(),
+                self.persistence.clone(),
+            );
+        }
+
+        fn append(&self, file: &str, text: &str) {
+            let mut file = std::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(self.project.join(file))
+                .unwrap();
+            file.write_all(text.as_bytes()).unwrap();
+        }
+
+        /// Output tokens and record count imported for the session.
+        fn usage(&self, session_id: &str) -> (i64, i64) {
+            assert!(self.persistence.sync(Duration::from_secs(5)));
+            let summary = self
+                .db
+                .with_conn(|conn| monitoring_repo::get_session_cost_summary(conn, session_id))
+                .unwrap();
+            (summary.total_output_tokens, summary.record_count)
+        }
+    }
+
+    impl Drop for Fixture {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.dir);
+        }
+    }
+
+    #[test]
+    fn test_tail_holds_back_partial_lines() {
+        let fixture = Fixture::new("tail");
+        let path = fixture.project.join("t.jsonl");
+        let mut tail = TranscriptTail::new(path.clone());
+        let line = |offset: u64, text: &str| (offset, text.to_string());
+        fixture.append("t.jsonl", "one\ntw");
+        assert_eq!(tail.read_lines().unwrap(), vec![line(0, "one")]);
+        fixture.append("t.jsonl", "o\r\n\nthree");
+        assert_eq!(tail.read_lines().unwrap(), vec![line(4, "two")]);
+        fixture.append("t.jsonl", "\n");
+        assert_eq!(tail.read_lines().unwrap(), vec![line(10, "three")]);
+
+        // Rewritten from scratch
+        std::fs::write(&path, "four\n").unwrap();
+        assert_eq!(tail.read_lines().unwrap(), vec![line(0, "four")]);
+    }
+
+    #[test]
+    fn test_imports_exact_usage_once() {
+        let fixture = Fixture::new("import");
+        let importer = UsageImporter::default();
+        fixture.append("earlier.jsonl", TRANSCRIPT);
+        fixture.watch(&importer, "a");
+
+        // Written a line at a time, split mid-line, as the tool streams
+        let (head, tail) = TRANSCRIPT.split_at(TRANSCRIPT.len() / 2);
+        fixture.append("conversation.jsonl", head);
+        importer.poll();
+        fixture.append("conversation.jsonl", tail);
+        importer.poll();
+        importer.unwatch("a");
+
+        // Two messages, the first written twice with its final counts last
+        assert_eq!(fixture.usage("a"), (96 + 58, 2));
+        let records = fixture
+            .db
+            .with_conn(|conn| monitoring_repo::get_session_usage(conn, "a"))
+            .unwrap();
+        assert!(records
+            .iter()
+            .all(|r| r.model == "claude-sonnet-4-20250514"));
+        assert_eq!(
+            records.iter().map(|r| r.cache_read_tokens).sum::<i64>(),
+            13872 + 20297
+        );
+
+        // Reading the transcript again, e.g. after a resume, does not count it twice
+        let importer = UsageImporter::default();
+        fixture.watch(&importer, "b");
+        fixture.append("conversation.jsonl", TRANSCRIPT);
+        importer.unwatch("b");
+        assert_eq!(fixture.usage("a"), (96 + 58, 2));
+        assert_eq!(fixture.usage("b"), (0, 0));
+    }
+
+    #[test]
+    fn test_links_transcripts_to_the_session_that_wrote_them() {
+        let fixture = Fixture::new("link");
+        let importer = UsageImporter::default();
+        fixture.watch(&importer, "a");
+        let first_message = TRANSCRIPT.lines().nth(2).unwrap();
+        fixture.append("first.jsonl", &format!("{first_message}\n"));
+
+        // Started after `first.jsonl` appeared, so that file stays with `a`
+        fixture.watch(&importer, "b");
+        fixture.append(
+            "first.jsonl",
+            &format!("{}\n", TRANSCRIPT.lines().nth(3).unwrap()),
+        );
+        fixture.append(
+            "second.jsonl",
+            &format!("{}\n", TRANSCRIPT.lines().nth(5).unwrap()),
+        );
+        importer.poll();
+
+        assert_eq!(fixture.usage("a"), (96, 1));
+        assert_eq!(fixture.usage("b"), (58, 1));
+    }
+
+    #[test]
+    fn test_imports_codex_rollouts_resumed_mid_file() {
+        let fixture = Fixture::new("codex");
+        let today = Local::now().date_naive();
+        let sessions = fixture
+            .dir
+            .join("codex")
+            .join("sessions")
+            .join(format!("{:04}", today.year()))
+            .join(format!("{:02}", today.month()))
+            .join(format!("{:02}", today.day()));
+        std::fs::create_dir_all(&sessions).unwrap();
+        let rollout = sessions.join("rollout-2025-10-03T09-41-27-0199a8d2.jsonl");
+        let rollout_text = include_str!("../../tests/fixtures/codex/rollout_2025_10.jsonl");
+        let (before, after) = rollout_text.split_at(rollout_text.match_indices('\n').nth(4).unwrap().0 + 1);
+        std::fs::write(&rollout, before).unwrap();
+
+        let importer = UsageImporter::default();
+        let env = HashMap::from([("CODEX_HOME".to_string(), fixture.dir.join("codex").display().to_string())]);
+        importer.watch("a", Arc::new(CodexAdapter), Path::new("/work/app"), env, fixture.persistence.clone());
+        std::fs::OpenOptions::new().append(true).open(&rollout).unwrap().write_all(after.as_bytes()).unwrap();
+        importer.unwatch("a");
+
+        // Only what was appended is imported, but the model comes from the earlier turn settings
+        assert_eq!(fixture.usage("a"), (421 + 87, 2));
+        let usage = fixture.db.with_conn(|conn| monitoring_repo::get_session_usage(conn, "a")).unwrap();
+        assert!(usage.iter().all(|record| record.model == "gpt-5-codex"));
+        let transcript = fixture
+            .db
+            .with_conn(|conn| history_repo::list_transcript_messages(conn, "a"))
+            .unwrap();
+        let kinds: Vec<_> = transcript.iter().map(|m| m.kind.as_str()).collect();
+        assert_eq!(kinds, vec!["reasoning", "tool_call", "tool_output", "message"]);
+        assert_eq!(transcript[1].name.as_deref(), Some("apply_patch"));
+    }
+}
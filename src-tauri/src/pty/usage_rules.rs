@@ -0,0 +1,533 @@
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, SystemTime};
+
+use regex::Regex;
+use serde::Deserialize;
+
+use crate::models::monitoring::{RecordTokenUsage, UsageRuleSummary, UsageRulesStatus};
+use crate::pty::token_parser;
+use crate::tools::ToolAdapter;
+
+pub const RULES_POLL_INTERVAL: Duration = Duration::from_secs(2);
+/// Names the user's rules file may have in the app data dir, in JSON or TOML. The first
+/// that exists is read.
+pub const USER_RULES_FILES: [&str; 2] = ["usage_rules.json", "usage_rules.toml"];
+
+const BUNDLED_RULES: &str = include_str!("../../rules/usage_rules.json");
+const USAGE_GROUPS: [&str; 5] = ["input", "output", "cache_read", "cache_creation", "cost"];
+/// Readings differing by less than this in cost match a sample's expectation.
+const COST_TOLERANCE: f64 = 1e-9;
+
+/// A rules file: the bundled one, or the user's, whose rules replace bundled rules of the
+/// same name.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RuleFile {
+    rules: Vec<RuleDefinition>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+struct RuleDefinition {
+    name: String,
+    /// `CliTool` names (e.g. `Aider`, `Custom`) or saved custom tool names the rule applies to.
+    tools: Vec<String>,
+    /// Named groups `input`, `output`, `cache_read`, `cache_creation`, `cost` and `model`.
+    /// Token counts may use `,` separators and `k`/`M` suffixes.
+    pattern: String,
+    /// Model recorded when the pattern has no `model` group.
+    #[serde(default)]
+    model: Option<String>,
+    /// Whether matches are the session's running totals rather than one message's usage.
+    #[serde(default)]
+    cumulative: bool,
+    /// Whether a reading is identified by where it appears in the session's output, so
+    /// parsing the same output again records it once.
+    #[serde(default)]
+    keyed: bool,
+    /// Example output the rule is checked against when it is loaded.
+    #[serde(default)]
+    samples: Vec<RuleSample>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RuleSample {
+    text: String,
+    /// One entry per reading the text should give, in order.
+    expect: Vec<ExpectedUsage>,
+}
+
+/// What a sample reading should contain; fields left out are not checked.
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+struct ExpectedUsage {
+    input_tokens: Option<i64>,
+    output_tokens: Option<i64>,
+    cache_read_tokens: Option<i64>,
+    cache_creation_tokens: Option<i64>,
+    cost_usd: Option<f64>,
+    model: Option<String>,
+}
+
+/// A validated rule, ready to match output.
+#[derive(Debug)]
+pub struct UsageRule {
+    name: String,
+    tools: Vec<String>,
+    pattern: Regex,
+    model: String,
+    cumulative: bool,
+    keyed: bool,
+    user: bool,
+}
+
+impl UsageRule {
+    /// The rule, or why it is invalid.
+    fn compile(definition: &RuleDefinition, user: bool) -> Result<Self, String> {
+        let invalid = |message: String| format!("Usage rule '{}': {message}", definition.name);
+        if definition.name.trim().is_empty() {
+            return Err("Usage rule name is empty".to_string());
+        }
+        if definition.tools.iter().all(|tool| tool.trim().is_empty()) {
+            return Err(invalid("no tools to apply to".to_string()));
+        }
+        let pattern = Regex::new(&definition.pattern).map_err(|e| invalid(format!("invalid regex: {e}")))?;
+        if !pattern.capture_names().flatten().any(|name| USAGE_GROUPS.contains(&name)) {
+            return Err(invalid(format!(
+                "pattern must capture at least one of {}",
+                USAGE_GROUPS.map(|group| format!("(?P<{group}>…)")).join(", ")
+            )));
+        }
+        let rule = UsageRule {
+            name: definition.name.clone(),
+            tools: definition.tools.clone(),
+            pattern,
+            model: definition.model.clone().unwrap_or_else(|| definition.name.clone()),
+            cumulative: definition.cumulative,
+            keyed: definition.keyed,
+            user,
+        };
+        for (i, sample) in definition.samples.iter().enumerate() {
+            rule.check_sample(sample).map_err(|message| invalid(format!("sample {}: {message}", i + 1)))?;
+        }
+        Ok(rule)
+    }
+
+    fn applies_to(&self, tools: &[String]) -> bool {
+        self.tools.iter().any(|tool| tools.contains(tool))
+    }
+
+    /// One reading per match in `text`, which starts at `position` in the session's output.
+    pub fn parse(&self, text: &str, session_id: &str, position: u64) -> Vec<RecordTokenUsage> {
+        self.pattern
+            .captures_iter(text)
+            .map(|captures| {
+                let mut usage = token_parser::usage_from_captures(&captures, session_id, &self.model);
+                usage.cumulative = self.cumulative;
+                if self.keyed {
+                    // Text is output with escapes stripped, so the match's offset in it only
+                    // identifies the reading together with where the text starts
+                    let start = captures.get(0).map_or(0, |m| m.start());
+                    usage.message_id = Some(format!("{}:{session_id}:{position}+{start}", self.name));
+                }
+                usage
+            })
+            .collect()
+    }
+
+    fn check_sample(&self, sample: &RuleSample) -> Result<(), String> {
+        let readings = self.parse(&sample.text, "sample", 0);
+        if readings.len() != sample.expect.len() {
+            return Err(format!(
+                "expected {} reading(s) from {:?}, got {}",
+                sample.expect.len(),
+                sample.text,
+                readings.len()
+            ));
+        }
+        for (reading, expected) in readings.iter().zip(&sample.expect) {
+            let mismatch = |field: &str, expected: &dyn std::fmt::Debug, actual: &dyn std::fmt::Debug| {
+                Err(format!("{field} of {:?} is {actual:?}, expected {expected:?}", sample.text))
+            };
+            let counts = [
+                ("inputTokens", expected.input_tokens, reading.input_tokens),
+                ("outputTokens", expected.output_tokens, reading.output_tokens),
+                ("cacheReadTokens", expected.cache_read_tokens, reading.cache_read_tokens),
+                ("cacheCreationTokens", expected.cache_creation_tokens, reading.cache_creation_tokens),
+            ];
+            for (field, expected, actual) in counts {
+                if let Some(expected) = expected.filter(|expected| *expected != actual) {
+                    return mismatch(field, &expected, &actual);
+                }
+            }
+            if let Some(cost) = expected.cost_usd.filter(|cost| (cost - reading.cost_usd).abs() > COST_TOLERANCE) {
+                return mismatch("costUsd", &cost, &reading.cost_usd);
+            }
+            if let Some(model) = expected.model.as_ref().filter(|model| **model != reading.model) {
+                return mismatch("model", model, &reading.model);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The rules in effect: the bundled rules plus those in the user's file, which is read
+/// again whenever it changes.
+struct LoadedRules {
+    rules: Arc<Vec<UsageRule>>,
+    /// Why the user's file, or some of its rules, could not be used.
+    errors: Vec<String>,
+    /// Path, modification time and length of the user's file when it was read.
+    stamp: Option<(PathBuf, SystemTime, u64)>,
+}
+
+pub struct UsageRules {
+    /// Where the user's file may be, in order of preference.
+    user_files: Mutex<Vec<PathBuf>>,
+    loaded: RwLock<LoadedRules>,
+}
+
+impl Default for UsageRules {
+    fn default() -> Self {
+        UsageRules {
+            user_files: Mutex::new(Vec::new()),
+            loaded: RwLock::new(LoadedRules {
+                rules: Arc::new(bundled_rules()),
+                errors: Vec::new(),
+                stamp: None,
+            }),
+        }
+    }
+}
+
+impl UsageRules {
+    /// Reads the user's rules from the first of `paths` that exists from now on, and loads them.
+    pub fn set_user_files(&self, paths: Vec<PathBuf>) {
+        if let Ok(mut user_files) = self.user_files.lock() {
+            *user_files = paths;
+        }
+        self.reload(true);
+    }
+
+    /// Polls the user's file every `interval` and reloads it when it changes.
+    pub fn start(self: &Arc<Self>, interval: Duration) {
+        let rules = Arc::downgrade(self);
+        std::thread::spawn(move || loop {
+            std::thread::sleep(interval);
+            let Some(rules) = rules.upgrade() else {
+                break;
+            };
+            rules.reload(false);
+        });
+    }
+
+    /// Loads the user's file again if it changed since it was last read, or if `force` is set.
+    pub fn reload(&self, force: bool) {
+        let Ok(user_files) = self.user_files.lock().map(|paths| paths.clone()) else {
+            return;
+        };
+        let stamp = user_files.iter().find_map(|path| file_stamp(path));
+        if !force && self.loaded.read().is_ok_and(|loaded| loaded.stamp == stamp) {
+            return;
+        }
+        let (rules, errors) = match &stamp {
+            Some((path, ..)) => load_user_rules(path),
+            None => (bundled_rules(), Vec::new()),
+        };
+        if let Ok(mut loaded) = self.loaded.write() {
+            *loaded = LoadedRules {
+                rules: Arc::new(rules),
+                errors,
+                stamp,
+            };
+        }
+    }
+
+    /// Readings from the rules that apply to `adapter`'s tool. `position` is where `text`
+    /// starts in the session's output.
+    pub fn parse(&self, adapter: &dyn ToolAdapter, text: &str, session_id: &str, position: u64) -> Vec<RecordTokenUsage> {
+        let tools = adapter.usage_rule_tools();
+        if tools.is_empty() {
+            return Vec::new();
+        }
+        let Some(rules) = self.loaded.read().ok().map(|loaded| loaded.rules.clone()) else {
+            return Vec::new();
+        };
+        rules
+            .iter()
+            .filter(|rule| rule.applies_to(&tools))
+            .flat_map(|rule| rule.parse(text, session_id, position))
+            .collect()
+    }
+
+    pub fn status(&self) -> UsageRulesStatus {
+        let Ok(loaded) = self.loaded.read() else {
+            return UsageRulesStatus {
+                user_file: None,
+                rules: Vec::new(),
+                errors: Vec::new(),
+            };
+        };
+        // The file that was read, or where a new one would be
+        let user_file = match &loaded.stamp {
+            Some((path, ..)) => Some(path.clone()),
+            None => self.user_files.lock().ok().and_then(|paths| paths.first().cloned()),
+        }
+        .map(|path| path.display().to_string());
+        UsageRulesStatus {
+            user_file,
+            rules: loaded
+                .rules
+                .iter()
+                .map(|rule| UsageRuleSummary {
+                    name: rule.name.clone(),
+                    tools: rule.tools.clone(),
+                    cumulative: rule.cumulative,
+                    user: rule.user,
+                })
+                .collect(),
+            errors: loaded.errors.clone(),
+        }
+    }
+}
+
+fn file_stamp(path: &Path) -> Option<(PathBuf, SystemTime, u64)> {
+    let metadata = std::fs::metadata(path).ok()?;
+    Some((path.to_path_buf(), metadata.modified().ok()?, metadata.len()))
+}
+
+/// The rules shipped with the app. They are checked by the tests, so none is dropped.
+fn bundled_rules() -> Vec<UsageRule> {
+    let (rules, _) = compile_rules(BUNDLED_RULES, RulesFormat::Json, false);
+    rules
+}
+
+#[derive(Debug, Clone, Copy)]
+enum RulesFormat {
+    Json,
+    Toml,
+}
+
+impl RulesFormat {
+    /// TOML for a `.toml` file, JSON otherwise.
+    fn of(path: &Path) -> Self {
+        match path.extension() {
+            Some(extension) if extension.eq_ignore_ascii_case("toml") => RulesFormat::Toml,
+            _ => RulesFormat::Json,
+        }
+    }
+}
+
+/// Compiles every valid rule in a rules file, with an error for each invalid one.
+fn compile_rules(text: &str, format: RulesFormat, user: bool) -> (Vec<UsageRule>, Vec<String>) {
+    let file: Result<RuleFile, String> = match format {
+        RulesFormat::Json => serde_json::from_str(text).map_err(|e| e.to_string()),
+        RulesFormat::Toml => toml::from_str(text).map_err(|e| e.to_string()),
+    };
+    let file = match file {
+        Ok(file) => file,
+        Err(e) => return (Vec::new(), vec![format!("Invalid usage rules file: {e}")]),
+    };
+    let mut rules: Vec<UsageRule> = Vec::new();
+    let mut errors = Vec::new();
+    for definition in &file.rules {
+        if rules.iter().any(|rule| rule.name == definition.name) {
+            errors.push(format!("Usage rule '{}' is defined twice", definition.name));
+            continue;
+        }
+        match UsageRule::compile(definition, user) {
+            Ok(rule) => rules.push(rule),
+            Err(e) => errors.push(e),
+        }
+    }
+    (rules, errors)
+}
+
+/// The bundled rules with the user's rules from `path` added, each replacing the bundled
+/// rule of the same name. Invalid user rules are left out and reported.
+fn load_user_rules(path: &Path) -> (Vec<UsageRule>, Vec<String>) {
+    let (user_rules, errors) = match std::fs::read_to_string(path) {
+        Ok(text) => compile_rules(&text, RulesFormat::of(path), true),
+        Err(e) => (Vec::new(), vec![format!("Failed to read usage rules: {e}")]),
+    };
+    let errors = errors
+        .into_iter()
+        .map(|error| format!("{}: {error}", path.display()))
+        .collect();
+    let mut rules: Vec<UsageRule> = bundled_rules()
+        .into_iter()
+        .filter(|rule| !user_rules.iter().any(|user_rule| user_rule.name == rule.name))
+        .collect();
+    rules.extend(user_rules);
+    (rules, errors)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tools::{AiderAdapter, ClaudeCodeAdapter, CustomAdapter};
+
+    fn rules_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("agentdesk-rules-{name}-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn rules_file(name: &str, json: &str) -> PathBuf {
+        let path = rules_dir(name).join(USER_RULES_FILES[0]);
+        std::fs::write(&path, json).unwrap();
+        path
+    }
+
+    fn user_files(dir: &Path) -> Vec<PathBuf> {
+        USER_RULES_FILES.iter().map(|name| dir.join(name)).collect()
+    }
+
+    #[test]
+    fn test_bundled_rules_pass_their_samples() {
+        let file: RuleFile = serde_json::from_str(BUNDLED_RULES).unwrap();
+        let (rules, errors) = compile_rules(BUNDLED_RULES, RulesFormat::Json, false);
+        assert_eq!(errors, Vec::<String>::new());
+        assert_eq!(rules.len(), file.rules.len());
+        for definition in &file.rules {
+            assert!(!definition.samples.is_empty(), "{} has no samples", definition.name);
+        }
+    }
+
+    #[test]
+    fn test_rejects_invalid_rules_with_clear_errors() {
+        let json = serde_json::json!({ "rules": [
+            { "name": "unclosed", "tools": ["Custom"], "pattern": "(?P<cost>\\d+" },
+            { "name": "no-groups", "tools": ["Custom"], "pattern": "\\d+ tokens" },
+            { "name": "no-tools", "tools": [], "pattern": "(?P<input>\\d+)" },
+            {
+                "name": "wrong-sample",
+                "tools": ["Custom"],
+                "pattern": "(?P<input>\\d+) in",
+                "samples": [{ "text": "12 in", "expect": [{ "inputTokens": 13 }] }]
+            },
+            { "name": "ok", "tools": ["Custom"], "pattern": "(?P<input>\\d+) in" },
+            { "name": "ok", "tools": ["Custom"], "pattern": "(?P<input>\\d+) in" },
+        ]});
+        let (rules, errors) = compile_rules(&json.to_string(), RulesFormat::Json, true);
+        assert_eq!(rules.len(), 1);
+        assert_eq!(errors.len(), 5);
+        assert!(errors[0].starts_with("Usage rule 'unclosed': invalid regex"), "{}", errors[0]);
+        assert!(errors[1].contains("must capture at least one of"), "{}", errors[1]);
+        assert!(errors[2].contains("no tools"), "{}", errors[2]);
+        assert_eq!(errors[3], "Usage rule 'wrong-sample': sample 1: inputTokens of \"12 in\" is 12, expected 13");
+        assert_eq!(errors[4], "Usage rule 'ok' is defined twice");
+
+        let (rules, errors) = compile_rules(r#"{ "rules": [{ "name": "x", "tool": "Aider" }] }"#, RulesFormat::Json, true);
+        assert!(rules.is_empty());
+        assert!(errors[0].starts_with("Invalid usage rules file"), "{}", errors[0]);
+    }
+
+    #[test]
+    fn test_user_rules_extend_and_replace_the_bundled_ones() {
+        let path = rules_file(
+            "user",
+            &serde_json::json!({ "rules": [
+                {
+                    "name": "gpt-cli",
+                    "tools": ["gpt"],
+                    "pattern": "(?i)(?P<input>[\\d.]+[km]?) in / (?P<output>[\\d.]+[km]?) out \\((?P<model>[\\w-]+)\\)",
+                    "samples": [{ "text": "1.5M in / 2k out (gpt-4o)", "expect": [{ "inputTokens": 1500000, "model": "gpt-4o" }] }]
+                },
+                { "name": "aider-message", "tools": ["Custom"], "pattern": "spent \\$(?P<cost>[\\d.]+)" },
+                { "name": "broken", "tools": ["gpt"], "pattern": "(" },
+            ]})
+            .to_string(),
+        );
+        let rules = UsageRules::default();
+        rules.set_user_files(user_files(path.parent().unwrap()));
+        let status = rules.status();
+        assert_eq!(status.errors.len(), 1, "{:?}", status.errors);
+        assert!(status.errors[0].starts_with(&path.display().to_string()), "{}", status.errors[0]);
+        let names: Vec<_> = status.rules.iter().map(|rule| (rule.name.as_str(), rule.user)).collect();
+        assert_eq!(
+            names,
+            vec![("claude-code-total", false), ("codex-total", false), ("gpt-cli", true), ("aider-message", true)]
+        );
+
+        // The replaced rule no longer applies to Aider
+        assert!(rules.parse(&AiderAdapter, "Tokens: 1k sent, 1k received. Cost: $0.01\n", "s", 0).is_empty());
+        let definition = serde_json::from_value(serde_json::json!({
+            "id": "t1",
+            "name": "gpt",
+            "command": "gpt-cli",
+            "args": [],
+            "envVars": {},
+            "iconLabel": "GPT",
+            "createdAt": "",
+            "updatedAt": "",
+        }))
+        .unwrap();
+        let gpt = CustomAdapter::from_definition(&definition).unwrap();
+        let usage = rules.parse(&gpt, "1.5M in / 2k out (gpt-4o)\nspent $0.25\n", "s", 0);
+        assert_eq!(usage.len(), 2);
+        assert_eq!((usage[0].output_tokens, usage[0].model.as_str()), (2000, "gpt-4o"));
+        assert_eq!((usage[1].cost_usd, usage[1].model.as_str()), (0.25, "aider-message"));
+        let _ = std::fs::remove_dir_all(path.parent().unwrap());
+    }
+
+    #[test]
+    fn test_reloads_when_the_file_changes() {
+        let path = rules_file("reload", r#"{ "rules": [] }"#);
+        let rules = UsageRules::default();
+        rules.set_user_files(user_files(path.parent().unwrap()));
+        let text = "Session spend: $0.50\n";
+        assert!(rules.parse(&CustomAdapter::default(), text, "s", 0).is_empty());
+
+        let json = r#"{ "rules": [{ "name": "spend", "tools": ["Custom"], "pattern": "spend: \\$(?P<cost>[\\d.]+)", "cumulative": true }] }"#;
+        std::fs::write(&path, json).unwrap();
+        rules.reload(false);
+        let usage = rules.parse(&CustomAdapter::default(), text, "s", 0);
+        assert_eq!(usage.len(), 1);
+        assert!(usage[0].cumulative);
+        // Rules only apply to the tools they name
+        assert!(rules.parse(&ClaudeCodeAdapter, text, "s", 0).is_empty());
+
+        // An invalid edit keeps the bundled rules and reports the problem
+        std::fs::write(&path, "{ not json").unwrap();
+        rules.reload(false);
+        assert!(rules.parse(&CustomAdapter::default(), text, "s", 0).is_empty());
+        assert_eq!(rules.status().errors.len(), 1);
+        assert_eq!(rules.status().rules.len(), 3);
+
+        std::fs::remove_file(&path).unwrap();
+        rules.reload(false);
+        assert!(rules.status().errors.is_empty());
+        let _ = std::fs::remove_dir_all(path.parent().unwrap());
+    }
+
+    #[test]
+    fn test_reads_rules_written_in_toml() {
+        let dir = rules_dir("toml");
+        let path = dir.join(USER_RULES_FILES[1]);
+        let toml = r#"
+            [[rules]]
+            name = "spend"
+            tools = ["Custom"]
+            pattern = 'spend: \$(?P<cost>[\d.]+)'
+            cumulative = true
+            samples = [{ text = "spend: $0.50", expect = [{ costUsd = 0.5 }] }]
+        "#;
+        std::fs::write(&path, toml).unwrap();
+        let rules = UsageRules::default();
+        rules.set_user_files(user_files(&dir));
+        let status = rules.status();
+        assert_eq!(status.errors, Vec::<String>::new());
+        assert_eq!(status.user_file, Some(path.display().to_string()));
+        let usage = rules.parse(&CustomAdapter::default(), "Session spend: $0.50\n", "s", 0);
+        assert_eq!(usage.len(), 1);
+        assert_eq!(usage[0].cost_usd, 0.5);
+
+        std::fs::write(&path, "[[rules]\n").unwrap();
+        rules.reload(false);
+        assert!(rules.status().errors[0].contains("Invalid usage rules file"), "{:?}", rules.status().errors);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
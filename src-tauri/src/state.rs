@@ -1,20 +1,26 @@
 use std::path::PathBuf;
 use std::sync::Arc;
+use tauri::AppHandle;
 
 use crate::db::DbPool;
+use crate::error::AppResult;
+use crate::pty::persistence::{PersistenceWorker, DEFAULT_QUEUE_CAPACITY};
 use crate::pty::PtyManager;
 
 pub struct AppState {
-    pub pty_manager: PtyManager,
+    pub pty_manager: Arc<PtyManager>,
     pub db: Arc<DbPool>,
+    pub persistence: Arc<PersistenceWorker>,
 }
 
 impl AppState {
-    pub fn new(app_dir: PathBuf) -> Result<Self, String> {
+    pub fn new(app_dir: PathBuf, app: AppHandle) -> AppResult<Self> {
         let db = Arc::new(DbPool::new(&app_dir)?);
+        let persistence = Arc::new(PersistenceWorker::start(db.clone(), DEFAULT_QUEUE_CAPACITY));
         Ok(AppState {
-            pty_manager: PtyManager::new(),
+            pty_manager: Arc::new(PtyManager::new(Arc::new(app))),
             db,
+            persistence,
         })
     }
 }
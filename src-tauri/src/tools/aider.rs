@@ -0,0 +1,41 @@
+use crate::models::session::{CliTool, ResumeTarget};
+use crate::tools::{last_lines, ToolAdapter};
+
+pub struct AiderAdapter;
+
+impl ToolAdapter for AiderAdapter {
+    fn tool(&self) -> CliTool {
+        CliTool::Aider
+    }
+
+    fn default_command(&self) -> &str {
+        "aider"
+    }
+
+    /// Aider's prompt is a bare `>` (prefixed with the mode, e.g. `ask>`), and confirmations
+    /// end in a `(Y)es/(N)o` choice.
+    fn is_awaiting_input(&self, screen: &str) -> bool {
+        last_lines(screen, 1).first().is_some_and(|line| {
+            line.split_whitespace().next().is_some_and(|word| word.ends_with('>'))
+                || line.contains("(Y)es/(N)o")
+        })
+    }
+
+    /// Aider has no conversation ids; it can only reload the chat history file.
+    fn resume_args(&self, _target: &ResumeTarget) -> Option<Vec<String>> {
+        Some(vec!["--restore-chat-history".to_string()])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detects_prompt() {
+        assert!(AiderAdapter.is_awaiting_input("Tokens: 1k sent, 200 received.\n>"));
+        assert!(AiderAdapter.is_awaiting_input("ask> "));
+        assert!(AiderAdapter.is_awaiting_input("Add file to the chat? (Y)es/(N)o [Yes]:"));
+        assert!(!AiderAdapter.is_awaiting_input("Applied edit to src/main.rs"));
+    }
+}
@@ -0,0 +1,244 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+
+use crate::models::monitoring::RecordTokenUsage;
+use crate::models::session::{CliTool, ResumeTarget};
+use crate::tools::{last_lines, ToolAdapter, TranscriptContext, TranscriptRecord};
+
+/// Model Claude Code writes for messages it made up itself, such as API errors.
+const SYNTHETIC_MODEL: &str = "<synthetic>";
+
+pub struct ClaudeCodeAdapter;
+
+impl ToolAdapter for ClaudeCodeAdapter {
+    fn tool(&self) -> CliTool {
+        CliTool::ClaudeCode
+    }
+
+    fn default_command(&self) -> &str {
+        "claude"
+    }
+
+    /// The input box is drawn with a `>` prompt and a shortcuts hint underneath.
+    fn is_awaiting_input(&self, screen: &str) -> bool {
+        last_lines(screen, 4).iter().any(|line| {
+            let line = line.trim_start_matches(['│', '|', ' ']);
+            line.starts_with('>') || line.contains("? for shortcuts") || line.contains("Do you want to")
+        })
+    }
+
+    /// Interactive sessions rarely print their usage, so it is imported from the
+    /// transcripts instead.
+    fn parse_usage(&self, _text: &str, _session_id: &str) -> Vec<RecordTokenUsage> {
+        Vec::new()
+    }
+
+    /// The `.jsonl` files in the project's directory under `~/.claude/projects`
+    /// (or `$CLAUDE_CONFIG_DIR/projects`), one per conversation.
+    fn transcript_files(&self, working_dir: &Path, env: &HashMap<String, String>) -> Vec<PathBuf> {
+        let Some(projects) = projects_dir(env) else {
+            return Vec::new();
+        };
+        let Ok(entries) = std::fs::read_dir(projects.join(project_dir_name(working_dir))) else {
+            return Vec::new();
+        };
+        entries
+            .filter_map(Result::ok)
+            .map(|entry| entry.path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == "jsonl"))
+            .collect()
+    }
+
+    fn parse_transcript_line(
+        &self,
+        line: &str,
+        _context: &mut TranscriptContext,
+        session_id: &str,
+    ) -> Vec<TranscriptRecord> {
+        parse_usage_line(line, session_id).map(TranscriptRecord::Usage).into_iter().collect()
+    }
+
+    fn resume_args(&self, target: &ResumeTarget) -> Option<Vec<String>> {
+        Some(match target {
+            ResumeTarget::Latest => vec!["--continue".to_string()],
+            ResumeTarget::Conversation(id) => vec!["--resume".to_string(), id.clone()],
+        })
+    }
+}
+
+/// Assistant messages carry their model and token counts. A message is written once per
+/// content block, each time with the usage so far, so every line is keyed by the message id.
+fn parse_usage_line(line: &str, session_id: &str) -> Option<RecordTokenUsage> {
+    let entry: TranscriptEntry = serde_json::from_str(line).ok()?;
+    if entry.kind != "assistant" {
+        return None;
+    }
+    let message = entry.message?;
+    let usage = message.usage?;
+    let model = message.model.unwrap_or_default();
+    if model == SYNTHETIC_MODEL {
+        return None;
+    }
+    let cost_usd = entry.cost_usd.unwrap_or_else(|| estimate_cost(&model, &usage));
+    Some(RecordTokenUsage {
+        session_id: session_id.to_string(),
+        input_tokens: usage.input_tokens,
+        output_tokens: usage.output_tokens,
+        cache_read_tokens: usage.cache_read_input_tokens,
+        cache_creation_tokens: usage.cache_creation_input_tokens,
+        model,
+        cost_usd,
+        message_id: message.id.or(entry.uuid),
+        cumulative: false,
+    })
+}
+
+/// One line of a transcript; only the fields usage import needs.
+#[derive(Deserialize)]
+struct TranscriptEntry {
+    #[serde(rename = "type")]
+    kind: String,
+    uuid: Option<String>,
+    message: Option<TranscriptMessage>,
+    /// Written by versions before 1.0.
+    #[serde(rename = "costUSD")]
+    cost_usd: Option<f64>,
+}
+
+#[derive(Deserialize)]
+struct TranscriptMessage {
+    id: Option<String>,
+    model: Option<String>,
+    usage: Option<MessageUsage>,
+}
+
+#[derive(Deserialize)]
+struct MessageUsage {
+    #[serde(default)]
+    input_tokens: i64,
+    #[serde(default)]
+    output_tokens: i64,
+    #[serde(default)]
+    cache_read_input_tokens: i64,
+    #[serde(default)]
+    cache_creation_input_tokens: i64,
+}
+
+fn projects_dir(env: &HashMap<String, String>) -> Option<PathBuf> {
+    let var = |name: &str| {
+        env.get(name)
+            .cloned()
+            .or_else(|| std::env::var(name).ok())
+            .filter(|value| !value.is_empty())
+    };
+    if let Some(config_dir) = var("CLAUDE_CONFIG_DIR") {
+        return Some(PathBuf::from(config_dir).join("projects"));
+    }
+    let home = var(if cfg!(windows) { "USERPROFILE" } else { "HOME" })?;
+    Some(PathBuf::from(home).join(".claude").join("projects"))
+}
+
+/// The directory name Claude Code gives a project: its path with everything but ASCII
+/// letters and digits replaced by `-`, e.g. `-Users-me-my-app` for `/Users/me/my_app`.
+pub fn project_dir_name(working_dir: &Path) -> String {
+    working_dir
+        .to_string_lossy()
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+        .collect()
+}
+
+/// List prices in dollars per million input and output tokens. Cache writes cost 1.25x
+/// the input price and cache reads 0.1x.
+fn prices(model: &str) -> Option<(f64, f64)> {
+    if model.contains("opus-4-5") {
+        Some((5.0, 25.0))
+    } else if model.contains("opus") {
+        Some((15.0, 75.0))
+    } else if model.contains("sonnet") {
+        Some((3.0, 15.0))
+    } else if model.contains("haiku-4-5") {
+        Some((1.0, 5.0))
+    } else if model.contains("3-5-haiku") {
+        Some((0.8, 4.0))
+    } else if model.contains("haiku") {
+        Some((0.25, 1.25))
+    } else {
+        None
+    }
+}
+
+/// What a message cost at list prices; zero for models without a known price.
+fn estimate_cost(model: &str, usage: &MessageUsage) -> f64 {
+    let Some((input, output)) = prices(model) else {
+        return 0.0;
+    };
+    (usage.input_tokens as f64 * input
+        + usage.output_tokens as f64 * output
+        + usage.cache_creation_input_tokens as f64 * input * 1.25
+        + usage.cache_read_input_tokens as f64 * input * 0.1)
+        / 1_000_000.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TRANSCRIPT: &str = include_str!("../../tests/fixtures/claude_code/transcript.jsonl");
+    const TRANSCRIPT_WITH_COST: &str = include_str!("../../tests/fixtures/claude_code/transcript_with_cost.jsonl");
+
+    fn parse(transcript: &str) -> Vec<RecordTokenUsage> {
+        transcript
+            .lines()
+            .filter_map(|line| parse_usage_line(line, "s"))
+            .collect()
+    }
+
+    #[test]
+    fn test_detects_input_box() {
+        let screen = "● Done.\n\n╭──────────╮\n│ >        │\n╰──────────╯\n  ? for shortcuts\n";
+        assert!(ClaudeCodeAdapter.is_awaiting_input(screen));
+        assert!(!ClaudeCodeAdapter.is_awaiting_input("✻ Thinking… (esc to interrupt)\n"));
+    }
+
+    #[test]
+    fn test_parses_transcript_usage() {
+        let records = parse(TRANSCRIPT);
+        // One line per content block of the first message; the synthetic error is skipped
+        let ids: Vec<_> = records.iter().map(|r| r.message_id.as_deref().unwrap()).collect();
+        assert_eq!(
+            ids,
+            vec!["msg_01XkQ7b2a9Lr4mV8cT3nP6sD", "msg_01XkQ7b2a9Lr4mV8cT3nP6sD", "msg_01PzR4c8d1Mn7wQ2vU5oK9tE"]
+        );
+        let last = &records[1];
+        assert_eq!(last.model, "claude-sonnet-4-20250514");
+        assert_eq!(
+            (last.input_tokens, last.output_tokens, last.cache_read_tokens, last.cache_creation_tokens),
+            (4, 96, 13872, 6425)
+        );
+        let expected = (4.0 * 3.0 + 96.0 * 15.0 + 6425.0 * 3.75 + 13872.0 * 0.3) / 1_000_000.0;
+        assert!((last.cost_usd - expected).abs() < 1e-12);
+
+        // Older versions wrote the cost themselves
+        let records = parse(TRANSCRIPT_WITH_COST);
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].cost_usd, 0.000189);
+    }
+
+    #[test]
+    fn test_finds_the_project_transcripts() {
+        assert_eq!(project_dir_name(Path::new("/Users/me/my_app.v2")), "-Users-me-my-app-v2");
+
+        let config_dir = std::env::temp_dir().join(format!("agentdesk-claude-{}", std::process::id()));
+        let project = config_dir.join("projects").join("-work-app");
+        std::fs::create_dir_all(&project).unwrap();
+        std::fs::write(project.join("a.jsonl"), TRANSCRIPT).unwrap();
+        std::fs::write(project.join("notes.txt"), "").unwrap();
+        let env = HashMap::from([("CLAUDE_CONFIG_DIR".to_string(), config_dir.display().to_string())]);
+        let files = ClaudeCodeAdapter.transcript_files(Path::new("/work/app"), &env);
+        assert_eq!(files, vec![project.join("a.jsonl")]);
+        let _ = std::fs::remove_dir_all(&config_dir);
+    }
+}
@@ -0,0 +1,15 @@
+use crate::models::session::CliTool;
+use crate::tools::ToolAdapter;
+
+/// Cline prints no usage summary and has no resume flag, so only launching is supported.
+pub struct ClineAdapter;
+
+impl ToolAdapter for ClineAdapter {
+    fn tool(&self) -> CliTool {
+        CliTool::Cline
+    }
+
+    fn default_command(&self) -> &str {
+        "cline"
+    }
+}
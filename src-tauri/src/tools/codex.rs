@@ -0,0 +1,345 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+
+use chrono::{Datelike, Local};
+use serde_json::Value;
+
+use crate::models::history::NewTranscriptMessage;
+use crate::models::monitoring::RecordTokenUsage;
+use crate::models::session::{CliTool, ResumeTarget};
+use crate::tools::{last_lines, ToolAdapter, TranscriptContext, TranscriptRecord};
+
+/// How far into a rollout to look for the directory its session ran in.
+const CWD_SEARCH_LINES: usize = 10;
+/// Model recorded for rollouts that never name one.
+const DEFAULT_MODEL: &str = "codex";
+
+pub struct CodexAdapter;
+
+impl ToolAdapter for CodexAdapter {
+    fn tool(&self) -> CliTool {
+        CliTool::Codex
+    }
+
+    fn default_command(&self) -> &str {
+        "codex"
+    }
+
+    /// The composer shows a `▌` prompt with a "send" hint while waiting.
+    fn is_awaiting_input(&self, screen: &str) -> bool {
+        last_lines(screen, 4)
+            .iter()
+            .any(|line| line.contains("⏎ send") || line.contains("Allow command?"))
+    }
+
+    /// Usage is imported from the rollout files; the summary printed on exit would count
+    /// it again.
+    fn parse_usage(&self, _text: &str, _session_id: &str) -> Vec<RecordTokenUsage> {
+        Vec::new()
+    }
+
+    /// Rollouts under `~/.codex/sessions/YYYY/MM/DD` (or `$CODEX_HOME/sessions`) from around
+    /// today whose session ran in `working_dir`.
+    fn transcript_files(&self, working_dir: &Path, env: &HashMap<String, String>) -> Vec<PathBuf> {
+        let Some(sessions) = sessions_dir(env) else {
+            return Vec::new();
+        };
+        // Filed by local date, and a session can run past midnight
+        let today = Local::now().date_naive();
+        [today.pred_opt(), Some(today), today.succ_opt()]
+            .into_iter()
+            .flatten()
+            .map(|date| {
+                sessions
+                    .join(format!("{:04}", date.year()))
+                    .join(format!("{:02}", date.month()))
+                    .join(format!("{:02}", date.day()))
+            })
+            .filter_map(|dir| std::fs::read_dir(dir).ok())
+            .flatten()
+            .filter_map(Result::ok)
+            .map(|entry| entry.path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == "jsonl"))
+            .filter(|path| rollout_cwd(path).is_some_and(|cwd| std::fs::canonicalize(&cwd).unwrap_or(cwd) == working_dir))
+            .collect()
+    }
+
+    /// Rollout lines are `{timestamp, type, payload}`, where the payload is a conversation
+    /// item, an event such as a token count, or the turn settings naming the model. Before
+    /// that wrapper existed each line was a bare conversation item.
+    fn parse_transcript_line(
+        &self,
+        line: &str,
+        context: &mut TranscriptContext,
+        session_id: &str,
+    ) -> Vec<TranscriptRecord> {
+        let Ok(entry) = serde_json::from_str::<Value>(line) else {
+            return Vec::new();
+        };
+        let timestamp = entry.get("timestamp").and_then(Value::as_str);
+        let payload = entry.get("payload").unwrap_or(&Value::Null);
+        let record = match entry.get("type").and_then(Value::as_str) {
+            Some("turn_context") => {
+                if let Some(model) = payload.get("model").and_then(Value::as_str) {
+                    context.model = Some(model.to_string());
+                }
+                None
+            }
+            Some("response_item") => parse_item(payload, timestamp, context, session_id).map(TranscriptRecord::Message),
+            Some("event_msg") => parse_token_count(payload, context, session_id).map(TranscriptRecord::Usage),
+            _ => parse_item(&entry, timestamp, context, session_id).map(TranscriptRecord::Message),
+        };
+        record.into_iter().collect()
+    }
+
+    fn resume_args(&self, target: &ResumeTarget) -> Option<Vec<String>> {
+        Some(match target {
+            ResumeTarget::Latest => vec!["resume".to_string(), "--last".to_string()],
+            ResumeTarget::Conversation(id) => vec!["resume".to_string(), id.clone()],
+        })
+    }
+}
+
+fn sessions_dir(env: &HashMap<String, String>) -> Option<PathBuf> {
+    let var = |name: &str| {
+        env.get(name)
+            .cloned()
+            .or_else(|| std::env::var(name).ok())
+            .filter(|value| !value.is_empty())
+    };
+    if let Some(codex_home) = var("CODEX_HOME") {
+        return Some(PathBuf::from(codex_home).join("sessions"));
+    }
+    let home = var(if cfg!(windows) { "USERPROFILE" } else { "HOME" })?;
+    Some(PathBuf::from(home).join(".codex").join("sessions"))
+}
+
+/// The directory the rollout's session ran in, from its session metadata or, in older
+/// rollouts, the environment context sent to the model.
+fn rollout_cwd(path: &Path) -> Option<PathBuf> {
+    let file = File::open(path).ok()?;
+    BufReader::new(file)
+        .lines()
+        .take(CWD_SEARCH_LINES)
+        .map_while(Result::ok)
+        .find_map(|line| {
+            let entry: Value = serde_json::from_str(&line).ok()?;
+            if let Some(cwd) = entry.pointer("/payload/cwd").and_then(Value::as_str) {
+                return Some(PathBuf::from(cwd));
+            }
+            let text = join_text(entry.get("content")?);
+            let start = text.find("<cwd>")? + "<cwd>".len();
+            let end = start + text[start..].find("</cwd>")?;
+            Some(PathBuf::from(&text[start..end]))
+        })
+}
+
+/// A conversation item as a transcript entry. Items only the model sees, such as the
+/// environment context, are left out.
+fn parse_item(
+    item: &Value,
+    timestamp: Option<&str>,
+    context: &TranscriptContext,
+    session_id: &str,
+) -> Option<NewTranscriptMessage> {
+    let text = |key: &str| item.get(key).and_then(Value::as_str).unwrap_or_default().to_string();
+    let (role, kind, name, content) = match item.get("type")?.as_str()? {
+        "message" => {
+            let role = item.get("role")?.as_str()?;
+            let content = join_text(item.get("content")?);
+            let injected = content.starts_with("<environment_context>") || content.starts_with("<user_instructions>");
+            if !matches!(role, "user" | "assistant") || injected {
+                return None;
+            }
+            (role, "message", None, content)
+        }
+        "reasoning" => {
+            let summary = join_text(item.get("summary")?);
+            if summary.is_empty() {
+                return None;
+            }
+            ("assistant", "reasoning", None, summary)
+        }
+        "function_call" => ("assistant", "tool_call", Some(text("name")), text("arguments")),
+        "custom_tool_call" => ("assistant", "tool_call", Some(text("name")), text("input")),
+        "local_shell_call" => {
+            let command: Vec<_> = item
+                .pointer("/action/command")?
+                .as_array()?
+                .iter()
+                .filter_map(Value::as_str)
+                .collect();
+            ("assistant", "tool_call", Some("shell".to_string()), command.join(" "))
+        }
+        "function_call_output" | "custom_tool_call_output" => ("tool", "tool_output", None, tool_output(item.get("output")?)),
+        _ => return None,
+    };
+    Some(NewTranscriptMessage {
+        session_id: session_id.to_string(),
+        entry_id: context.entry_id("codex"),
+        role: role.to_string(),
+        kind: kind.to_string(),
+        name,
+        content,
+        timestamp: timestamp.map(str::to_string),
+    })
+}
+
+/// Shell output is stored as JSON holding the output and its exit metadata; other tools
+/// return plain text.
+fn tool_output(output: &Value) -> String {
+    match output {
+        Value::String(output) => serde_json::from_str::<Value>(output)
+            .ok()
+            .and_then(|wrapped| wrapped.get("output")?.as_str().map(str::to_string))
+            .unwrap_or_else(|| output.clone()),
+        other => other.to_string(),
+    }
+}
+
+/// Usage of one turn. Newer versions nest it under `info`, next to the running total, and
+/// send `info: null` before the first turn finishes.
+fn parse_token_count(payload: &Value, context: &TranscriptContext, session_id: &str) -> Option<RecordTokenUsage> {
+    if payload.get("type")?.as_str()? != "token_count" {
+        return None;
+    }
+    let usage = match payload.get("info") {
+        Some(info) => info.get("last_token_usage")?,
+        None => payload,
+    };
+    let count = |key: &str| usage.get(key).and_then(Value::as_i64).unwrap_or(0);
+    // Codex counts cached input as part of the input
+    let cached = count("cached_input_tokens");
+    Some(RecordTokenUsage {
+        session_id: session_id.to_string(),
+        input_tokens: (count("input_tokens") - cached).max(0),
+        output_tokens: count("output_tokens"),
+        cache_read_tokens: cached,
+        cache_creation_tokens: 0,
+        model: context.model.clone().unwrap_or_else(|| DEFAULT_MODEL.to_string()),
+        cost_usd: 0.0,
+        message_id: Some(context.entry_id("codex")),
+        cumulative: false,
+    })
+}
+
+fn join_text(parts: &Value) -> String {
+    parts
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|part| part.get("text").and_then(Value::as_str))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ROLLOUT_2025_05: &str = include_str!("../../tests/fixtures/codex/rollout_2025_05.jsonl");
+    const ROLLOUT_2025_08: &str = include_str!("../../tests/fixtures/codex/rollout_2025_08.jsonl");
+    const ROLLOUT_2025_10: &str = include_str!("../../tests/fixtures/codex/rollout_2025_10.jsonl");
+
+    fn parse(rollout: &str) -> (Vec<RecordTokenUsage>, Vec<NewTranscriptMessage>) {
+        let mut context = TranscriptContext {
+            source: "rollout".to_string(),
+            ..Default::default()
+        };
+        let mut usage = Vec::new();
+        let mut messages = Vec::new();
+        for (index, line) in rollout.lines().enumerate() {
+            context.offset = index as u64;
+            for record in CodexAdapter.parse_transcript_line(line, &mut context, "s") {
+                match record {
+                    TranscriptRecord::Usage(record) => usage.push(record),
+                    TranscriptRecord::Message(message) => messages.push(message),
+                }
+            }
+        }
+        (usage, messages)
+    }
+
+    fn summary(messages: &[NewTranscriptMessage]) -> Vec<(&str, &str, &str)> {
+        messages
+            .iter()
+            .map(|m| (m.role.as_str(), m.kind.as_str(), m.content.as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn test_parses_unwrapped_rollouts() {
+        let (usage, messages) = parse(ROLLOUT_2025_05);
+        assert!(usage.is_empty());
+        assert_eq!(
+            summary(&messages),
+            vec![
+                ("user", "message", "List the failing tests"),
+                ("assistant", "reasoning", "**Running the test suite**"),
+                ("assistant", "tool_call", "bash -lc cargo test"),
+                ("tool", "tool_output", "test result: FAILED. 1 failed\n"),
+                ("assistant", "message", "One test fails: `parser::tests::test_trailing_newline`."),
+            ]
+        );
+        assert_eq!(messages[2].name.as_deref(), Some("shell"));
+        assert_eq!(messages[0].timestamp, None);
+        assert_eq!(messages[0].entry_id, "codex:rollout:3");
+    }
+
+    #[test]
+    fn test_parses_per_turn_token_counts() {
+        let (usage, messages) = parse(ROLLOUT_2025_08);
+        let counts: Vec<_> = usage
+            .iter()
+            .map(|u| (u.input_tokens, u.cache_read_tokens, u.output_tokens, u.model.as_str()))
+            .collect();
+        assert_eq!(counts, vec![(2132, 7680, 214, "codex"), (512, 9728, 96, "codex")]);
+        assert_eq!(messages.len(), 4);
+        assert_eq!(messages[1].name.as_deref(), Some("shell"));
+        assert_eq!(messages[1].timestamp.as_deref(), Some("2025-08-12T14:03:19.771Z"));
+    }
+
+    #[test]
+    fn test_parses_token_count_info() {
+        let (usage, messages) = parse(ROLLOUT_2025_10);
+        let counts: Vec<_> = usage
+            .iter()
+            .map(|u| (u.input_tokens, u.cache_read_tokens, u.output_tokens, u.model.as_str()))
+            .collect();
+        assert_eq!(counts, vec![(8589, 3456, 421, "gpt-5-codex"), (889, 11776, 87, "gpt-5-codex")]);
+        // Event copies of the messages are not repeated
+        assert_eq!(
+            summary(&messages),
+            vec![
+                ("user", "message", "Why is CI red?"),
+                ("assistant", "reasoning", "**Checking the CI logs**"),
+                ("assistant", "tool_call", "*** Begin Patch\n*** Update File: ci.yml\n*** End Patch"),
+                ("tool", "tool_output", "Success. Updated the following files:\nM ci.yml"),
+                ("assistant", "message", "The workflow pinned an old toolchain; I updated ci.yml."),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_finds_rollouts_for_the_working_dir() {
+        let codex_home = std::env::temp_dir().join(format!("agentdesk-codex-{}", std::process::id()));
+        let today = Local::now().date_naive();
+        let dir = codex_home
+            .join("sessions")
+            .join(format!("{:04}", today.year()))
+            .join(format!("{:02}", today.month()))
+            .join(format!("{:02}", today.day()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("rollout-old.jsonl"), ROLLOUT_2025_05).unwrap();
+        std::fs::write(dir.join("rollout-new.jsonl"), ROLLOUT_2025_10).unwrap();
+        std::fs::write(dir.join("rollout-other.jsonl"), ROLLOUT_2025_10.replace("/work/app", "/work/other")).unwrap();
+        let env = HashMap::from([("CODEX_HOME".to_string(), codex_home.display().to_string())]);
+
+        let mut files = CodexAdapter.transcript_files(Path::new("/work/app"), &env);
+        files.sort();
+        assert_eq!(files, vec![dir.join("rollout-new.jsonl"), dir.join("rollout-old.jsonl")]);
+        let _ = std::fs::remove_dir_all(&codex_home);
+    }
+}
@@ -0,0 +1,144 @@
+use std::collections::HashMap;
+
+use regex::Regex;
+
+use crate::models::custom_tool::CustomTool;
+use crate::models::monitoring::RecordTokenUsage;
+use crate::models::session::CliTool;
+use crate::pty::token_parser;
+use crate::tools::{last_lines, ToolAdapter};
+
+/// Any other command, optionally described by a saved `CustomTool`. Without a usage regex,
+/// the usage rules for custom commands apply, which recognise every known tool's summary
+/// since a shell session may run any of them.
+#[derive(Debug, Clone, Default)]
+pub struct CustomAdapter {
+    name: String,
+    command: String,
+    args: Vec<String>,
+    env_vars: HashMap<String, String>,
+    /// Matches the last screen line while the tool waits for input.
+    idle_pattern: Option<Regex>,
+    usage_pattern: Option<Regex>,
+}
+
+impl CustomAdapter {
+    pub fn new(command: &str, args: &[String]) -> Self {
+        CustomAdapter {
+            name: "custom".to_string(),
+            command: command.to_string(),
+            args: args.to_vec(),
+            ..Default::default()
+        }
+    }
+
+    /// An adapter for a saved definition. Regexes are validated when a definition is saved,
+    /// but rows saved before that or edited directly may still hold broken ones.
+    pub fn from_definition(tool: &CustomTool) -> Result<Self, String> {
+        let compile = |kind: &str, pattern: &Option<String>| {
+            pattern
+                .as_deref()
+                .map(Regex::new)
+                .transpose()
+                .map_err(|e| format!("Custom tool '{}': invalid {kind} regex: {e}", tool.name))
+        };
+        Ok(CustomAdapter {
+            name: tool.name.clone(),
+            command: tool.command.clone(),
+            args: tool.args.clone(),
+            env_vars: tool.env_vars.clone(),
+            idle_pattern: compile("idle", &tool.idle_regex)?,
+            usage_pattern: compile("usage", &tool.usage_regex)?,
+        })
+    }
+}
+
+impl ToolAdapter for CustomAdapter {
+    fn tool(&self) -> CliTool {
+        CliTool::Custom
+    }
+
+    fn default_command(&self) -> &str {
+        &self.command
+    }
+
+    fn default_args(&self) -> Vec<String> {
+        self.args.clone()
+    }
+
+    fn default_env(&self) -> HashMap<String, String> {
+        self.env_vars.clone()
+    }
+
+    fn is_awaiting_input(&self, screen: &str) -> bool {
+        let Some(pattern) = &self.idle_pattern else {
+            return false;
+        };
+        last_lines(screen, 1)
+            .first()
+            .is_some_and(|line| pattern.is_match(line))
+    }
+
+    fn parse_usage(&self, text: &str, session_id: &str) -> Vec<RecordTokenUsage> {
+        match &self.usage_pattern {
+            Some(pattern) => token_parser::parse_pattern(pattern, text, session_id, &self.name),
+            None => Vec::new(),
+        }
+    }
+
+    /// A saved tool's own usage regex replaces the rules. Otherwise both the rules for custom
+    /// commands and those naming the saved tool apply.
+    fn usage_rule_tools(&self) -> Vec<String> {
+        if self.usage_pattern.is_some() {
+            return Vec::new();
+        }
+        vec![format!("{:?}", CliTool::Custom), self.name.clone()]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn definition() -> CustomTool {
+        serde_json::from_value(serde_json::json!({
+            "id": "t1",
+            "name": "gpt",
+            "command": "gpt-cli",
+            "args": ["--chat"],
+            "envVars": { "GPT_MODE": "tty" },
+            "iconLabel": "GPT",
+            "idleRegex": r"^gpt \[\w+\]>$",
+            "usageRegex": r"(?P<input>[\d.]+k?) in, (?P<output>[\d,]+) out, \$(?P<cost>[\d.]+)",
+            "createdAt": "",
+            "updatedAt": "",
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_definition_drives_idle_and_usage() {
+        let adapter = CustomAdapter::from_definition(&definition()).unwrap();
+        assert_eq!(adapter.default_command(), "gpt-cli");
+        assert_eq!(adapter.default_env()["GPT_MODE"], "tty");
+        assert!(adapter.is_awaiting_input("answer\n\ngpt [main]>\n"));
+        assert!(!adapter.is_awaiting_input("gpt [main]> thinking"));
+
+        let usage = adapter.parse_usage("done: 1.5k in, 2,300 out, $0.012\n", "s");
+        assert_eq!(usage.len(), 1);
+        assert_eq!((usage[0].input_tokens, usage[0].output_tokens), (1500, 2300));
+        assert_eq!(usage[0].cost_usd, 0.012);
+        assert_eq!(usage[0].model, "gpt");
+        // A usage regex replaces the built-in formats
+        assert!(adapter.parse_usage("Total cost: $1.00", "s").is_empty());
+        assert!(adapter.usage_rule_tools().is_empty());
+    }
+
+    #[test]
+    fn test_broken_definition_regex_is_reported() {
+        let mut tool = definition();
+        tool.usage_regex = Some("(?P<input>".to_string());
+        let error = CustomAdapter::from_definition(&tool).unwrap_err();
+        assert!(error.starts_with("Custom tool 'gpt': invalid usage regex:"), "{error}");
+    }
+}
@@ -0,0 +1,242 @@
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+use crate::error::{AppError, AppResult};
+use crate::models::session::DiscoveredTool;
+use crate::process;
+use crate::pty::preflight;
+use crate::tools::{self, ToolAdapter};
+
+/// How long a tool gets to print its version.
+pub const VERSION_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A binary path and its modification time.
+type ProbeKey = (PathBuf, Option<SystemTime>);
+type ProbeResult = Result<Option<String>, String>;
+
+/// Probed versions keyed by binary path and modification time, so an upgrade re-probes.
+#[derive(Default)]
+pub struct ToolVersions {
+    probed: Mutex<HashMap<ProbeKey, ProbeResult>>,
+}
+
+impl ToolVersions {
+    /// The version `path` reports, running it only if it changed since the last probe.
+    /// `Ok(None)` means it ran but printed nothing recognisable as a version.
+    pub fn probe(
+        &self,
+        adapter: &dyn ToolAdapter,
+        path: &Path,
+        env: &HashMap<String, String>,
+    ) -> ProbeResult {
+        let key = (
+            path.to_path_buf(),
+            std::fs::metadata(path).and_then(|m| m.modified()).ok(),
+        );
+        if let Some(cached) = self.probed.lock().ok().and_then(|probed| probed.get(&key).cloned()) {
+            return cached;
+        }
+        let result = run_version(adapter, path, env).map_err(|e| e.to_string());
+        if let Ok(mut probed) = self.probed.lock() {
+            probed.insert(key, result.clone());
+        }
+        result
+    }
+}
+
+fn run_version(adapter: &dyn ToolAdapter, path: &Path, env: &HashMap<String, String>) -> AppResult<Option<String>> {
+    let output = process::output_with_timeout(
+        Command::new(path).args(adapter.version_args()).envs(env),
+        VERSION_TIMEOUT,
+    )?;
+    if !output.status.success() {
+        return Err(AppError::Internal(format!(
+            "{} exited with {}",
+            path.display(),
+            output.status
+        )));
+    }
+    // Some tools print their version on stderr
+    let mut text = String::from_utf8_lossy(&output.stdout).into_owned();
+    text.push('\n');
+    text.push_str(&String::from_utf8_lossy(&output.stderr));
+    Ok(adapter.parse_version(&text))
+}
+
+/// Looks for every known tool on the effective PATH and in common install locations,
+/// probing the version of each one found. Tools are probed in parallel.
+pub fn discover_tools(env: &HashMap<String, String>, versions: &ToolVersions) -> Vec<DiscoveredTool> {
+    let path_dirs: Vec<PathBuf> = effective_path(env)
+        .map(|path| std::env::split_paths(&path).collect())
+        .unwrap_or_default();
+    let mut search = path_dirs.clone();
+    for dir in install_dirs(env) {
+        if !search.contains(&dir) {
+            search.push(dir);
+        }
+    }
+    let search = std::env::join_paths(&search).ok();
+
+    let adapters = tools::known_adapters();
+    std::thread::scope(|scope| {
+        let handles: Vec<_> = adapters
+            .iter()
+            .map(|adapter| {
+                let search = search.as_deref();
+                let path_dirs = &path_dirs;
+                scope.spawn(move || discover_tool(adapter.as_ref(), search, path_dirs, env, versions))
+            })
+            .collect();
+        handles.into_iter().filter_map(|handle| handle.join().ok()).collect()
+    })
+}
+
+fn discover_tool(
+    adapter: &dyn ToolAdapter,
+    search: Option<&std::ffi::OsStr>,
+    path_dirs: &[PathBuf],
+    env: &HashMap<String, String>,
+    versions: &ToolVersions,
+) -> DiscoveredTool {
+    let command = adapter.default_command().to_string();
+    let Some(path) = preflight::resolve_command(&command, Path::new(""), search) else {
+        return DiscoveredTool {
+            tool: adapter.tool(),
+            command,
+            path: None,
+            on_path: false,
+            version: None,
+            usable: false,
+            error: None,
+        };
+    };
+    let on_path = path.parent().is_some_and(|dir| path_dirs.iter().any(|d| d == dir));
+    let (version, error) = match versions.probe(adapter, &path, env) {
+        Ok(version) => (version, None),
+        Err(e) => (None, Some(e)),
+    };
+    DiscoveredTool {
+        tool: adapter.tool(),
+        command,
+        path: Some(path),
+        on_path,
+        version,
+        usable: error.is_none(),
+        error,
+    }
+}
+
+/// The PATH a session would see: the login environment's, falling back to the app's own.
+pub fn effective_path(env: &HashMap<String, String>) -> Option<OsString> {
+    env.get("PATH").map(OsString::from).or_else(|| std::env::var_os("PATH"))
+}
+
+/// Where tools are commonly installed when the launcher's PATH does not include them.
+fn install_dirs(env: &HashMap<String, String>) -> Vec<PathBuf> {
+    let var = |name: &str| env.get(name).cloned().or_else(|| std::env::var(name).ok());
+    let mut dirs = Vec::new();
+    if let Some(prefix) = var("NPM_CONFIG_PREFIX") {
+        dirs.push(npm_bin(Path::new(&prefix)));
+    }
+    if let Some(pipx) = var("PIPX_BIN_DIR") {
+        dirs.push(PathBuf::from(pipx));
+    }
+    if let Some(home) = var(if cfg!(windows) { "USERPROFILE" } else { "HOME" }) {
+        let home = PathBuf::from(home);
+        dirs.extend([
+            // pipx, uv tool and the Claude Code native installer
+            home.join(".local").join("bin"),
+            home.join(".claude").join("local"),
+            npm_bin(&home.join(".npm-global")),
+            home.join(".bun").join("bin"),
+            home.join(".volta").join("bin"),
+            home.join(".cargo").join("bin"),
+        ]);
+    }
+    if cfg!(windows) {
+        if let Some(appdata) = var("APPDATA") {
+            dirs.push(PathBuf::from(appdata).join("npm"));
+        }
+    } else {
+        dirs.extend(["/usr/local/bin", "/opt/homebrew/bin"].map(PathBuf::from));
+    }
+    dirs
+}
+
+/// npm puts global binaries in the prefix itself on Windows and in `bin` elsewhere.
+fn npm_bin(prefix: &Path) -> PathBuf {
+    if cfg!(windows) {
+        prefix.to_path_buf()
+    } else {
+        prefix.join("bin")
+    }
+}
+
+/// Probes the version of a session's command in the background and hands it to `record`.
+pub fn probe_in_background(
+    versions: Arc<ToolVersions>,
+    adapter: Arc<dyn ToolAdapter>,
+    path: PathBuf,
+    env: Arc<HashMap<String, String>>,
+    record: impl FnOnce(String) + Send + 'static,
+) {
+    std::thread::spawn(move || {
+        if let Ok(Some(version)) = versions.probe(adapter.as_ref(), &path, &env) {
+            record(version);
+        }
+    });
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::tools::CustomAdapter;
+    use std::os::unix::fs::PermissionsExt;
+
+    fn fake_tool(dir: &Path, name: &str, script: &str) -> PathBuf {
+        std::fs::create_dir_all(dir).unwrap();
+        let path = dir.join(name);
+        std::fs::write(&path, format!("#!/bin/sh\n{script}\n")).unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_finds_tools_in_install_dirs() {
+        let home = std::env::temp_dir().join(format!("agentdesk-discovery-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&home);
+        fake_tool(&home.join(".local/bin"), "claude", "echo '1.0.35 (Claude Code)'");
+        fake_tool(&home.join(".npm-global/bin"), "codex", "exit 3");
+
+        let env = HashMap::from([
+            ("HOME".to_string(), home.display().to_string()),
+            ("PATH".to_string(), "/no/such/dir".to_string()),
+        ]);
+        let found = discover_tools(&env, &ToolVersions::default());
+        let claude = found.iter().find(|t| t.command == "claude").unwrap();
+        assert_eq!(claude.version.as_deref(), Some("1.0.35"));
+        assert!(claude.usable && !claude.on_path);
+        let codex = found.iter().find(|t| t.command == "codex").unwrap();
+        assert!(codex.path.is_some() && !codex.usable && codex.error.is_some());
+
+        let _ = std::fs::remove_dir_all(&home);
+    }
+
+    #[test]
+    fn test_versions_are_cached_until_the_binary_changes() {
+        let dir = std::env::temp_dir().join(format!("agentdesk-versions-{}", std::process::id()));
+        let counter = dir.join("runs");
+        let tool = fake_tool(&dir, "tool", &format!("echo x >> {}; echo 2.1.0", counter.display()));
+        let versions = ToolVersions::default();
+        let adapter = CustomAdapter::default();
+        for _ in 0..3 {
+            assert_eq!(versions.probe(&adapter, &tool, &HashMap::new()).unwrap().as_deref(), Some("2.1.0"));
+        }
+        assert_eq!(std::fs::read_to_string(&counter).unwrap().lines().count(), 1);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
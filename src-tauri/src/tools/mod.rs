@@ -0,0 +1,242 @@
+pub mod aider;
+pub mod claude_code;
+pub mod cline;
+pub mod codex;
+pub mod custom;
+pub mod discovery;
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::error::{AppError, AppResult};
+use crate::models::history::NewTranscriptMessage;
+use crate::models::monitoring::RecordTokenUsage;
+use crate::models::session::{CliTool, ResumeTarget, SessionConfig};
+
+pub use aider::AiderAdapter;
+pub use claude_code::ClaudeCodeAdapter;
+pub use cline::ClineAdapter;
+pub use codex::CodexAdapter;
+pub use custom::CustomAdapter;
+
+/// Everything the backend knows about driving one CLI tool: how to launch it, how to tell
+/// it is waiting for input, how to read its usage output and how to resume a conversation.
+pub trait ToolAdapter: Send + Sync {
+    fn tool(&self) -> CliTool;
+
+    /// Command used when the session config leaves `command` empty.
+    fn default_command(&self) -> &str;
+
+    fn default_args(&self) -> Vec<String> {
+        Vec::new()
+    }
+
+    /// Variables the tool always runs with. Session `env_vars` override them.
+    fn default_env(&self) -> HashMap<String, String> {
+        HashMap::new()
+    }
+
+    /// Arguments that make the tool print its version and exit.
+    fn version_args(&self) -> Vec<String> {
+        vec!["--version".to_string()]
+    }
+
+    /// Extracts the version from the output of `version_args`.
+    fn parse_version(&self, output: &str) -> Option<String> {
+        first_version(output)
+    }
+
+    /// Whether the visible screen shows the tool waiting for the user, so the session can
+    /// be reported idle without waiting for the silence threshold.
+    fn is_awaiting_input(&self, _screen: &str) -> bool {
+        false
+    }
+
+    /// Token usage reported in a block of output that the usage rules do not cover.
+    fn parse_usage(&self, _text: &str, _session_id: &str) -> Vec<RecordTokenUsage> {
+        Vec::new()
+    }
+
+    /// Names that select the usage rules for this tool's output; see `pty::usage_rules`.
+    fn usage_rule_tools(&self) -> Vec<String> {
+        vec![format!("{:?}", self.tool())]
+    }
+
+    /// Transcript files the tool keeps for conversations run in `working_dir`, located
+    /// through the session environment `env`. Usage in them is imported while the session
+    /// runs; see `pty::usage_import`.
+    fn transcript_files(&self, _working_dir: &Path, _env: &HashMap<String, String>) -> Vec<PathBuf> {
+        Vec::new()
+    }
+
+    /// What one line of a transcript file records. `context` carries what earlier lines of
+    /// the same file said.
+    fn parse_transcript_line(
+        &self,
+        _line: &str,
+        _context: &mut TranscriptContext,
+        _session_id: &str,
+    ) -> Vec<TranscriptRecord> {
+        Vec::new()
+    }
+
+    /// Arguments that resume a previous conversation, or `None` if the tool cannot.
+    fn resume_args(&self, _target: &ResumeTarget) -> Option<Vec<String>> {
+        None
+    }
+}
+
+/// State carried from line to line while reading one transcript file.
+#[derive(Debug, Clone, Default)]
+pub struct TranscriptContext {
+    /// Names the file, unique among the tool's transcripts.
+    pub source: String,
+    /// Byte offset of the line being parsed within the file.
+    pub offset: u64,
+    /// The model the conversation uses, for formats that name it once rather than per message.
+    pub model: Option<String>,
+}
+
+impl TranscriptContext {
+    /// An id for what the current line records, stable across reads of the file.
+    pub fn entry_id(&self, tool: &str) -> String {
+        format!("{tool}:{}:{}", self.source, self.offset)
+    }
+}
+
+/// Something a transcript line records.
+#[derive(Debug, Clone)]
+pub enum TranscriptRecord {
+    Usage(RecordTokenUsage),
+    Message(NewTranscriptMessage),
+}
+
+/// Adapters for the built-in tools, i.e. everything but Custom.
+pub fn known_adapters() -> Vec<Arc<dyn ToolAdapter>> {
+    vec![
+        Arc::new(ClaudeCodeAdapter),
+        Arc::new(CodexAdapter),
+        Arc::new(AiderAdapter),
+        Arc::new(ClineAdapter),
+    ]
+}
+
+/// The adapter for a session's tool. Fails if the session's custom tool definition has a
+/// regex that does not compile.
+pub fn adapter_for(config: &SessionConfig) -> AppResult<Arc<dyn ToolAdapter>> {
+    Ok(match config.tool {
+        CliTool::ClaudeCode => Arc::new(ClaudeCodeAdapter),
+        CliTool::Codex => Arc::new(CodexAdapter),
+        CliTool::Aider => Arc::new(AiderAdapter),
+        CliTool::Cline => Arc::new(ClineAdapter),
+        CliTool::Custom => match &config.custom_tool {
+            Some(definition) => {
+                Arc::new(CustomAdapter::from_definition(definition).map_err(AppError::Validation)?)
+            }
+            None => Arc::new(CustomAdapter::new(&config.command, &config.args)),
+        },
+    })
+}
+
+/// The program and arguments a session actually runs.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LaunchCommand {
+    pub command: String,
+    pub args: Vec<String>,
+}
+
+/// Resolves what to run for `config`: its own command and args, or the adapter's defaults
+/// when `command` is empty, followed by the resume arguments if a resume was requested.
+pub fn launch_command(adapter: &dyn ToolAdapter, config: &SessionConfig) -> AppResult<LaunchCommand> {
+    let mut launch = if config.command.trim().is_empty() {
+        LaunchCommand {
+            command: adapter.default_command().to_string(),
+            args: adapter.default_args(),
+        }
+    } else {
+        LaunchCommand {
+            command: config.command.clone(),
+            args: config.args.clone(),
+        }
+    };
+    if let Some(target) = &config.resume {
+        let resume_args = adapter.resume_args(target).ok_or_else(|| {
+            AppError::Validation(format!("{:?} sessions cannot be resumed", adapter.tool()))
+        })?;
+        launch.args.extend(resume_args);
+    }
+    Ok(launch)
+}
+
+/// The first `N.N[.N…]` token in `output`, e.g. `1.0.35` from `1.0.35 (Claude Code)`.
+pub fn first_version(output: &str) -> Option<String> {
+    output
+        .split(|c: char| c.is_whitespace() || c == ',' || c == '(' || c == ')')
+        .map(|token| token.trim_start_matches('v'))
+        .find(|token| {
+            let parts: Vec<&str> = token.split('.').collect();
+            parts.len() >= 2
+                && parts[..2]
+                    .iter()
+                    .all(|part| !part.is_empty() && part.chars().all(|c| c.is_ascii_digit()))
+        })
+        .map(str::to_string)
+}
+
+/// The last `count` non-blank lines of a screen, trimmed on the right.
+pub fn last_lines(screen: &str, count: usize) -> Vec<&str> {
+    let mut lines: Vec<&str> = screen
+        .lines()
+        .map(str::trim_end)
+        .filter(|line| !line.is_empty())
+        .rev()
+        .take(count)
+        .collect();
+    lines.reverse();
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(tool: &str, command: &str) -> SessionConfig {
+        serde_json::from_value(serde_json::json!({
+            "id": "s",
+            "name": "s",
+            "tool": tool,
+            "command": command,
+            "args": [],
+            "workingDir": "/tmp",
+            "envVars": {},
+            "cols": 80,
+            "rows": 24,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_launch_command_defaults_and_resume() {
+        let mut config = config("ClaudeCode", "");
+        let adapter = adapter_for(&config).unwrap();
+        assert_eq!(launch_command(adapter.as_ref(), &config).unwrap().command, "claude");
+
+        config.resume = Some(ResumeTarget::Conversation("abc".to_string()));
+        let launch = launch_command(adapter.as_ref(), &config).unwrap();
+        assert_eq!(launch.args, vec!["--resume", "abc"]);
+
+        let mut config = self::config("Cline", "cline");
+        config.resume = Some(ResumeTarget::Latest);
+        let adapter = adapter_for(&config).unwrap();
+        assert_eq!(launch_command(adapter.as_ref(), &config).unwrap_err().code(), "VALIDATION");
+    }
+
+    #[test]
+    fn test_first_version() {
+        assert_eq!(first_version("1.0.35 (Claude Code)").as_deref(), Some("1.0.35"));
+        assert_eq!(first_version("codex-cli 0.20.0").as_deref(), Some("0.20.0"));
+        assert_eq!(first_version("aider v0.85.1\n").as_deref(), Some("0.85.1"));
+        assert_eq!(first_version("no version here."), None);
+    }
+}
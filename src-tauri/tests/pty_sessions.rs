@@ -0,0 +1,394 @@
+//! Drives real `/bin/sh` sessions through `PtyManager` with in-memory sinks.
+#![cfg(unix)]
+
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use agentdesk_lib::db::{history_repo, migrations, monitoring_repo, session_repo, DbPool};
+use agentdesk_lib::models::session::{PtyOutputEvent, SessionConfig};
+use agentdesk_lib::pty::persistence::{PersistenceWorker, DEFAULT_QUEUE_CAPACITY};
+use agentdesk_lib::pty::sink::{MemorySink, SessionEvent};
+use agentdesk_lib::pty::PtyManager;
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+struct Harness {
+    manager: PtyManager,
+    db: Arc<DbPool>,
+    persistence: Arc<PersistenceWorker>,
+    events: MemorySink<SessionEvent>,
+    dir: PathBuf,
+}
+
+impl Harness {
+    fn new(name: &str) -> Self {
+        let dir = std::env::temp_dir().join(format!("agentdesk-test-{}-{name}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let db = Arc::new(DbPool::new(&dir).unwrap());
+        db.with_conn(migrations::run_migrations).unwrap();
+        let events = MemorySink::default();
+        Harness {
+            manager: PtyManager::new(Arc::new(events.clone())),
+            persistence: Arc::new(PersistenceWorker::start(db.clone(), DEFAULT_QUEUE_CAPACITY)),
+            db,
+            events,
+            dir,
+        }
+    }
+
+    fn spawn(&self, id: &str, args: &[&str]) -> MemorySink<PtyOutputEvent> {
+        self.spawn_with(id, args, serde_json::json!({}))
+    }
+
+    /// Spawns with `extra` merged into the config.
+    fn spawn_with(&self, id: &str, args: &[&str], extra: serde_json::Value) -> MemorySink<PtyOutputEvent> {
+        let mut config = serde_json::json!({
+            "id": id,
+            "name": id,
+            "tool": "Custom",
+            "command": "/bin/sh",
+            "args": args,
+            "workingDir": self.dir,
+            "envVars": { "PS1": "$ " },
+            "cols": 80,
+            "rows": 24,
+        });
+        if let (Some(config), Some(extra)) = (config.as_object_mut(), extra.as_object()) {
+            config.extend(extra.clone());
+        }
+        let config: SessionConfig = serde_json::from_value(config).unwrap();
+        // Logs reference the session row
+        self.db
+            .with_conn(|conn| {
+                conn.execute(
+                    "INSERT OR IGNORE INTO sessions (id, name, tool, command, working_dir) VALUES (?1, ?1, 'Custom', '/bin/sh', '')",
+                    [id],
+                )
+            })
+            .unwrap();
+        let sink = MemorySink::default();
+        self.manager
+            .create_session(&config, sink.clone(), self.persistence.clone())
+            .unwrap();
+        sink
+    }
+}
+
+impl Drop for Harness {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+#[test]
+fn test_write_input_and_read_output() {
+    let harness = Harness::new("write");
+    let sink = harness.spawn("write", &[]);
+    harness
+        .manager
+        .write_to_session("write", b"echo marker-$((40 + 2))\n")
+        .unwrap();
+    assert!(sink.wait_for_output("marker-42", TIMEOUT));
+
+    harness.manager.write_to_session("write", b"exit 0\n").unwrap();
+    assert_eq!(sink.wait_for_exit(TIMEOUT), Some(Some(0)));
+}
+
+#[test]
+fn test_resize_is_visible_to_the_child() {
+    let harness = Harness::new("resize");
+    let sink = harness.spawn("resize", &[]);
+    harness.manager.resize_session("resize", 132, 50).unwrap();
+    harness.manager.write_to_session("resize", b"stty size\n").unwrap();
+    assert!(sink.wait_for_output("50 132", TIMEOUT));
+
+    let snapshot = harness.manager.screen_snapshot("resize", false).unwrap();
+    assert_eq!((snapshot.rows, snapshot.cols), (50, 132));
+}
+
+#[test]
+fn test_exit_code_and_log_flush() {
+    let harness = Harness::new("exit");
+    let sink = harness.spawn("exit", &["-c", "echo flushed-line; exit 3"]);
+    assert_eq!(sink.wait_for_exit(TIMEOUT), Some(Some(3)));
+    assert!(String::from_utf8_lossy(&sink.output()).contains("flushed-line"));
+
+    // The reader flushes the log before the exit is reported
+    let log = harness
+        .db
+        .with_conn(|conn| history_repo::get_session_log(conn, "exit"))
+        .unwrap();
+    assert!(log.contains("flushed-line"));
+}
+
+#[test]
+fn test_clean_exit_reports_no_error() {
+    let harness = Harness::new("clean-exit");
+    let sink = harness.spawn("clean-exit", &["-c", "echo done; exit 0"]);
+    assert_eq!(sink.wait_for_exit(TIMEOUT), Some(Some(0)));
+    assert!(!sink
+        .events()
+        .iter()
+        .any(|event| matches!(event, PtyOutputEvent::Error { .. })));
+}
+
+#[test]
+fn test_subscribe_after_exit_replays_output_and_exit() {
+    let harness = Harness::new("exited");
+    let sink = harness.spawn("exited", &["-c", "echo last-words; exit 4"]);
+    assert_eq!(sink.wait_for_exit(TIMEOUT), Some(Some(4)));
+
+    let late = MemorySink::default();
+    harness.manager.subscribe_session("exited", late.clone(), true).unwrap();
+    assert!(String::from_utf8_lossy(&late.output()).contains("last-words"));
+    assert_eq!(late.exit_code(), Some(Some(4)));
+
+    // Kept until stopped
+    assert_eq!(harness.manager.list_session_ids().unwrap(), vec!["exited".to_string()]);
+    harness.manager.stop_session("exited", Duration::from_millis(500)).unwrap();
+    assert!(harness.manager.list_session_ids().unwrap().is_empty());
+}
+
+#[test]
+fn test_stop_session_reports_exit() {
+    let harness = Harness::new("stop");
+    let sink = harness.spawn("stop", &["-c", "sleep 30"]);
+    harness
+        .manager
+        .stop_session("stop", Duration::from_millis(500))
+        .unwrap();
+    assert!(sink.wait_for_exit(TIMEOUT).is_some());
+    assert!(harness.manager.list_session_ids().unwrap().is_empty());
+}
+
+#[test]
+fn test_running_session_id_cannot_be_created_again() {
+    let harness = Harness::new("duplicate");
+    let sink = harness.spawn("duplicate", &["-c", "sleep 30"]);
+    let config: SessionConfig = serde_json::from_value(serde_json::json!({
+        "id": "duplicate",
+        "name": "duplicate",
+        "tool": "Custom",
+        "command": "/bin/sh",
+        "args": [],
+        "workingDir": harness.dir,
+        "envVars": {},
+        "cols": 80,
+        "rows": 24,
+    }))
+    .unwrap();
+    let error = harness
+        .manager
+        .create_session(&config, MemorySink::default(), harness.persistence.clone())
+        .unwrap_err();
+    assert_eq!(error.code(), "CONFLICT");
+
+    // The original process is still reachable and can be stopped
+    harness
+        .manager
+        .stop_session("duplicate", Duration::from_millis(500))
+        .unwrap();
+    assert!(sink.wait_for_exit(TIMEOUT).is_some());
+}
+
+#[test]
+fn test_title_change_is_emitted() {
+    let harness = Harness::new("title");
+    let sink = harness.spawn("title", &["-c", "printf '\\033]0;build running\\007'"]);
+    assert!(sink.wait_for_exit(TIMEOUT).is_some());
+    assert!(harness.events.wait_until(TIMEOUT, |events| events.iter().any(|event| matches!(
+        event,
+        SessionEvent::TitleChanged(change) if change.title.as_deref() == Some("build running")
+    ))));
+}
+
+#[test]
+fn test_invalid_config_is_rejected_before_spawn() {
+    let harness = Harness::new("invalid");
+    let config: SessionConfig = serde_json::from_value(serde_json::json!({
+        "id": "invalid",
+        "name": "invalid",
+        "tool": "Custom",
+        "command": "/bin/sh",
+        "args": [],
+        "workingDir": harness.dir.join("missing"),
+        "envVars": {},
+        "cols": 80,
+        "rows": 24,
+    }))
+    .unwrap();
+    let error = harness
+        .manager
+        .create_session(&config, MemorySink::default(), harness.persistence.clone())
+        .unwrap_err();
+    assert_eq!(error.code(), "VALIDATION");
+    assert!(harness.manager.list_session_ids().unwrap().is_empty());
+}
+
+#[test]
+fn test_terminal_profile_is_advertised() {
+    let harness = Harness::new("terminal");
+    let sink = harness.spawn("terminal", &["-c", "echo \"[$TERM_PROGRAM|$COLORTERM|$TERM]\"; sleep 1"]);
+    assert!(sink.wait_for_output("[AgentDesk|truecolor|xterm-256color]", TIMEOUT));
+    let terminal = harness.manager.terminal_environment("terminal").unwrap();
+    assert_eq!(terminal.vars["TERM_PROGRAM"], "AgentDesk");
+}
+
+#[test]
+fn test_failed_session_restarts_until_retries_run_out() {
+    let harness = Harness::new("restart");
+    let restart = serde_json::json!({
+        "restart": { "mode": "on-failure", "maxRetries": 2, "initialBackoffMs": 10 },
+    });
+    let sink = harness.spawn_with("restart", &["-c", "echo run-$$; exit 3"], restart);
+    assert_eq!(sink.wait_for_exit(TIMEOUT), Some(Some(3)));
+
+    // One Exited for the whole session, after the last attempt
+    let output = String::from_utf8_lossy(&sink.output()).into_owned();
+    assert_eq!(output.matches("run-").count(), 3);
+    let exits = sink.events().iter().filter(|event| matches!(event, PtyOutputEvent::Exited { .. })).count();
+    assert_eq!(exits, 1);
+
+    let attempts: Vec<_> = harness
+        .events
+        .events()
+        .into_iter()
+        .filter_map(|event| match event {
+            SessionEvent::Restarting(restart) => Some((restart.attempt, restart.exit_code)),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(attempts, vec![(1, Some(3)), (2, Some(3))]);
+
+    assert!(harness.persistence.sync(TIMEOUT));
+    let restarts = harness
+        .db
+        .with_conn(|conn| session_repo::list_restarts(conn, "restart"))
+        .unwrap();
+    assert_eq!(restarts.iter().map(|r| r.attempt).collect::<Vec<_>>(), vec![1, 2]);
+    // Every process appends to the same log stream
+    let log = harness
+        .db
+        .with_conn(|conn| history_repo::get_session_log(conn, "restart"))
+        .unwrap();
+    assert_eq!(log.matches("run-").count(), 3);
+}
+
+#[test]
+fn test_immediate_exit_with_immediate_restart_reports_exit() {
+    let harness = Harness::new("restart-race");
+    let restart = serde_json::json!({
+        "restart": { "mode": "on-failure", "maxRetries": 2, "initialBackoffMs": 0 },
+    });
+    // The exit hook may run before `create_session` returns
+    for attempt in 0..20 {
+        let id = format!("restart-race-{attempt}");
+        let sink = harness.spawn_with(&id, &["-c", "exit 3"], restart.clone());
+        assert_eq!(sink.wait_for_exit(TIMEOUT), Some(Some(3)), "attempt {attempt}");
+    }
+}
+
+#[test]
+fn test_command_output_after_a_restart() {
+    let harness = Harness::new("restart-commands");
+    let restart = serde_json::json!({
+        "restart": { "mode": "on-failure", "maxRetries": 1, "initialBackoffMs": 10 },
+    });
+    // Each run prints a command's output between OSC 133 marks
+    let script = "n=$(($(cat runs 2>/dev/null || echo 0) + 1)); echo $n > runs; \
+                  printf '\\033]133;C\\007run-%s\\n\\033]133;D;3\\007' $n; exit 3";
+    let sink = harness.spawn_with("restart-commands", &["-c", script], restart);
+    assert_eq!(sink.wait_for_exit(TIMEOUT), Some(Some(3)));
+
+    assert!(harness.persistence.sync(TIMEOUT));
+    let outputs = harness
+        .db
+        .with_conn(|conn| {
+            history_repo::list_commands(conn, "restart-commands")?
+                .iter()
+                .map(|command| history_repo::get_command_output(conn, command.id))
+                .collect::<Result<Vec<_>, _>>()
+        })
+        .unwrap();
+    assert_eq!(outputs, vec!["run-1\r\n", "run-2\r\n"]);
+}
+
+#[test]
+fn test_command_output_of_a_recreated_session() {
+    let harness = Harness::new("recreated");
+    for run in ["first", "second"] {
+        let script = format!("printf '\\033]133;C\\007{run}\\n\\033]133;D;0\\007'; exit 0");
+        let sink = harness.spawn("recreated", &["-c", &script]);
+        assert_eq!(sink.wait_for_exit(TIMEOUT), Some(Some(0)));
+        harness
+            .manager
+            .stop_session("recreated", Duration::from_millis(500))
+            .unwrap();
+    }
+
+    // The second run continues the stored stream instead of overlapping the first
+    assert!(harness.persistence.sync(TIMEOUT));
+    let outputs = harness
+        .db
+        .with_conn(|conn| {
+            history_repo::list_commands(conn, "recreated")?
+                .iter()
+                .map(|command| history_repo::get_command_output(conn, command.id))
+                .collect::<Result<Vec<_>, _>>()
+        })
+        .unwrap();
+    assert_eq!(outputs, vec!["first\r\n", "second\r\n"]);
+}
+
+#[test]
+fn test_keyed_usage_of_a_recreated_session() {
+    let harness = Harness::new("recreated-usage");
+    for _ in 0..2 {
+        let sink = harness.spawn("recreated-usage", &["-c", "echo 'Tokens: 1k sent, 1k received. Cost: $0.01 message.'"]);
+        assert_eq!(sink.wait_for_exit(TIMEOUT), Some(Some(0)));
+        harness
+            .manager
+            .stop_session("recreated-usage", Duration::from_millis(500))
+            .unwrap();
+    }
+
+    // Both runs print the reading at the same position of their own output
+    assert!(harness.persistence.sync(TIMEOUT));
+    let usage = harness
+        .db
+        .with_conn(|conn| monitoring_repo::get_session_usage(conn, "recreated-usage"))
+        .unwrap();
+    assert_eq!(usage.len(), 2);
+    assert_ne!(usage[0].message_id, usage[1].message_id);
+}
+
+#[test]
+fn test_stop_cancels_pending_restart() {
+    let harness = Harness::new("cancel");
+    let restart = serde_json::json!({
+        "restart": { "mode": "always", "initialBackoffMs": 60000 },
+    });
+    let sink = harness.spawn_with("cancel", &["-c", "exit 0"], restart);
+    assert!(harness.events.wait_until(TIMEOUT, |events| events
+        .iter()
+        .any(|event| matches!(event, SessionEvent::Restarting(_)))));
+    harness
+        .db
+        .with_conn(|conn| session_repo::update_session_status(conn, "cancel", "running"))
+        .unwrap();
+
+    harness
+        .manager
+        .stop_session("cancel", Duration::from_millis(500))
+        .unwrap();
+    assert_eq!(sink.wait_for_exit(TIMEOUT), Some(Some(0)));
+    assert!(harness.manager.list_session_ids().unwrap().is_empty());
+
+    // Not offered for restore as if it were still running
+    assert!(harness.persistence.sync(TIMEOUT));
+    let sessions = harness.db.with_conn(session_repo::list_restorable_sessions).unwrap();
+    assert!(sessions.is_empty());
+    let sessions = harness.db.with_conn(session_repo::list_all_sessions).unwrap();
+    assert_eq!(sessions[0].status, "stopped");
+}
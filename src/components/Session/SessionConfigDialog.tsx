@@ -1,34 +1,56 @@
 import { useState } from 'react';
 import { open } from '@tauri-apps/plugin-dialog';
-import { TOOL_LABELS } from '../../lib/constants';
-import type { CliTool } from '../../lib/types';
+import type { CliTool, ConfigValidation, CustomTool } from '../../lib/types';
+import ToolPicker from './ToolPicker';
 
 interface SessionConfigDialogProps {
+    customTools: CustomTool[];
     onClose: () => void;
-    onCreate: (tool: CliTool, name: string, workingDir: string) => void;
+    /** Resolves to the validation result when the launch was blocked, or null once launched. */
+    onCreate: (
+        tool: CliTool,
+        customToolId: string | null,
+        name: string,
+        workingDir: string,
+        ignoreWarnings: boolean,
+    ) => Promise<ConfigValidation | null>;
 }
 
-export default function SessionConfigDialog({ onClose, onCreate }: SessionConfigDialogProps) {
+export default function SessionConfigDialog({ customTools, onClose, onCreate }: SessionConfigDialogProps) {
     const [tool, setTool] = useState<CliTool>('ClaudeCode');
+    const [customToolId, setCustomToolId] = useState<string | null>(null);
     const [name, setName] = useState('');
     const [workingDir, setWorkingDir] = useState('');
-
-    const tools: CliTool[] = ['ClaudeCode', 'Codex', 'Aider', 'Cline', 'Custom'];
+    const [validation, setValidation] = useState<ConfigValidation | null>(null);
 
     const handleBrowse = async () => {
         try {
             const selected = await open({ directory: true, title: 'Select Working Directory' });
             if (selected) {
-                setWorkingDir(selected);
+                changeWorkingDir(selected);
             }
         } catch (err) {
             console.error('Failed to open directory picker:', err);
         }
     };
 
-    const handleSubmit = (e: React.FormEvent) => {
+    // Warnings only block the first attempt; submitting again with the same input launches anyway
+    const warningsShown = validation !== null && validation.errors.length === 0;
+
+    const handleSubmit = async (e: React.FormEvent) => {
         e.preventDefault();
-        onCreate(tool, name, workingDir);
+        setValidation(await onCreate(tool, customToolId, name, workingDir, warningsShown));
+    };
+
+    const selectTool = (t: CliTool, id: string | null) => {
+        setTool(t);
+        setCustomToolId(id);
+        setValidation(null);
+    };
+
+    const changeWorkingDir = (dir: string) => {
+        setWorkingDir(dir);
+        setValidation(null);
     };
 
     return (
@@ -37,18 +59,12 @@ export default function SessionConfigDialog({ onClose, onCreate }: SessionConfig
                 <h2>New Session</h2>
                 <div className="form-group">
                     <label>Tool</label>
-                    <div className="tool-grid">
-                        {tools.map((t) => (
-                            <button
-                                type="button"
-                                key={t}
-                                className={`tool-option ${tool === t ? 'selected' : ''}`}
-                                onClick={() => setTool(t)}
-                            >
-                                {TOOL_LABELS[t] ?? t}
-                            </button>
-                        ))}
-                    </div>
+                    <ToolPicker
+                        tool={tool}
+                        customToolId={customToolId}
+                        customTools={customTools}
+                        onSelect={selectTool}
+                    />
                 </div>
                 <div className="form-group">
                     <label>Session Name</label>
@@ -67,19 +83,33 @@ export default function SessionConfigDialog({ onClose, onCreate }: SessionConfig
                             type="text"
                             placeholder="C:\projects\my-app"
                             value={workingDir}
-                            onChange={(e) => setWorkingDir(e.target.value)}
+                            onChange={(e) => changeWorkingDir(e.target.value)}
                         />
                         <button type="button" className="btn-secondary browse-btn" onClick={handleBrowse}>
                             Browse
                         </button>
                     </div>
                 </div>
+                {validation && (
+                    <ul className="config-issues">
+                        {validation.errors.map((issue) => (
+                            <li key={`error-${issue.field}`} className="config-issue-error">
+                                {issue.message}
+                            </li>
+                        ))}
+                        {validation.warnings.map((issue) => (
+                            <li key={`warning-${issue.field}`} className="config-issue-warning">
+                                {issue.message}
+                            </li>
+                        ))}
+                    </ul>
+                )}
                 <div className="dialog-actions">
                     <button type="button" className="btn-secondary" onClick={onClose}>
                         Cancel
                     </button>
                     <button type="submit" className="btn-primary">
-                        Create
+                        {warningsShown ? 'Create Anyway' : 'Create'}
                     </button>
                 </div>
             </form>
@@ -0,0 +1,41 @@
+import { TOOL_LABELS } from '../../lib/constants';
+import type { CliTool, CustomTool } from '../../lib/types';
+
+const BUILT_IN_TOOLS: CliTool[] = ['ClaudeCode', 'Codex', 'Aider', 'Cline', 'Custom'];
+
+interface ToolPickerProps {
+    tool: string;
+    customToolId: string | null;
+    customTools: CustomTool[];
+    /** Saved custom tools are selected as tool 'Custom' with their id. */
+    onSelect: (tool: CliTool, customToolId: string | null) => void;
+}
+
+export default function ToolPicker({ tool, customToolId, customTools, onSelect }: ToolPickerProps) {
+    return (
+        <div className="tool-grid">
+            {BUILT_IN_TOOLS.map((t) => (
+                <button
+                    type="button"
+                    key={t}
+                    className={`tool-option ${tool === t && !customToolId ? 'selected' : ''}`}
+                    onClick={() => onSelect(t, null)}
+                >
+                    {TOOL_LABELS[t] ?? t}
+                </button>
+            ))}
+            {customTools.map((ct) => (
+                <button
+                    type="button"
+                    key={ct.id}
+                    className={`tool-option ${customToolId === ct.id ? 'selected' : ''}`}
+                    title={[ct.command, ...ct.args].join(' ')}
+                    onClick={() => onSelect('Custom', ct.id)}
+                >
+                    {ct.iconLabel && <span className="tool-icon-label">{ct.iconLabel}</span>}
+                    {ct.name}
+                </button>
+            ))}
+        </div>
+    );
+}
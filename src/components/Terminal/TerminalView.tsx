@@ -1,9 +1,10 @@
 import { useEffect, useRef, useCallback, useState } from 'react';
 import { useTerminal } from '../../hooks/useTerminal';
 import { usePtyChannel } from '../../hooks/usePtyChannel';
-import { createSession, writeToPty, resizePty } from '../../lib/tauri-commands';
+import { attachSession, createSession, writeToPty, resizePty, errorMessage, isAppError } from '../../lib/tauri-commands';
 import { useSessionStore } from '../../stores/sessionStore';
 import { useToastStore } from '../../stores/toastStore';
+import { useSettingsStore } from '../../stores/settingsStore';
 import type { SessionConfig } from '../../lib/types';
 import '@xterm/xterm/css/xterm.css';
 
@@ -19,6 +20,7 @@ export default function TerminalView({ sessionConfig, onOutput }: TerminalViewPr
     const updateStatus = useSessionStore((s) => s.updateStatus);
     const updateActivity = useSessionStore((s) => s.updateActivity);
     const addToast = useToastStore((s) => s.addToast);
+    const outputCoalesceMs = useSettingsStore((s) => s.outputCoalesceMs);
     const [error, setError] = useState<string | null>(null);
 
     const onData = useCallback(
@@ -64,18 +66,27 @@ export default function TerminalView({ sessionConfig, onOutput }: TerminalViewPr
 
         const channel = getChannel();
         const sid = sessionConfig.id;
-        createSession(sessionConfig, channel as never)
-            .then(() => {
-                updateStatus(sid, { type: 'Running' });
-                fit();
+        // A remounted view reattaches to its session, replaying the scrollback, and only
+        // starts a new process when the backend has none under this id
+        attachSession(sid, channel as never)
+            .then(() => fit())
+            .catch((err) => {
+                if (!isAppError(err) || err.code !== 'NOT_FOUND') throw err;
+                return createSession(
+                    { ...sessionConfig, outputCoalesceMs: sessionConfig.outputCoalesceMs ?? outputCoalesceMs },
+                    channel as never,
+                ).then(() => {
+                    updateStatus(sid, { type: 'Running' });
+                    fit();
+                });
             })
             .catch((err) => {
-                const message = String(err);
+                const message = errorMessage(err);
                 updateStatus(sid, { type: 'Error', message });
                 setError(message);
                 addToast(`Failed to create session: ${message}`, 'error');
             });
-    }, [sessionConfig, attach, getChannel, fit, updateStatus, addToast]);
+    }, [sessionConfig, outputCoalesceMs, attach, getChannel, fit, updateStatus, addToast]);
 
     useEffect(() => {
         if (!containerRef.current || initializedRef.current) return;
@@ -20,7 +20,7 @@ export function usePtyChannel({ onData, onExit, onError }: UsePtyChannelOptions)
                 switch (event.type) {
                     case 'data':
                         if (event.data) {
-                            callbacksRef.current.onData(new Uint8Array(event.data));
+                            callbacksRef.current.onData(decodeBase64(event.data));
                         }
                         break;
                     case 'exited':
@@ -44,3 +44,12 @@ export function usePtyChannel({ onData, onExit, onError }: UsePtyChannelOptions)
 
     return { getChannel };
 }
+
+function decodeBase64(encoded: string): Uint8Array {
+    const binary = atob(encoded);
+    const bytes = new Uint8Array(binary.length);
+    for (let i = 0; i < binary.length; i++) {
+        bytes[i] = binary.charCodeAt(i);
+    }
+    return bytes;
+}
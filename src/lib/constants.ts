@@ -1,6 +1,7 @@
 export const DEFAULT_COLS = 120;
 export const DEFAULT_ROWS = 30;
 
+// Mirrors the default commands of the backend tool adapters
 export const TOOL_COMMANDS: Record<string, { command: string; args: string[] }> = {
     ClaudeCode: { command: 'claude', args: [] },
     Codex: { command: 'codex', args: [] },
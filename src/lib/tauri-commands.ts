@@ -1,5 +1,17 @@
 import { invoke, Channel } from '@tauri-apps/api/core';
-import type { SessionConfig, PtyOutputEvent, PromptTemplate, CreateTemplate, UpdateTemplate, SearchQuery, SearchResult, RecordTokenUsage, TokenUsageRecord, SessionCostSummary, GlobalCostSummary, SavedSession } from './types';
+import type { AppError, ConfigValidation, DiscoveredTool, LoginEnvironment, TerminalEnvironment, SessionConfig, PtyOutputEvent, ScreenSnapshot, PromptTemplate, CreateTemplate, UpdateTemplate, CustomTool, CreateCustomTool, UpdateCustomTool, SearchQuery, SearchResult, SessionCommand, TranscriptMessage, RecordTokenUsage, TokenUsageRecord, SessionCostSummary, GlobalCostSummary, UsageRulesStatus, PersistenceMetrics, MigrationReport, SavedSession, SessionRestart } from './types';
+
+export function isAppError(err: unknown): err is AppError {
+    return typeof err === 'object' && err !== null && 'code' in err && 'message' in err;
+}
+
+export function errorMessage(err: unknown): string {
+    return isAppError(err) ? err.message : String(err);
+}
+
+export async function validateSessionConfig(config: SessionConfig): Promise<ConfigValidation> {
+    return invoke('validate_session_config', { config });
+}
 
 export async function createSession(
     config: SessionConfig,
@@ -8,6 +20,25 @@ export async function createSession(
     return invoke('create_session', { config, onEvent });
 }
 
+export async function attachSession(
+    sessionId: string,
+    onEvent: Channel<PtyOutputEvent>,
+): Promise<number> {
+    return invoke('attach_session', { sessionId, onEvent });
+}
+
+export async function subscribeSession(
+    sessionId: string,
+    onEvent: Channel<PtyOutputEvent>,
+    replay?: boolean,
+): Promise<number> {
+    return invoke('subscribe_session', { sessionId, onEvent, replay });
+}
+
+export async function unsubscribeSession(sessionId: string, subscriberId: number): Promise<void> {
+    return invoke('unsubscribe_session', { sessionId, subscriberId });
+}
+
 export async function writeToPty(sessionId: string, data: string): Promise<void> {
     return invoke('write_to_pty', { sessionId, data });
 }
@@ -16,8 +47,16 @@ export async function resizePty(sessionId: string, cols: number, rows: number):
     return invoke('resize_pty', { sessionId, cols, rows });
 }
 
-export async function stopSession(sessionId: string): Promise<void> {
-    return invoke('stop_session', { sessionId });
+export async function getScreenSnapshot(sessionId: string, includeCells?: boolean): Promise<ScreenSnapshot> {
+    return invoke('get_screen_snapshot', { sessionId, includeCells });
+}
+
+export async function getTerminalEnvironment(sessionId: string): Promise<TerminalEnvironment> {
+    return invoke('get_terminal_environment', { sessionId });
+}
+
+export async function stopSession(sessionId: string, gracePeriodMs?: number): Promise<void> {
+    return invoke('stop_session', { sessionId, gracePeriodMs });
 }
 
 export async function listSessions(): Promise<SessionConfig[]> {
@@ -45,6 +84,27 @@ export async function deleteTemplate(id: string): Promise<void> {
     return invoke('delete_template', { id });
 }
 
+// Custom tools
+export async function createCustomTool(input: CreateCustomTool): Promise<CustomTool> {
+    return invoke('create_custom_tool', { input });
+}
+
+export async function listCustomTools(): Promise<CustomTool[]> {
+    return invoke('list_custom_tools');
+}
+
+export async function getCustomTool(id: string): Promise<CustomTool> {
+    return invoke('get_custom_tool', { id });
+}
+
+export async function updateCustomTool(id: string, input: UpdateCustomTool): Promise<CustomTool> {
+    return invoke('update_custom_tool', { id, input });
+}
+
+export async function deleteCustomTool(id: string): Promise<void> {
+    return invoke('delete_custom_tool', { id });
+}
+
 // History
 export async function searchHistory(query: SearchQuery): Promise<SearchResult> {
     return invoke('search_history', { query });
@@ -58,6 +118,18 @@ export async function insertSessionLog(sessionId: string, content: string): Prom
     return invoke('insert_session_log', { sessionId, content });
 }
 
+export async function listSessionCommands(sessionId: string): Promise<SessionCommand[]> {
+    return invoke('list_session_commands', { sessionId });
+}
+
+export async function getCommandOutput(commandId: number): Promise<string> {
+    return invoke('get_command_output', { commandId });
+}
+
+export async function getSessionTranscript(sessionId: string): Promise<TranscriptMessage[]> {
+    return invoke('get_session_transcript', { sessionId });
+}
+
 // Monitoring
 export async function recordTokenUsage(input: RecordTokenUsage): Promise<void> {
     return invoke('record_token_usage', { input });
@@ -75,6 +147,18 @@ export async function getGlobalCostSummary(): Promise<GlobalCostSummary> {
     return invoke('get_global_cost_summary');
 }
 
+export async function getUsageRules(): Promise<UsageRulesStatus> {
+    return invoke('get_usage_rules');
+}
+
+export async function getPersistenceMetrics(): Promise<PersistenceMetrics> {
+    return invoke('get_persistence_metrics');
+}
+
+export async function getMigrationReport(): Promise<MigrationReport> {
+    return invoke('get_migration_report');
+}
+
 // Session Persistence
 export async function saveSessionConfig(input: SavedSession): Promise<void> {
     return invoke('save_session_config', { input });
@@ -100,6 +184,10 @@ export async function markStaleSessionsStopped(): Promise<void> {
     return invoke('mark_stale_sessions_stopped');
 }
 
+export async function listSessionRestarts(sessionId: string): Promise<SessionRestart[]> {
+    return invoke('list_session_restarts', { sessionId });
+}
+
 // Platform
 export interface PlatformDefaults {
     defaultShell: string;
@@ -111,3 +199,15 @@ export interface PlatformDefaults {
 export async function getPlatformDefaults(): Promise<PlatformDefaults> {
     return invoke('get_platform_defaults');
 }
+
+export async function getLoginEnvironment(): Promise<LoginEnvironment> {
+    return invoke('get_login_environment');
+}
+
+export async function refreshLoginEnvironment(): Promise<LoginEnvironment> {
+    return invoke('refresh_login_environment');
+}
+
+export async function discoverTools(): Promise<DiscoveredTool[]> {
+    return invoke('discover_tools');
+}
@@ -18,6 +18,95 @@ export interface SessionConfig {
     envVars: Record<string, string>;
     cols: number;
     rows: number;
+    idleThresholdMs?: number;
+    scrollbackBytes?: number;
+    outputCoalesceMs?: number;
+    outputCoalesceBytes?: number;
+    terminal?: TerminalProfile;
+    resume?: ResumeTarget;
+    // With tool 'Custom', a saved tool definition; leave command empty to use its command
+    customToolId?: string;
+    restart?: RestartPolicy;
+}
+
+// Unset fields fall back to 5 retries and a 1s backoff doubling up to 60s
+export interface RestartPolicy {
+    mode: 'never' | 'on-failure' | 'always';
+    maxRetries?: number;
+    initialBackoffMs?: number;
+    maxBackoffMs?: number;
+}
+
+// Resume uses the tool's own flags, e.g. `claude --resume <id>`
+export type ResumeTarget = { type: 'latest' } | { type: 'conversation'; id: string };
+
+// Unset fields fall back to xterm-256color, truecolor and a UTF-8 locale
+export interface TerminalProfile {
+    term?: string;
+    truecolor?: boolean;
+    locale?: string;
+    extraEnv?: Record<string, string>;
+}
+
+export interface TerminalEnvironment {
+    vars: Record<string, string>;
+    unset: string[];
+}
+
+// Pre-flight checks run before a session launches
+export interface ConfigIssue {
+    field: string;
+    message: string;
+}
+
+export interface ConfigValidation {
+    errors: ConfigIssue[];
+    warnings: ConfigIssue[];
+    resolvedCommand: string | null;
+}
+
+export interface DiscoveredTool {
+    tool: CliTool;
+    command: string;
+    path: string | null;
+    onPath: boolean;
+    version: string | null;
+    usable: boolean;
+    error: string | null;
+}
+
+export interface LoginEnvironment {
+    shell: string | null;
+    vars: Record<string, string>;
+    capturedAt: string | null;
+    error: string | null;
+}
+
+export interface SessionActivityEvent {
+    sessionId: string;
+    state: 'Working' | 'Idle';
+    since: number;
+}
+
+export interface SessionTitleEvent {
+    sessionId: string;
+    title: string | null;
+    cwd: string | null;
+}
+
+export interface SessionRestartEvent {
+    sessionId: string;
+    attempt: number;
+    exitCode: number | null;
+    delayMs: number;
+}
+
+export interface SessionRestart {
+    id: number;
+    sessionId: string;
+    attempt: number;
+    exitCode: number | null;
+    restartedAt: string;
 }
 
 export interface Session {
@@ -37,11 +126,42 @@ export interface TokenUsage {
 export interface PtyOutputEvent {
     type: 'data' | 'exited' | 'error';
     sessionId: string;
-    data?: number[];
+    /** Base64-encoded output bytes. */
+    data?: string;
     exitCode?: number;
     message?: string;
 }
 
+// Headless terminal screen
+export type CellColor =
+    | { type: 'indexed'; index: number }
+    | { type: 'rgb'; r: number; g: number; b: number };
+
+export interface ScreenCell {
+    text: string;
+    fg?: CellColor;
+    bg?: CellColor;
+    bold: boolean;
+    italic: boolean;
+    underline: boolean;
+    inverse: boolean;
+    wide: boolean;
+}
+
+export interface ScreenSnapshot {
+    sessionId: string;
+    rows: number;
+    cols: number;
+    lines: string[];
+    cursorRow: number;
+    cursorCol: number;
+    cursorVisible: boolean;
+    alternateScreen: boolean;
+    title: string | null;
+    cwd: string | null;
+    cells?: ScreenCell[][];
+}
+
 // Saved Sessions (persistence)
 export interface SavedSession {
     id: string;
@@ -54,6 +174,10 @@ export interface SavedSession {
     cols: number;
     rows: number;
     status: string;
+    title?: string | null;
+    cwd?: string | null;
+    toolVersion?: string | null;
+    customToolId?: string | null;
     createdAt: string;
     updatedAt: string;
 }
@@ -66,6 +190,7 @@ export interface PromptTemplate {
     prompt: string;
     description: string;
     tags: string[];
+    customToolId: string | null;
     createdAt: string;
     updatedAt: string;
 }
@@ -76,6 +201,7 @@ export interface CreateTemplate {
     prompt: string;
     description: string;
     tags: string[];
+    customToolId?: string | null;
 }
 
 export interface UpdateTemplate {
@@ -84,6 +210,44 @@ export interface UpdateTemplate {
     prompt?: string;
     description?: string;
     tags?: string[];
+    // An empty string clears it
+    customToolId?: string;
+}
+
+// Custom tools
+export interface CustomTool {
+    id: string;
+    name: string;
+    command: string;
+    args: string[];
+    envVars: Record<string, string>;
+    iconLabel: string;
+    idleRegex: string | null;
+    // Named groups: input, output, cost, model
+    usageRegex: string | null;
+    createdAt: string;
+    updatedAt: string;
+}
+
+export interface CreateCustomTool {
+    name: string;
+    command: string;
+    args?: string[];
+    envVars?: Record<string, string>;
+    iconLabel?: string;
+    idleRegex?: string | null;
+    usageRegex?: string | null;
+}
+
+// An empty regex clears it
+export interface UpdateCustomTool {
+    name?: string;
+    command?: string;
+    args?: string[];
+    envVars?: Record<string, string>;
+    iconLabel?: string;
+    idleRegex?: string;
+    usageRegex?: string;
 }
 
 // History
@@ -106,14 +270,43 @@ export interface SearchResult {
     total: number;
 }
 
+export interface SessionCommand {
+    id: number;
+    sessionId: string;
+    command: string;
+    exitCode: number | null;
+    outputStart: number;
+    outputEnd: number;
+    startedAt: string;
+    endedAt: string;
+    durationMs: number;
+}
+
+/** An entry of a conversation imported from the session tool's transcript files. */
+export interface TranscriptMessage {
+    id: number;
+    sessionId: string;
+    role: 'user' | 'assistant' | 'tool';
+    kind: 'message' | 'reasoning' | 'tool_call' | 'tool_output';
+    /** The tool called, for tool calls. */
+    name: string | null;
+    content: string;
+    timestamp: string | null;
+    recordedAt: string;
+}
+
 // Monitoring
 export interface TokenUsageRecord {
     id: number;
     sessionId: string;
     inputTokens: number;
     outputTokens: number;
+    cacheReadTokens: number;
+    cacheCreationTokens: number;
     model: string;
     costUsd: number;
+    /** Set for usage imported from the tool's transcript. */
+    messageId: string | null;
     recordedAt: string;
 }
 
@@ -121,14 +314,20 @@ export interface RecordTokenUsage {
     sessionId: string;
     inputTokens: number;
     outputTokens: number;
+    cacheReadTokens?: number;
+    cacheCreationTokens?: number;
     model: string;
     costUsd: number;
+    messageId?: string;
+    cumulative?: boolean;
 }
 
 export interface SessionCostSummary {
     sessionId: string;
     totalInputTokens: number;
     totalOutputTokens: number;
+    totalCacheReadTokens: number;
+    totalCacheCreationTokens: number;
     totalCostUsd: number;
     recordCount: number;
 }
@@ -136,7 +335,65 @@ export interface SessionCostSummary {
 export interface GlobalCostSummary {
     totalInputTokens: number;
     totalOutputTokens: number;
+    totalCacheReadTokens: number;
+    totalCacheCreationTokens: number;
     totalCostUsd: number;
     sessionCount: number;
     perSession: SessionCostSummary[];
 }
+
+export interface UsageRuleSummary {
+    name: string;
+    tools: string[];
+    cumulative: boolean;
+    user: boolean;
+}
+
+export interface UsageRulesStatus {
+    userFile: string | null;
+    rules: UsageRuleSummary[];
+    errors: string[];
+}
+
+export interface PersistenceMetrics {
+    queueCapacity: number;
+    queueDepth: number;
+    enqueuedJobs: number;
+    writtenJobs: number;
+    batches: number;
+    failedJobs: number;
+    failedBatches: number;
+    queueFullCount: number;
+    blockedSends: number;
+    lastBatchMs: number;
+}
+
+export interface MigrationReport {
+    applied: string[];
+    checksumMismatches: string[];
+    drift: SchemaDrift;
+    repaired: string[];
+}
+
+export interface SchemaDrift {
+    missingObjects: string[];
+    missingColumns: string[];
+}
+
+// Errors returned by backend commands
+export type AppErrorCode =
+    | 'NOT_FOUND'
+    | 'DATABASE'
+    | 'MIGRATION'
+    | 'PTY'
+    | 'SPAWN_FAILED'
+    | 'IO'
+    | 'VALIDATION'
+    | 'CONFLICT'
+    | 'INTERNAL';
+
+export interface AppError {
+    code: AppErrorCode;
+    message: string;
+    context: Record<string, unknown> | null;
+}
@@ -5,6 +5,7 @@ import SessionCard from '../components/Session/SessionCard';
 import SessionConfigDialog from '../components/Session/SessionConfigDialog';
 import QuickLaunchBar from '../components/Session/QuickLaunchBar';
 import RestoreDialog from '../components/Session/RestoreDialog';
+import { useCustomToolStore } from '../stores/customToolStore';
 import {
     stopSession,
     getPlatformDefaults,
@@ -12,10 +13,12 @@ import {
     listRestorableSessions,
     updateSavedSessionStatus,
     markStaleSessionsStopped,
+    validateSessionConfig,
+    discoverTools,
     type PlatformDefaults,
 } from '../lib/tauri-commands';
 import { DEFAULT_COLS, DEFAULT_ROWS, TOOL_COMMANDS } from '../lib/constants';
-import type { CliTool, SessionConfig, SavedSession } from '../lib/types';
+import type { CliTool, DiscoveredTool, SessionConfig, SavedSession } from '../lib/types';
 
 export default function DashboardPage() {
     const sessions = useSessionStore((s) => s.sessions);
@@ -24,37 +27,66 @@ export default function DashboardPage() {
     const [showDialog, setShowDialog] = useState(false);
     const [platform, setPlatform] = useState<PlatformDefaults | null>(null);
     const [restorableSessions, setRestorableSessions] = useState<SavedSession[]>([]);
+    const [discovered, setDiscovered] = useState<DiscoveredTool[]>([]);
+    const customTools = useCustomToolStore((s) => s.tools);
+    const fetchCustomTools = useCustomToolStore((s) => s.fetchTools);
     const navigate = useNavigate();
 
     // On mount: load platform defaults and check for restorable sessions
     useEffect(() => {
         getPlatformDefaults().then(setPlatform);
+        discoverTools().then(setDiscovered);
+        fetchCustomTools();
         listRestorableSessions().then((sessions) => {
             if (sessions.length > 0) {
                 setRestorableSessions(sessions);
             }
         });
-    }, []);
+    }, [fetchCustomTools]);
 
-    const handleCreate = useCallback(
-        (tool: CliTool, name: string, workingDir: string) => {
-            const id = crypto.randomUUID();
+    const buildConfig = useCallback(
+        (tool: CliTool, name: string, workingDir: string, customToolId: string | null = null): SessionConfig => {
             const toolConfig = TOOL_COMMANDS[tool] ?? TOOL_COMMANDS.Custom;
             const defaultShell = platform?.defaultShell ?? 'cmd.exe';
             const defaultHome = platform?.homeDir ?? '';
-            const config: SessionConfig = {
-                id,
+            // Tools installed outside the session PATH are launched by full path
+            const found = discovered.find((d) => d.tool === tool && d.usable);
+            const toolCommand = found?.path && !found.onPath ? found.path : toolConfig.command;
+            const customTool = customTools.find((t) => t.id === customToolId);
+            if (customTool) {
+                // An empty command makes the backend use the saved definition's
+                return {
+                    id: crypto.randomUUID(),
+                    name: name || `${customTool.name} Session`,
+                    tool: 'Custom',
+                    command: '',
+                    args: [],
+                    workingDir: workingDir || defaultHome,
+                    envVars: {},
+                    cols: DEFAULT_COLS,
+                    rows: DEFAULT_ROWS,
+                    customToolId: customTool.id,
+                };
+            }
+            return {
+                id: crypto.randomUUID(),
                 name: name || `${tool} Session`,
                 tool,
-                command: tool === 'Custom' ? defaultShell : toolConfig.command,
+                command: tool === 'Custom' ? defaultShell : toolCommand,
                 args: tool === 'Custom' ? (platform?.defaultShellArgs ?? []) : toolConfig.args,
                 workingDir: workingDir || defaultHome,
                 envVars: {},
                 cols: DEFAULT_COLS,
                 rows: DEFAULT_ROWS,
             };
+        },
+        [platform, discovered, customTools],
+    );
+
+    const launch = useCallback(
+        (config: SessionConfig) => {
             addSession(config);
-            setActiveSession(id);
+            setActiveSession(config.id);
             setShowDialog(false);
 
             // Persist to DB
@@ -69,20 +101,35 @@ export default function DashboardPage() {
                 cols: config.cols,
                 rows: config.rows,
                 status: 'running',
+                customToolId: config.customToolId ?? null,
                 createdAt: new Date().toISOString(),
                 updatedAt: new Date().toISOString(),
             });
 
-            navigate(`/session/${id}`);
+            navigate(`/session/${config.id}`);
+        },
+        [addSession, setActiveSession, navigate],
+    );
+
+    const handleCreate = useCallback(
+        async (tool: CliTool, customToolId: string | null, name: string, workingDir: string, ignoreWarnings: boolean) => {
+            const config = buildConfig(tool, name, workingDir, customToolId);
+            const validation = await validateSessionConfig(config);
+            if (validation.errors.length > 0 || (validation.warnings.length > 0 && !ignoreWarnings)) {
+                return validation;
+            }
+            launch(config);
+            return null;
         },
-        [addSession, setActiveSession, navigate, platform],
+        [buildConfig, launch],
     );
 
+    // Quick launch skips the dialog; create_session still rejects invalid configs
     const handleQuickLaunch = useCallback(
         (tool: CliTool) => {
-            handleCreate(tool, '', '');
+            launch(buildConfig(tool, '', ''));
         },
-        [handleCreate],
+        [buildConfig, launch],
     );
 
     const handleCardClick = useCallback(
@@ -112,6 +159,7 @@ export default function DashboardPage() {
                     envVars: saved.envVars,
                     cols: saved.cols,
                     rows: saved.rows,
+                    customToolId: saved.customToolId ?? undefined,
                 };
                 addSession(config);
                 updateSavedSessionStatus(saved.id, 'running');
@@ -170,6 +218,7 @@ export default function DashboardPage() {
             )}
             {showDialog && (
                 <SessionConfigDialog
+                    customTools={customTools}
                     onClose={() => setShowDialog(false)}
                     onCreate={handleCreate}
                 />
@@ -37,6 +37,10 @@ export default function MonitoringPage() {
                             <span className="stat-label">Output Tokens</span>
                             <span className="stat-value">{formatTokens(summary.totalOutputTokens)}</span>
                         </div>
+                        <div className="stat-card">
+                            <span className="stat-label">Cache Reads</span>
+                            <span className="stat-value">{formatTokens(summary.totalCacheReadTokens)}</span>
+                        </div>
                         <div className="stat-card">
                             <span className="stat-label">Sessions Tracked</span>
                             <span className="stat-value">{summary.sessionCount}</span>
@@ -52,6 +56,7 @@ export default function MonitoringPage() {
                                         <th>Session</th>
                                         <th>Input</th>
                                         <th>Output</th>
+                                        <th>Cache Reads</th>
                                         <th>Cost</th>
                                         <th>Records</th>
                                     </tr>
@@ -62,6 +67,7 @@ export default function MonitoringPage() {
                                             <td className="session-id-cell">{s.sessionId.slice(0, 8)}</td>
                                             <td>{formatTokens(s.totalInputTokens)}</td>
                                             <td>{formatTokens(s.totalOutputTokens)}</td>
+                                            <td>{formatTokens(s.totalCacheReadTokens)}</td>
                                             <td className="cost-cell">{formatCost(s.totalCostUsd)}</td>
                                             <td>{s.recordCount}</td>
                                         </tr>
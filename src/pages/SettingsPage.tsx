@@ -1,11 +1,40 @@
+import { useEffect, useState } from 'react';
 import { useUiStore } from '../stores/uiStore';
 import { useSettingsStore } from '../stores/settingsStore';
+import { useCustomToolStore } from '../stores/customToolStore';
+import { errorMessage, getLoginEnvironment, refreshLoginEnvironment } from '../lib/tauri-commands';
+import type { CustomTool, LoginEnvironment } from '../lib/types';
 
 export default function SettingsPage() {
     const theme = useUiStore((s) => s.theme);
     const setTheme = useUiStore((s) => s.setTheme);
-    const { fontSize, fontFamily, defaultShell, setFontSize, setFontFamily, setDefaultShell } =
-        useSettingsStore();
+    const {
+        fontSize,
+        fontFamily,
+        defaultShell,
+        outputCoalesceMs,
+        setFontSize,
+        setFontFamily,
+        setDefaultShell,
+        setOutputCoalesceMs,
+    } = useSettingsStore();
+    const [loginEnv, setLoginEnv] = useState<LoginEnvironment | null>(null);
+    const [refreshing, setRefreshing] = useState(false);
+
+    useEffect(() => {
+        getLoginEnvironment().then(setLoginEnv);
+    }, []);
+
+    const handleRefreshEnv = async () => {
+        setRefreshing(true);
+        try {
+            setLoginEnv(await refreshLoginEnvironment());
+        } catch (err) {
+            setLoginEnv((env) => (env ? { ...env, error: errorMessage(err) } : env));
+        } finally {
+            setRefreshing(false);
+        }
+    };
 
     return (
         <div className="settings-page">
@@ -61,8 +90,48 @@ export default function SettingsPage() {
                         placeholder="cmd.exe"
                     />
                 </div>
+                <div className="form-group">
+                    <label>Output Batching (ms)</label>
+                    <input
+                        type="number"
+                        min={0}
+                        max={100}
+                        value={outputCoalesceMs}
+                        onChange={(e) => setOutputCoalesceMs(Math.max(0, parseInt(e.target.value) || 0))}
+                    />
+                </div>
+                <div className="form-group">
+                    <label>Login Shell Environment</label>
+                    <div className="input-with-btn">
+                        <input
+                            type="text"
+                            readOnly
+                            value={
+                                loginEnv?.shell
+                                    ? `${Object.keys(loginEnv.vars).length} variables from ${loginEnv.shell}` +
+                                      (loginEnv.capturedAt ? ` (captured ${loginEnv.capturedAt} UTC)` : '')
+                                    : 'Not used on this platform'
+                            }
+                        />
+                        <button
+                            type="button"
+                            className="btn-secondary browse-btn"
+                            onClick={handleRefreshEnv}
+                            disabled={refreshing}
+                        >
+                            {refreshing ? 'Refreshing…' : 'Refresh'}
+                        </button>
+                    </div>
+                    {loginEnv?.error && (
+                        <ul className="config-issues">
+                            <li className="config-issue-error">{loginEnv.error}</li>
+                        </ul>
+                    )}
+                </div>
             </div>
 
+            <CustomToolsSection />
+
             <div className="settings-section">
                 <h2>Keyboard Shortcuts</h2>
                 <div className="shortcuts-list">
@@ -110,3 +179,178 @@ export default function SettingsPage() {
         </div>
     );
 }
+
+function CustomToolsSection() {
+    const { tools, fetchTools, createTool, updateTool, deleteTool } = useCustomToolStore();
+    const [editing, setEditing] = useState<CustomTool | 'new' | null>(null);
+    const [error, setError] = useState<string | null>(null);
+
+    useEffect(() => {
+        fetchTools();
+    }, [fetchTools]);
+
+    const handleDelete = async (id: string) => {
+        try {
+            setError(null);
+            await deleteTool(id);
+        } catch (err) {
+            setError(errorMessage(err));
+        }
+    };
+
+    return (
+        <div className="settings-section">
+            <h2>Custom Tools</h2>
+            {tools.map((t) => (
+                <div key={t.id} className="form-group">
+                    <label>
+                        {t.iconLabel && <span className="tool-icon-label">{t.iconLabel}</span>}
+                        {t.name}
+                    </label>
+                    <div className="input-with-btn">
+                        <input type="text" readOnly value={[t.command, ...t.args].join(' ')} />
+                        <button type="button" className="btn-secondary browse-btn" onClick={() => setEditing(t)}>
+                            Edit
+                        </button>
+                        <button type="button" className="btn-secondary browse-btn" onClick={() => handleDelete(t.id)}>
+                            Delete
+                        </button>
+                    </div>
+                </div>
+            ))}
+            {error && (
+                <ul className="config-issues">
+                    <li className="config-issue-error">{error}</li>
+                </ul>
+            )}
+            <button type="button" className="btn-secondary" onClick={() => setEditing('new')}>
+                + Add Custom Tool
+            </button>
+            {editing && (
+                <CustomToolEditor
+                    tool={editing === 'new' ? null : editing}
+                    onSave={async (input) => {
+                        if (editing === 'new') {
+                            await createTool(input);
+                        } else {
+                            // Empty regexes clear them
+                            await updateTool(editing.id, {
+                                ...input,
+                                idleRegex: input.idleRegex ?? '',
+                                usageRegex: input.usageRegex ?? '',
+                            });
+                        }
+                        setEditing(null);
+                    }}
+                    onClose={() => setEditing(null)}
+                />
+            )}
+        </div>
+    );
+}
+
+interface CustomToolInput {
+    name: string;
+    command: string;
+    args: string[];
+    envVars: Record<string, string>;
+    iconLabel: string;
+    idleRegex: string | null;
+    usageRegex: string | null;
+}
+
+function CustomToolEditor({
+    tool,
+    onSave,
+    onClose,
+}: {
+    tool: CustomTool | null;
+    onSave: (input: CustomToolInput) => Promise<void>;
+    onClose: () => void;
+}) {
+    const [name, setName] = useState(tool?.name ?? '');
+    const [iconLabel, setIconLabel] = useState(tool?.iconLabel ?? '');
+    const [command, setCommand] = useState(tool?.command ?? '');
+    const [argsStr, setArgsStr] = useState(tool?.args.join(' ') ?? '');
+    const [envStr, setEnvStr] = useState(
+        Object.entries(tool?.envVars ?? {})
+            .map(([key, value]) => `${key}=${value}`)
+            .join('\n'),
+    );
+    const [idleRegex, setIdleRegex] = useState(tool?.idleRegex ?? '');
+    const [usageRegex, setUsageRegex] = useState(tool?.usageRegex ?? '');
+    const [error, setError] = useState<string | null>(null);
+
+    const handleSubmit = async (e: React.FormEvent) => {
+        e.preventDefault();
+        const envVars: Record<string, string> = {};
+        for (const line of envStr.split('\n')) {
+            const eq = line.indexOf('=');
+            if (eq > 0) {
+                envVars[line.slice(0, eq).trim()] = line.slice(eq + 1);
+            }
+        }
+        try {
+            await onSave({
+                name,
+                command,
+                args: argsStr.split(/\s+/).filter(Boolean),
+                envVars,
+                iconLabel,
+                idleRegex: idleRegex || null,
+                usageRegex: usageRegex || null,
+            });
+        } catch (err) {
+            setError(errorMessage(err));
+        }
+    };
+
+    return (
+        <div className="dialog-overlay" onClick={onClose}>
+            <form className="dialog" onClick={(e) => e.stopPropagation()} onSubmit={handleSubmit}>
+                <h2>{tool ? 'Edit Custom Tool' : 'New Custom Tool'}</h2>
+                <div className="form-group">
+                    <label>Name</label>
+                    <input value={name} onChange={(e) => setName(e.target.value)} placeholder="Internal GPT" autoFocus />
+                </div>
+                <div className="form-group">
+                    <label>Icon Label</label>
+                    <input value={iconLabel} onChange={(e) => setIconLabel(e.target.value)} placeholder="GPT" maxLength={4} />
+                </div>
+                <div className="form-group">
+                    <label>Command</label>
+                    <input value={command} onChange={(e) => setCommand(e.target.value)} placeholder="gpt-cli" />
+                </div>
+                <div className="form-group">
+                    <label>Default Arguments (space-separated)</label>
+                    <input value={argsStr} onChange={(e) => setArgsStr(e.target.value)} placeholder="--chat" />
+                </div>
+                <div className="form-group">
+                    <label>Environment (KEY=VALUE per line)</label>
+                    <textarea value={envStr} onChange={(e) => setEnvStr(e.target.value)} rows={3} />
+                </div>
+                <div className="form-group">
+                    <label>Idle Prompt Regex</label>
+                    <input value={idleRegex} onChange={(e) => setIdleRegex(e.target.value)} placeholder="^> $" />
+                </div>
+                <div className="form-group">
+                    <label>Usage Regex (groups: input, output, cost, model)</label>
+                    <input
+                        value={usageRegex}
+                        onChange={(e) => setUsageRegex(e.target.value)}
+                        placeholder="(?P<input>\d+) in, (?P<output>\d+) out"
+                    />
+                </div>
+                {error && (
+                    <ul className="config-issues">
+                        <li className="config-issue-error">{error}</li>
+                    </ul>
+                )}
+                <div className="dialog-actions">
+                    <button type="button" className="btn-secondary" onClick={onClose}>Cancel</button>
+                    <button type="submit" className="btn-primary">{tool ? 'Update' : 'Create'}</button>
+                </div>
+            </form>
+        </div>
+    );
+}
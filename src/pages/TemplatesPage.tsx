@@ -1,5 +1,7 @@
 import { useEffect, useState } from 'react';
 import { useTemplateStore } from '../stores/templateStore';
+import { useCustomToolStore } from '../stores/customToolStore';
+import ToolPicker from '../components/Session/ToolPicker';
 import { TOOL_LABELS } from '../lib/constants';
 import type { CliTool, CreateTemplate, PromptTemplate } from '../lib/types';
 
@@ -7,10 +9,15 @@ export default function TemplatesPage() {
     const { templates, loading, fetchTemplates, createTemplate, deleteTemplate } = useTemplateStore();
     const [showEditor, setShowEditor] = useState(false);
     const [editingTemplate, setEditingTemplate] = useState<PromptTemplate | null>(null);
+    const { tools: customTools, fetchTools: fetchCustomTools } = useCustomToolStore();
 
     useEffect(() => {
         fetchTemplates();
-    }, [fetchTemplates]);
+        fetchCustomTools();
+    }, [fetchTemplates, fetchCustomTools]);
+
+    const toolLabel = (t: PromptTemplate) =>
+        customTools.find((ct) => ct.id === t.customToolId)?.name ?? TOOL_LABELS[t.tool] ?? t.tool;
 
     const handleCreate = async (input: CreateTemplate) => {
         await createTemplate(input);
@@ -53,7 +60,7 @@ export default function TemplatesPage() {
                         <div key={t.id} className="template-card">
                             <div className="template-card-header">
                                 <span className="template-name">{t.name}</span>
-                                <span className="session-tool">{TOOL_LABELS[t.tool] ?? t.tool}</span>
+                                <span className="session-tool">{toolLabel(t)}</span>
                             </div>
                             {t.description && (
                                 <p className="template-desc">{t.description}</p>
@@ -97,24 +104,29 @@ function TemplateEditor({
 }) {
     const [name, setName] = useState(template?.name ?? '');
     const [tool, setTool] = useState<string>(template?.tool ?? 'ClaudeCode');
+    const [customToolId, setCustomToolId] = useState<string | null>(template?.customToolId ?? null);
     const [prompt, setPrompt] = useState(template?.prompt ?? '');
     const [description, setDescription] = useState(template?.description ?? '');
     const [tagsStr, setTagsStr] = useState(template?.tags.join(', ') ?? '');
 
     const updateTemplate = useTemplateStore((s) => s.updateTemplate);
+    const customTools = useCustomToolStore((s) => s.tools);
 
     const handleSubmit = async (e: React.FormEvent) => {
         e.preventDefault();
         const tags = tagsStr.split(',').map((s) => s.trim()).filter(Boolean);
         if (template) {
-            await updateTemplate(template.id, { name, tool, prompt, description, tags });
+            await updateTemplate(template.id, { name, tool, prompt, description, tags, customToolId: customToolId ?? '' });
             onClose();
         } else {
-            onSave({ name, tool, prompt, description, tags });
+            onSave({ name, tool, prompt, description, tags, customToolId });
         }
     };
 
-    const tools: CliTool[] = ['ClaudeCode', 'Codex', 'Aider', 'Cline', 'Custom'];
+    const selectTool = (t: CliTool, id: string | null) => {
+        setTool(t);
+        setCustomToolId(id);
+    };
 
     return (
         <div className="dialog-overlay" onClick={onClose}>
@@ -126,18 +138,7 @@ function TemplateEditor({
                 </div>
                 <div className="form-group">
                     <label>Tool</label>
-                    <div className="tool-grid">
-                        {tools.map((t) => (
-                            <button
-                                type="button"
-                                key={t}
-                                className={`tool-option ${tool === t ? 'selected' : ''}`}
-                                onClick={() => setTool(t)}
-                            >
-                                {TOOL_LABELS[t] ?? t}
-                            </button>
-                        ))}
-                    </div>
+                    <ToolPicker tool={tool} customToolId={customToolId} customTools={customTools} onSelect={selectTool} />
                 </div>
                 <div className="form-group">
                     <label>Description</label>
@@ -0,0 +1,44 @@
+import { create } from 'zustand';
+import type { CustomTool, CreateCustomTool, UpdateCustomTool } from '../lib/types';
+import * as cmd from '../lib/tauri-commands';
+
+interface CustomToolState {
+    tools: CustomTool[];
+    loading: boolean;
+
+    fetchTools: () => Promise<void>;
+    createTool: (input: CreateCustomTool) => Promise<CustomTool>;
+    updateTool: (id: string, input: UpdateCustomTool) => Promise<void>;
+    deleteTool: (id: string) => Promise<void>;
+}
+
+export const useCustomToolStore = create<CustomToolState>((set, get) => ({
+    tools: [],
+    loading: false,
+
+    fetchTools: async () => {
+        set({ loading: true });
+        const tools = await cmd.listCustomTools();
+        set({ tools, loading: false });
+    },
+
+    createTool: async (input) => {
+        const tool = await cmd.createCustomTool(input);
+        set({ tools: [...get().tools, tool].sort((a, b) => a.name.localeCompare(b.name)) });
+        return tool;
+    },
+
+    updateTool: async (id, input) => {
+        const updated = await cmd.updateCustomTool(id, input);
+        set({
+            tools: get().tools.map((t) => (t.id === id ? updated : t)),
+        });
+    },
+
+    deleteTool: async (id) => {
+        await cmd.deleteCustomTool(id);
+        set({
+            tools: get().tools.filter((t) => t.id !== id),
+        });
+    },
+}));
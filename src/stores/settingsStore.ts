@@ -4,17 +4,21 @@ interface SettingsState {
     defaultShell: string;
     fontSize: number;
     fontFamily: string;
+    outputCoalesceMs: number;
     setDefaultShell: (shell: string) => void;
     setFontSize: (size: number) => void;
     setFontFamily: (family: string) => void;
+    setOutputCoalesceMs: (ms: number) => void;
 }
 
 export const useSettingsStore = create<SettingsState>((set) => ({
     defaultShell: 'cmd.exe',
     fontSize: 14,
     fontFamily: "'Cascadia Code', 'Fira Code', 'Consolas', monospace",
+    outputCoalesceMs: 8,
 
     setDefaultShell: (shell) => set({ defaultShell: shell }),
     setFontSize: (size) => set({ fontSize: size }),
     setFontFamily: (family) => set({ fontFamily: family }),
+    setOutputCoalesceMs: (ms) => set({ outputCoalesceMs: ms }),
 }));